@@ -0,0 +1,922 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+//! Obtaining the certificate of the server from an ACME server like Let's Encrypt
+//!
+//! Domains are validated with DNS-01 challenges, whose TXT records are published through the
+//! provider of a configured domain in the same zone.
+
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use log::{info, warn};
+use rcgen::{CertificateParams, DistinguishedName, DnType, KeyPair};
+use reqwest::header::CONTENT_TYPE;
+use ring::{
+    digest,
+    rand::SystemRandom,
+    signature::{self, EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+use x509_parser::extensions::GeneralName;
+
+use crate::{
+    config::{store::ConfigStore, Config, DomainConfig, Providers},
+    providers::{add_txt, remove_txt},
+    tls::TlsConfig,
+};
+
+/// Interval in which the certificate is checked for renewal
+const CHECK_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+/// Time to wait before trying again after a failed renewal
+const RETRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Interval in which authorizations and orders are checked while the ACME server processes them
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const POLL_ATTEMPTS: u32 = 60;
+
+fn default_directory() -> String {
+    "https://acme-v02.api.letsencrypt.org/directory".to_string()
+}
+
+fn default_renew_days() -> i64 {
+    30
+}
+
+fn default_propagation_delay() -> u64 {
+    60
+}
+
+#[derive(Deserialize, Clone)]
+pub struct AcmeConfig {
+    /// Directory of the ACME server, Let's Encrypt by default
+    #[serde(default = "default_directory")]
+    pub directory: String,
+    /// Contact addresses of the account, e.g. mailto:admin@example.com
+    #[serde(default)]
+    pub contact: Vec<String>,
+    /// Names the certificate is issued for, each has to be in the zone of a configured domain
+    pub domains: Vec<String>,
+    /// File the key of the ACME account is kept in, which is created if it is missing
+    pub account_key: PathBuf,
+    /// Root certificate of the ACME server in PEM format, e.g. for testing with Pebble
+    pub ca_certificate: Option<PathBuf>,
+    /// Number of days before expiry the certificate is renewed
+    #[serde(default = "default_renew_days")]
+    pub renew_days: i64,
+    /// Seconds to wait for the nameservers to serve the challenge records
+    #[serde(default = "default_propagation_delay")]
+    pub propagation_delay: u64,
+}
+
+fn base64url(data: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(data)
+}
+
+/// Value of the TXT record that answers a DNS-01 challenge
+fn dns_value(token: &str, thumbprint: &str) -> String {
+    base64url(digest::digest(&digest::SHA256, format!("{token}.{thumbprint}").as_bytes()).as_ref())
+}
+
+/// Write a file that only its owner may read
+fn write_private(path: &Path, content: &str) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options.open(path)?.write_all(content.as_bytes())
+}
+
+/// Key the requests of an ACME account are signed with
+struct AccountKey {
+    key: EcdsaKeyPair,
+    rng: SystemRandom,
+}
+
+impl AccountKey {
+    fn from_pem(pem: &str) -> Result<AccountKey, Box<dyn Error + Send + Sync>> {
+        let rng = SystemRandom::new();
+        let key = EcdsaKeyPair::from_pkcs8(
+            &ECDSA_P256_SHA256_FIXED_SIGNING,
+            &KeyPair::from_pem(pem)?.serialize_der(),
+            &rng,
+        )
+        .map_err(|e| format!("Invalid account key: {e}"))?;
+
+        Ok(AccountKey { key, rng })
+    }
+
+    /// Read the key from `path`, creating a new key if there is none
+    fn load(path: &Path) -> Result<AccountKey, Box<dyn Error + Send + Sync>> {
+        if path.exists() {
+            return AccountKey::from_pem(&fs::read_to_string(path)?);
+        }
+
+        let pem = KeyPair::generate()?.serialize_pem();
+        write_private(path, &pem)?;
+        info!("Created ACME account key {}", path.display());
+
+        AccountKey::from_pem(&pem)
+    }
+
+    /// Public key as JSON Web Key, with its members in the order required for the thumbprint
+    fn jwk(&self) -> Value {
+        // Uncompressed point: 0x04, x and y
+        let point = signature::KeyPair::public_key(&self.key).as_ref();
+
+        json!({
+            "crv": "P-256",
+            "kty": "EC",
+            "x": base64url(&point[1..33]),
+            "y": base64url(&point[33..]),
+        })
+    }
+
+    /// Thumbprint of the public key according to RFC 7638
+    fn thumbprint(&self) -> String {
+        base64url(digest::digest(&digest::SHA256, self.jwk().to_string().as_bytes()).as_ref())
+    }
+
+    /// JSON Web Signature of an already encoded payload
+    fn sign(
+        &self,
+        protected: &Value,
+        payload: &str,
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        let protected = base64url(protected.to_string().as_bytes());
+        let signature = self
+            .key
+            .sign(&self.rng, format!("{protected}.{payload}").as_bytes())
+            .map_err(|e| format!("Error signing ACME request: {e}"))?;
+
+        Ok(json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": base64url(signature.as_ref()),
+        }))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+#[derive(Deserialize)]
+struct Order {
+    authorizations: Vec<String>,
+    finalize: String,
+    certificate: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Identifier {
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct Challenge {
+    #[serde(rename = "type")]
+    challenge_type: String,
+    url: String,
+    #[serde(default)]
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct Authorization {
+    status: String,
+    identifier: Identifier,
+    challenges: Vec<Challenge>,
+}
+
+/// Description of the error of a failed order or authorization
+fn problem(resource: &Value) -> String {
+    let detail = |v: &Value| v["detail"].as_str().map(ToString::to_string);
+
+    detail(&resource["error"])
+        .or_else(|| {
+            resource["challenges"]
+                .as_array()?
+                .iter()
+                .find_map(|c| detail(&c["error"]))
+        })
+        .unwrap_or_else(|| format!("status {}", resource["status"]))
+}
+
+fn location(response: &reqwest::Response) -> Result<String, Box<dyn Error + Send + Sync>> {
+    Ok(response
+        .headers()
+        .get("Location")
+        .ok_or("ACME server sent no location")?
+        .to_str()?
+        .to_string())
+}
+
+/// Connection to an ACME server with a registered account
+struct Client {
+    http: reqwest::Client,
+    directory: Directory,
+    key: AccountKey,
+    /// URL of the account, which identifies it after registration
+    account: Option<String>,
+    nonce: Option<String>,
+}
+
+impl Client {
+    async fn connect(config: &AcmeConfig) -> Result<Client, Box<dyn Error + Send + Sync>> {
+        let mut builder = reqwest::Client::builder();
+        if let Some(path) = &config.ca_certificate {
+            builder =
+                builder.add_root_certificate(reqwest::Certificate::from_pem(&fs::read(path)?)?);
+        }
+        let http = builder.build()?;
+
+        let directory = http
+            .get(&config.directory)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut client = Client {
+            http,
+            directory,
+            key: AccountKey::load(&config.account_key)?,
+            account: None,
+            nonce: None,
+        };
+
+        // Returns the existing account if the key is already registered
+        let url = client.directory.new_account.clone();
+        let payload = json!({ "termsOfServiceAgreed": true, "contact": config.contact });
+        let response = client.post(&url, Some(&payload)).await?;
+        client.account = Some(location(&response)?);
+
+        Ok(client)
+    }
+
+    async fn nonce(&mut self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        if let Some(nonce) = self.nonce.take() {
+            return Ok(nonce);
+        }
+
+        let response = self
+            .http
+            .head(&self.directory.new_nonce)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(response
+            .headers()
+            .get("Replay-Nonce")
+            .ok_or("ACME server sent no nonce")?
+            .to_str()?
+            .to_string())
+    }
+
+    /// Send a signed request, which fetches the resource at `url` if there is no payload
+    async fn post(
+        &mut self,
+        url: &str,
+        payload: Option<&Value>,
+    ) -> Result<reqwest::Response, Box<dyn Error + Send + Sync>> {
+        let payload = payload.map_or_else(String::new, |p| base64url(p.to_string().as_bytes()));
+        let mut retried = false;
+
+        loop {
+            let mut protected = json!({ "alg": "ES256", "nonce": self.nonce().await?, "url": url });
+            match &self.account {
+                Some(account) => protected["kid"] = json!(account),
+                None => protected["jwk"] = self.key.jwk(),
+            }
+            let body = self.key.sign(&protected, &payload)?.to_string();
+
+            let response = self
+                .http
+                .post(url)
+                .header(CONTENT_TYPE, "application/jose+json")
+                .body(body)
+                .send()
+                .await?;
+
+            self.nonce = response
+                .headers()
+                .get("Replay-Nonce")
+                .and_then(|n| n.to_str().ok())
+                .map(ToString::to_string);
+
+            if response.status().is_success() {
+                return Ok(response);
+            }
+
+            let error: Value = response.json().await.unwrap_or_default();
+
+            // Nonces may be rejected at any time, e.g. when the server restarted
+            if !retried && error["type"] == "urn:ietf:params:acme:error:badNonce" {
+                retried = true;
+                continue;
+            }
+
+            return Err(format!(
+                "ACME request to {url} failed: {}",
+                problem(&json!({ "error": error }))
+            )
+            .into());
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &mut self,
+        url: &str,
+    ) -> Result<T, Box<dyn Error + Send + Sync>> {
+        Ok(self.post(url, None).await?.json().await?)
+    }
+
+    /// Fetch a resource until the ACME server is done processing it
+    async fn poll(&mut self, url: &str) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        for _ in 0..POLL_ATTEMPTS {
+            let resource: Value = self.get(url).await?;
+
+            match resource["status"].as_str() {
+                Some("pending" | "processing") => tokio::time::sleep(POLL_INTERVAL).await,
+                _ => return Ok(resource),
+            }
+        }
+
+        Err(format!("Timeout waiting for ACME server to process {url}").into())
+    }
+}
+
+/// Add the values of the challenges to their TXT records, or remove them if `clear` is set
+///
+/// Other values of the records, e.g. of challenges answered through the httpreq API, are kept.
+fn set_records(
+    records: &BTreeMap<String, Vec<String>>,
+    config: &Config,
+    providers: &Providers,
+    clear: bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    for (name, values) in records {
+        let zone_domain = config
+            .zone_domain(name)
+            .ok_or_else(|| format!("No configured domain in the zone of {name}"))?;
+        let domain = DomainConfig {
+            host: name.clone(),
+            ..zone_domain.clone()
+        };
+
+        let provider = providers.get(&domain).map_err(|e| e.to_string())?;
+        for value in values {
+            if clear {
+                remove_txt(provider, &domain, value)
+            } else {
+                add_txt(provider, &domain, value, None)
+            }
+            .map_err(|e| e.to_string())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Answer the challenges of all authorizations of an order
+async fn authorize(
+    client: &mut Client,
+    order: &Order,
+    acme: &AcmeConfig,
+    config: &Config,
+    providers: &Providers,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let thumbprint = client.key.thumbprint();
+
+    // A domain and its wildcard are validated with the same record
+    let mut records: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut pending = Vec::new();
+
+    for url in &order.authorizations {
+        let authorization: Authorization = client.get(url).await?;
+        if authorization.status == "valid" {
+            continue;
+        }
+
+        let challenge = authorization
+            .challenges
+            .into_iter()
+            .find(|c| c.challenge_type == "dns-01")
+            .ok_or_else(|| {
+                format!(
+                    "ACME server offers no DNS-01 challenge for {}",
+                    authorization.identifier.value
+                )
+            })?;
+
+        records
+            .entry(format!(
+                "_acme-challenge.{}",
+                authorization.identifier.value
+            ))
+            .or_default()
+            .push(dns_value(&challenge.token, &thumbprint));
+        pending.push((url.clone(), challenge.url));
+    }
+
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    set_records(&records, config, providers, false)?;
+
+    let result = async {
+        tokio::time::sleep(Duration::from_secs(acme.propagation_delay)).await;
+
+        for (_, challenge) in &pending {
+            client.post(challenge, Some(&json!({}))).await?;
+        }
+
+        for (authorization, _) in &pending {
+            let authorization = client.poll(authorization).await?;
+            if authorization["status"] != "valid" {
+                return Err(format!("Validation failed: {}", problem(&authorization)).into());
+            }
+        }
+
+        Ok(())
+    }
+    .await;
+
+    if let Err(error) = set_records(&records, config, providers, true) {
+        warn!("Error deleting ACME challenge records: {error}");
+    }
+
+    result
+}
+
+/// Obtain a certificate for the configured domains and write it and its key to the files of `tls`
+pub async fn obtain(
+    acme: &AcmeConfig,
+    tls: &TlsConfig,
+    config: &Config,
+    providers: &Providers,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    info!(
+        "Requesting certificate for {} from {}",
+        acme.domains.join(", "),
+        acme.directory
+    );
+
+    let mut client = Client::connect(acme).await?;
+
+    let identifiers: Vec<Value> = acme
+        .domains
+        .iter()
+        .map(|d| json!({ "type": "dns", "value": d }))
+        .collect();
+    let url = client.directory.new_order.clone();
+    let response = client
+        .post(&url, Some(&json!({ "identifiers": identifiers })))
+        .await?;
+    let order_url = location(&response)?;
+    let order: Order = response.json().await?;
+
+    authorize(&mut client, &order, acme, config, providers).await?;
+
+    let key = KeyPair::generate()?;
+    let mut params = CertificateParams::new(acme.domains.clone())?;
+    params.distinguished_name = DistinguishedName::new();
+    params
+        .distinguished_name
+        .push(DnType::CommonName, acme.domains[0].as_str());
+    let csr = params.serialize_request(&key)?;
+
+    client
+        .post(
+            &order.finalize,
+            Some(&json!({ "csr": base64url(csr.der()) })),
+        )
+        .await?;
+
+    let order = client.poll(&order_url).await?;
+    if order["status"] != "valid" {
+        return Err(format!("Order failed: {}", problem(&order)).into());
+    }
+    let order: Order = serde_json::from_value(order)?;
+    let certificate = client
+        .post(
+            order
+                .certificate
+                .as_deref()
+                .ok_or("Order has no certificate")?,
+            None,
+        )
+        .await?
+        .text()
+        .await?;
+
+    write_private(&tls.key, &key.serialize_pem())?;
+    fs::write(&tls.certs, certificate)?;
+
+    info!("Obtained certificate for {}", acme.domains.join(", "));
+
+    Ok(())
+}
+
+/// Expiry and names of the certificate in the file at `path`
+fn certificate_info(path: &Path) -> Option<(OffsetDateTime, Vec<String>)> {
+    let content = fs::read(path).ok()?;
+    let (_, pem) = x509_parser::pem::parse_x509_pem(&content).ok()?;
+    let certificate = pem.parse_x509().ok()?;
+
+    let expires =
+        OffsetDateTime::from_unix_timestamp(certificate.validity().not_after.timestamp()).ok()?;
+    let names = certificate
+        .subject_alternative_name()
+        .ok()
+        .flatten()
+        .map(|san| {
+            san.value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(name) => Some((*name).to_string()),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Some((expires, names))
+}
+
+/// Whether the certificate is missing, about to expire or does not cover all configured domains
+#[must_use]
+pub fn needs_renewal(acme: &AcmeConfig, tls: &TlsConfig, now: OffsetDateTime) -> bool {
+    certificate_info(&tls.certs).is_none_or(|(expires, names)| {
+        expires - time::Duration::days(acme.renew_days) <= now
+            || acme.domains.iter().any(|d| !names.contains(d))
+    })
+}
+
+/// Renew the certificate whenever it is about to expire
+///
+/// The server picks up the new certificate by itself, see [`crate::tls::serve`].
+pub async fn renew(acme: AcmeConfig, tls: TlsConfig, store: ConfigStore, providers: Providers) {
+    loop {
+        let wait = if needs_renewal(&acme, &tls, OffsetDateTime::now_utc()) {
+            match obtain(&acme, &tls, &store.get(), &providers).await {
+                Ok(()) => CHECK_INTERVAL,
+                Err(error) => {
+                    warn!("Error renewing certificate: {error}");
+                    RETRY_INTERVAL
+                }
+            }
+        } else {
+            CHECK_INTERVAL
+        };
+
+        tokio::time::sleep(wait).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_FIXED};
+    use rocket::{
+        http::{Method, Status},
+        route::{Handler, Outcome},
+        Data, Request, Response, Route,
+    };
+    use time::macros::datetime;
+
+    use crate::providers::tests::{txt_provider, txt_records, TxtRecords};
+
+    use super::*;
+
+    fn acme_config(domains: &[&str]) -> AcmeConfig {
+        serde_json::from_value(json!({
+            "domains": domains,
+            "account_key": "account.pem",
+        }))
+        .unwrap()
+    }
+
+    /// Configuration with a domain in the zone example.com
+    fn zone_config() -> Config {
+        serde_json::from_value(json!({
+            "users": [{
+                "name": "test",
+                "password": "testpassword",
+                "domains": [{
+                    "provider": "MockProvider",
+                    "apitoken": "testapitoken",
+                    "host": "home.example.com",
+                    "zone": { "name": "example.com", "id": "idexamplecom" }
+                }]
+            }]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn challenge_value() {
+        assert_eq!(
+            dns_value("token", "thumbprint"),
+            "61rBZ_4knHblO0MNoxFsXZ_eTFUHum0B6IVRbhvUn5I"
+        );
+    }
+
+    #[test]
+    fn signed_request() {
+        let key = AccountKey::from_pem(&KeyPair::generate().unwrap().serialize_pem()).unwrap();
+
+        let jwk = key.jwk();
+        assert_eq!(
+            jwk.to_string(),
+            format!(
+                r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+                jwk["x"].as_str().unwrap(),
+                jwk["y"].as_str().unwrap()
+            )
+        );
+        assert_eq!(key.thumbprint().len(), 43);
+
+        let jws = key.sign(&json!({ "alg": "ES256" }), "e30").unwrap();
+        let protected = jws["protected"].as_str().unwrap();
+        let signature = URL_SAFE_NO_PAD
+            .decode(jws["signature"].as_str().unwrap())
+            .unwrap();
+
+        let public_key = signature::KeyPair::public_key(&key.key);
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, public_key.as_ref())
+            .verify(format!("{protected}.e30").as_bytes(), &signature)
+            .unwrap();
+    }
+
+    #[test]
+    fn renew_expiring_certificates() {
+        let path = std::env::temp_dir().join(format!("dyndns-acme-{}.pem", std::process::id()));
+        let tls = TlsConfig {
+            certs: path.clone(),
+            key: PathBuf::from("key.pem"),
+            redirect_port: None,
+            acme: None,
+        };
+
+        let acme = acme_config(&["dyndns.example.com"]);
+        assert!(needs_renewal(&acme, &tls, datetime!(2026-01-01 00:00 UTC)));
+
+        let mut params = CertificateParams::new(vec!["dyndns.example.com".to_string()]).unwrap();
+        params.not_after = datetime!(2026-03-01 00:00 UTC);
+        let certificate = params.self_signed(&KeyPair::generate().unwrap()).unwrap();
+        fs::write(&path, certificate.pem()).unwrap();
+
+        assert!(!needs_renewal(&acme, &tls, datetime!(2026-01-01 00:00 UTC)));
+        assert!(needs_renewal(&acme, &tls, datetime!(2026-02-01 00:00 UTC)));
+
+        // Domains that are added to the configuration are added to the certificate right away
+        let acme = acme_config(&["dyndns.example.com", "example.com"]);
+        assert!(needs_renewal(&acme, &tls, datetime!(2026-01-01 00:00 UTC)));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    const CHALLENGE_RECORD: &str = "_acme-challenge.dyndns.example.com";
+
+    /// ACME server that accepts every request, with a single order for dyndns.example.com
+    #[derive(Clone)]
+    struct FakeAcmeServer {
+        base: String,
+        certificate: String,
+        validated: Arc<AtomicBool>,
+        /// Records of the DNS provider, which are looked up when the challenge is validated
+        records: TxtRecords,
+        /// Values of the challenge record at the time of the validation
+        presented: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[rocket::async_trait]
+    impl Handler for FakeAcmeServer {
+        async fn handle<'r>(&self, request: &'r Request<'_>, _data: Data<'r>) -> Outcome<'r> {
+            let url = |path: &str| format!("{}{path}", self.base);
+            let order = |status: &str| {
+                json!({
+                    "status": status,
+                    "authorizations": [url("/authz/1")],
+                    "finalize": url("/finalize/1"),
+                    "certificate": url("/cert/1"),
+                })
+            };
+
+            let (status, location, body) = match request.uri().path().as_str() {
+                "/dir" => (
+                    Status::Ok,
+                    None,
+                    json!({
+                        "newNonce": url("/nonce"),
+                        "newAccount": url("/account"),
+                        "newOrder": url("/order"),
+                    })
+                    .to_string(),
+                ),
+                "/nonce" => (Status::Ok, None, String::new()),
+                "/account" => (Status::Created, Some(url("/account/1")), "{}".to_string()),
+                "/order" => (
+                    Status::Created,
+                    Some(url("/order/1")),
+                    order("pending").to_string(),
+                ),
+                "/authz/1" => {
+                    let status = if self.validated.load(Ordering::SeqCst) {
+                        "valid"
+                    } else {
+                        "pending"
+                    };
+                    let authorization = json!({
+                        "status": status,
+                        "identifier": { "type": "dns", "value": "dyndns.example.com" },
+                        "challenges": [
+                            { "type": "http-01", "url": url("/challenge/0"), "token": "other" },
+                            { "type": "dns-01", "url": url("/challenge/1"), "token": "token" },
+                        ],
+                    });
+                    (Status::Ok, None, authorization.to_string())
+                }
+                "/challenge/1" => {
+                    *self.presented.lock().unwrap() =
+                        self.records.lock().unwrap()[CHALLENGE_RECORD].clone();
+                    self.validated.store(true, Ordering::SeqCst);
+                    (Status::Ok, None, "{}".to_string())
+                }
+                "/finalize/1" => (Status::Ok, None, order("processing").to_string()),
+                "/order/1" => (Status::Ok, None, order("valid").to_string()),
+                "/cert/1" => (Status::Ok, None, self.certificate.clone()),
+                _ => (Status::NotFound, None, "{}".to_string()),
+            };
+
+            let mut response = Response::build();
+            response
+                .status(status)
+                .raw_header("Replay-Nonce", "nonce")
+                .sized_body(body.len(), Cursor::new(body));
+            if let Some(location) = location {
+                response.raw_header("Location", location);
+            }
+
+            Outcome::Success(response.finalize())
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn obtain_certificate() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let certificate = CertificateParams::new(vec!["dyndns.example.com".to_string()])
+            .unwrap()
+            .self_signed(&KeyPair::generate().unwrap())
+            .unwrap()
+            .pem();
+
+        // The record already holds the value of a challenge of an httpreq client
+        let records = txt_records(CHALLENGE_RECORD, &["other"]);
+        let server = FakeAcmeServer {
+            base: format!("http://127.0.0.1:{port}"),
+            certificate: certificate.clone(),
+            validated: Arc::new(AtomicBool::new(false)),
+            records: records.clone(),
+            presented: Arc::default(),
+        };
+        let routes: Vec<Route> = [Method::Get, Method::Post]
+            .into_iter()
+            .map(|method| Route::new(method, "/<path..>", server.clone()))
+            .collect();
+        let figment = rocket::Config::figment()
+            .merge(("port", port))
+            .merge(("log_level", "off"));
+        let rocket = rocket::custom(figment)
+            .mount("/", routes)
+            .ignite()
+            .await
+            .unwrap();
+        let shutdown = rocket.shutdown();
+        tokio::spawn(rocket.launch());
+
+        let directory = std::env::temp_dir().join(format!("dyndns-acme-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let account_key = directory.join("account.pem");
+
+        let mut acme = acme_config(&["dyndns.example.com"]);
+        acme.directory = format!("{}/dir", server.base);
+        acme.account_key = account_key.clone();
+        acme.propagation_delay = 0;
+        let tls = TlsConfig {
+            certs: directory.join("fullchain.pem"),
+            key: directory.join("privkey.pem"),
+            redirect_port: None,
+            acme: None,
+        };
+
+        let config = zone_config();
+
+        let providers = Providers {
+            hetzner_provider: None,
+            rfc2136_provider: None,
+            mock_provider: Some(txt_provider(&records)),
+        };
+
+        // The server starts listening in the background
+        for _ in 0..50 {
+            if reqwest::get(&acme.directory).await.is_ok() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        obtain(&acme, &tls, &config, &providers).await.unwrap();
+
+        assert_eq!(fs::read_to_string(&tls.certs).unwrap(), certificate);
+        assert!(KeyPair::from_pem(&fs::read_to_string(&tls.key).unwrap()).is_ok());
+        assert!(!needs_renewal(&acme, &tls, OffsetDateTime::now_utc()));
+
+        // Only the value of this challenge is added and removed again
+        let thumbprint = AccountKey::load(&account_key).unwrap().thumbprint();
+        assert_eq!(
+            *server.presented.lock().unwrap(),
+            ["other".to_string(), dns_value("token", &thumbprint)]
+        );
+        assert_eq!(records.lock().unwrap()[CHALLENGE_RECORD], ["other"]);
+
+        shutdown.notify();
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    /// Obtain a certificate from a local Pebble server
+    ///
+    /// The challenge records only exist in the mock provider, so Pebble has to be started with
+    /// `PEBBLE_VA_ALWAYS_VALID=1`, and with `PEBBLE_WFE_NONCEREJECT=0` as bad nonces are not retried.
+    /// Run with `PEBBLE_CA=/path/to/pebble.minica.pem cargo test -- --ignored pebble`;
+    /// `PEBBLE_DIRECTORY` defaults to the directory of Pebble on localhost.
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "needs a running Pebble server"]
+    async fn obtain_certificate_from_pebble() {
+        let directory = std::env::temp_dir().join(format!("dyndns-pebble-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let mut acme = acme_config(&["dyndns.example.com"]);
+        acme.directory = std::env::var("PEBBLE_DIRECTORY")
+            .unwrap_or_else(|_| "https://localhost:14000/dir".to_string());
+        acme.ca_certificate = std::env::var_os("PEBBLE_CA").map(PathBuf::from);
+        acme.account_key = directory.join("account.pem");
+        acme.propagation_delay = 0;
+        let tls = TlsConfig {
+            certs: directory.join("fullchain.pem"),
+            key: directory.join("privkey.pem"),
+            redirect_port: None,
+            acme: None,
+        };
+
+        let records = TxtRecords::default();
+        let providers = Providers {
+            hetzner_provider: None,
+            rfc2136_provider: None,
+            mock_provider: Some(txt_provider(&records)),
+        };
+
+        obtain(&acme, &tls, &zone_config(), &providers)
+            .await
+            .unwrap();
+
+        assert!(!needs_renewal(&acme, &tls, OffsetDateTime::now_utc()));
+        assert!(records.lock().unwrap().is_empty());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,596 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+//! API compatible with acme-dns for answering DNS-01 challenges of other machines
+//!
+//! Every account owns a subdomain of the configured domain, whose TXT record it may set.
+//! The `_acme-challenge` records of the names in the certificates point there with a CNAME record.
+
+use std::net::IpAddr;
+
+use ipnet::IpNet;
+use log::{info, warn};
+use rocket::{
+    get,
+    http::Status,
+    post,
+    request::{FromRequest, Outcome},
+    serde::json::Json,
+    Request, Route, State,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    admin::edit_error,
+    api::{error, ApiError},
+    config::{
+        store::{generate_token, list_mut, ConfigStore, EditError},
+        Config, DomainConfig, Providers,
+    },
+    logging,
+    providers::{add_txt, remove_txt},
+    security::{self, SecurityEvent},
+};
+
+#[derive(Deserialize, Clone)]
+pub struct AcmeDnsConfig {
+    /// Domain the subdomains of the accounts are created in
+    pub domain: String,
+    /// Allow anyone to register an account, like acme-dns does
+    #[serde(default)]
+    pub register: bool,
+    #[serde(default)]
+    pub accounts: Vec<AcmeDnsAccount>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct AcmeDnsAccount {
+    pub username: String,
+    pub password: String,
+    /// Single label below the domain of the API, which is the only record the account may set
+    pub subdomain: String,
+    /// Networks updates are accepted from, any if empty
+    #[serde(default)]
+    pub allowfrom: Vec<IpNet>,
+}
+
+impl AcmeDnsConfig {
+    /// Check that the records of all accounts can be managed through a configured domain
+    pub fn validate(&self, config: &Config) -> Result<(), String> {
+        if config.zone_domain(&self.domain).is_none() {
+            return Err(format!(
+                "Domain {} of the acme-dns API is not in the zone of any configured domain",
+                self.domain
+            ));
+        }
+
+        for (i, account) in self.accounts.iter().enumerate() {
+            let valid = !account.subdomain.is_empty()
+                && account
+                    .subdomain
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-');
+            if !valid {
+                return Err(format!(
+                    "Invalid subdomain {} of acme-dns account {}",
+                    account.subdomain, account.username
+                ));
+            }
+
+            if self.accounts[..i]
+                .iter()
+                .any(|a| a.username == account.username || a.subdomain == account.subdomain)
+            {
+                return Err(format!(
+                    "acme-dns account {} is not unique",
+                    account.username
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn fulldomain(&self, account: &AcmeDnsAccount) -> String {
+        format!("{}.{}", account.subdomain, self.domain)
+    }
+}
+
+/// Credentials in the X-Api-User and X-Api-Key headers
+pub struct ApiKey {
+    user: String,
+    key: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiKey {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = request.headers();
+
+        match (headers.get_one("X-Api-User"), headers.get_one("X-Api-Key")) {
+            (Some(user), Some(key)) => Outcome::Success(ApiKey {
+                user: user.to_string(),
+                key: key.to_string(),
+            }),
+            _ => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+fn forbidden() -> ApiError {
+    error(Status::Unauthorized, "forbidden")
+}
+
+/// Find the account of `api_key`, which has to own `subdomain` and be used from an allowed network
+fn authenticate<'a>(
+    config: &'a AcmeDnsConfig,
+    api_key: &ApiKey,
+    client: Option<IpAddr>,
+    subdomain: &str,
+) -> Result<&'a AcmeDnsAccount, ApiError> {
+    let Some(account) = config.accounts.iter().find(|a| a.username == api_key.user) else {
+        warn!("Invalid acme-dns account {}", api_key.user);
+        security::log_event(SecurityEvent::InvalidUser, client, &api_key.user, subdomain);
+        return Err(forbidden());
+    };
+
+    if account.password != api_key.key {
+        warn!("Wrong password for acme-dns account {}", account.username);
+        security::log_event(
+            SecurityEvent::WrongPassword,
+            client,
+            &api_key.user,
+            subdomain,
+        );
+        return Err(forbidden());
+    }
+
+    if account.subdomain != subdomain {
+        warn!(
+            "acme-dns account {} may not update subdomain {subdomain}",
+            account.username
+        );
+        security::log_event(SecurityEvent::InvalidHost, client, &api_key.user, subdomain);
+        return Err(forbidden());
+    }
+
+    if !account.allowfrom.is_empty()
+        && !client.is_some_and(|ip| account.allowfrom.iter().any(|net| net.contains(&ip)))
+    {
+        warn!(
+            "acme-dns account {} may not be used from {}",
+            account.username,
+            client.map_or_else(|| "-".to_string(), |ip| ip.to_string())
+        );
+        return Err(forbidden());
+    }
+
+    Ok(account)
+}
+
+/// Random identifier in the format of a UUID, as used by acme-dns for usernames and subdomains
+fn random_uuid() -> String {
+    let hex = generate_token();
+
+    format!(
+        "{}-{}-4{}-a{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[13..16],
+        &hex[17..20],
+        &hex[20..32]
+    )
+}
+
+#[derive(Deserialize, Default)]
+struct RegisterRequest {
+    #[serde(default)]
+    allowfrom: Vec<IpNet>,
+}
+
+#[derive(Serialize)]
+pub struct RegisterResponse {
+    pub username: String,
+    pub password: String,
+    pub fulldomain: String,
+    pub subdomain: String,
+    pub allowfrom: Vec<IpNet>,
+}
+
+#[post("/register", data = "<body>")]
+fn register(
+    body: &str,
+    store: &State<ConfigStore>,
+) -> Result<(Status, Json<RegisterResponse>), ApiError> {
+    let _context = logging::enter_request();
+
+    let config = store.get();
+    let Some(acme_dns) = &config.acme_dns else {
+        return Err(error(Status::NotFound, "acme-dns API is not enabled"));
+    };
+    if !acme_dns.register {
+        return Err(error(Status::Forbidden, "Registration is disabled"));
+    }
+
+    let request: RegisterRequest = if body.trim().is_empty() {
+        RegisterRequest::default()
+    } else {
+        serde_json::from_str(body)
+            .map_err(|_| error(Status::BadRequest, "invalid_allowfrom_cidr"))?
+    };
+
+    let account = AcmeDnsAccount {
+        username: random_uuid(),
+        password: generate_token(),
+        subdomain: random_uuid(),
+        allowfrom: request.allowfrom,
+    };
+    let response = RegisterResponse {
+        username: account.username.clone(),
+        password: account.password.clone(),
+        fulldomain: acme_dns.fulldomain(&account),
+        subdomain: account.subdomain.clone(),
+        allowfrom: account.allowfrom.clone(),
+    };
+
+    store
+        .edit(|value| {
+            let acme_dns = value
+                .get_mut("acme_dns")
+                .ok_or_else(|| EditError::NotFound("acme-dns API is not enabled".to_string()))?;
+            list_mut(acme_dns, "accounts")?.push(json!(account));
+            Ok(())
+        })
+        .map_err(|e| {
+            warn!("Error registering acme-dns account: {e}");
+            edit_error(e)
+        })?;
+
+    logging::set_field("user", &response.username);
+    info!("Registered acme-dns account for {}", response.fulldomain);
+
+    Ok((Status::Created, Json(response)))
+}
+
+#[derive(Deserialize)]
+pub struct UpdateRequest {
+    pub subdomain: String,
+    pub txt: String,
+}
+
+#[derive(Serialize)]
+pub struct UpdateResponse {
+    pub txt: String,
+}
+
+/// Whether `txt` looks like the value of a DNS-01 challenge, a base64url encoded SHA-256 digest
+fn valid_txt(txt: &str) -> bool {
+    txt.len() == 43
+        && txt
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Add `request.txt` to the TXT record of the subdomain of the account, or remove it if `present` is false
+fn change(
+    api_key: &ApiKey,
+    request: UpdateRequest,
+    present: bool,
+    client_ip: Option<IpAddr>,
+    store: &ConfigStore,
+    providers: &Providers,
+) -> Result<Json<UpdateResponse>, ApiError> {
+    let _context = logging::enter_request();
+    logging::set_field("user", &api_key.user);
+
+    let config = store.get();
+    let Some(acme_dns) = &config.acme_dns else {
+        return Err(error(Status::NotFound, "acme-dns API is not enabled"));
+    };
+
+    let account = authenticate(acme_dns, api_key, client_ip, &request.subdomain)?;
+    if !valid_txt(&request.txt) {
+        return Err(error(Status::BadRequest, "bad_txt"));
+    }
+
+    let name = acme_dns.fulldomain(account);
+    logging::set_field("host", &name);
+
+    let zone_domain = config.zone_domain(&name).ok_or_else(|| {
+        error(
+            Status::InternalServerError,
+            "No configured domain in the zone of the record",
+        )
+    })?;
+    let domain = DomainConfig {
+        host: name.clone(),
+        ..zone_domain.clone()
+    };
+
+    // Like acme-dns, the two latest values are kept, so a name and its wildcard can be validated together
+    if let Err(e) = providers.get(&domain).and_then(|provider| {
+        if present {
+            add_txt(provider, &domain, &request.txt, Some(2))
+        } else {
+            remove_txt(provider, &domain, &request.txt)
+        }
+    }) {
+        warn!("Error updating TXT record {name}: {e}");
+        return Err(error(
+            Status::InternalServerError,
+            &format!("Error updating TXT record: {e}"),
+        ));
+    }
+
+    if present {
+        info!("Updated TXT record {name}");
+    } else {
+        info!("Removed value from TXT record {name}");
+    }
+
+    Ok(Json(UpdateResponse { txt: request.txt }))
+}
+
+#[post("/update", data = "<request>")]
+fn update(
+    api_key: ApiKey,
+    request: Json<UpdateRequest>,
+    client_ip: Option<IpAddr>,
+    store: &State<ConfigStore>,
+    providers: &State<Providers>,
+) -> Result<Json<UpdateResponse>, ApiError> {
+    change(
+        &api_key,
+        request.into_inner(),
+        true,
+        client_ip,
+        store,
+        providers,
+    )
+}
+
+/// Remove a value once the challenge is done, which acme-dns itself does not offer
+#[post("/delete", data = "<request>")]
+fn delete(
+    api_key: ApiKey,
+    request: Json<UpdateRequest>,
+    client_ip: Option<IpAddr>,
+    store: &State<ConfigStore>,
+    providers: &State<Providers>,
+) -> Result<Json<UpdateResponse>, ApiError> {
+    change(
+        &api_key,
+        request.into_inner(),
+        false,
+        client_ip,
+        store,
+        providers,
+    )
+}
+
+#[get("/health")]
+fn health() -> Status {
+    Status::Ok
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![register, update, delete, health]
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{
+        http::Header,
+        local::blocking::{Client, LocalResponse},
+    };
+
+    use crate::{
+        history::{History, HistoryConfig},
+        keyed::KeyedEntries,
+        metrics::Metrics,
+        notifications::Notifier,
+        providers::{
+            tests::{txt_provider, txt_records, TxtRecords},
+            MockProvider,
+        },
+        server,
+        ui::Sessions,
+    };
+
+    use super::*;
+
+    const TXT: &str = "61rBZ_4knHblO0MNoxFsXZ_eTFUHum0B6IVRbhvUn5I";
+    const OTHER_TXT: &str = "4ZZOTsJNqFqcNqk8tpa_6QJXgmaYWCoUcSW-ti87ocY";
+    const THIRD_TXT: &str = "Zx0fPqeW2hkfvO8rJIqdI6m2V-TkRBnf1y3aHLCg3sQ";
+
+    fn construct(mock: MockProvider) -> Client {
+        let store = ConfigStore::in_memory(json!({
+            "users": [{
+                "name": "test",
+                "password": "testpassword",
+                "domains": [{
+                    "provider": "MockProvider",
+                    "apitoken": "testapitoken",
+                    "host": "example.com",
+                    "zone": { "name": "example.com", "id": "idexamplecom" }
+                }]
+            }],
+            "acme_dns": {
+                "domain": "acme.example.com",
+                "register": true,
+                "accounts": [{
+                    "username": "camera",
+                    "password": "camerapassword",
+                    "subdomain": "camera",
+                    "allowfrom": ["192.0.2.0/24"]
+                }]
+            }
+        }))
+        .unwrap();
+
+        Client::tracked(
+            server::rocket(rocket::Config::figment())
+                .manage(store)
+                .manage(Providers {
+                    hetzner_provider: None,
+                    rfc2136_provider: None,
+                    mock_provider: Some(mock),
+                })
+                .manage(Notifier::new(&Default::default()).unwrap())
+                .manage(Metrics::default())
+                .manage(History::new(&HistoryConfig::default()).unwrap())
+                .manage(KeyedEntries::default())
+                .manage(Sessions::default()),
+        )
+        .unwrap()
+    }
+
+    fn send_update<'c>(
+        client: &'c Client,
+        credentials: (&str, &str),
+        client_ip: &str,
+        subdomain: &str,
+        txt: &str,
+    ) -> LocalResponse<'c> {
+        send(client, "/update", credentials, client_ip, subdomain, txt)
+    }
+
+    fn send<'c>(
+        client: &'c Client,
+        path: &str,
+        (user, key): (&str, &str),
+        client_ip: &str,
+        subdomain: &str,
+        txt: &str,
+    ) -> LocalResponse<'c> {
+        client
+            .post(format!("/acme-dns{path}"))
+            .header(Header::new("X-Api-User", user.to_string()))
+            .header(Header::new("X-Api-Key", key.to_string()))
+            .header(Header::new("X-Real-IP", client_ip.to_string()))
+            .body(json!({ "subdomain": subdomain, "txt": txt }).to_string())
+            .dispatch()
+    }
+
+    #[test]
+    fn update_txt_record() {
+        let name = "camera.acme.example.com";
+        let records = TxtRecords::default();
+        let client = construct(txt_provider(&records));
+        let camera = ("camera", "camerapassword");
+        let ip = "192.0.2.1";
+
+        let response = send_update(&client, camera, ip, "camera", TXT);
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.into_string().unwrap(),
+            json!({ "txt": TXT }).to_string()
+        );
+
+        // Both values are kept, e.g. for a name and its wildcard
+        let response = send_update(&client, camera, ip, "camera", OTHER_TXT);
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(records.lock().unwrap()[name], [TXT, OTHER_TXT]);
+
+        let response = send_update(&client, camera, ip, "camera", THIRD_TXT);
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(records.lock().unwrap()[name], [OTHER_TXT, THIRD_TXT]);
+
+        let response = send_update(&client, ("camera", "wrongpassword"), ip, "camera", TXT);
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = send_update(&client, ("other", "camerapassword"), ip, "camera", TXT);
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        // Accounts are restricted to their own subdomain and networks
+        let response = send_update(&client, camera, ip, "other", TXT);
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = send_update(&client, camera, "198.51.100.1", "camera", TXT);
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = send_update(&client, camera, ip, "camera", "not a challenge");
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn delete_txt_value() {
+        let name = "camera.acme.example.com";
+        let records = txt_records(name, &[TXT, OTHER_TXT]);
+        let client = construct(txt_provider(&records));
+        let camera = ("camera", "camerapassword");
+        let ip = "192.0.2.1";
+
+        let response = send(&client, "/delete", camera, ip, "camera", TXT);
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(records.lock().unwrap()[name], [OTHER_TXT]);
+
+        // Accounts can only delete values of their own subdomain
+        let response = send(&client, "/delete", camera, ip, "other", OTHER_TXT);
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = send(
+            &client,
+            "/delete",
+            ("camera", "wrongpassword"),
+            ip,
+            "camera",
+            OTHER_TXT,
+        );
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(records.lock().unwrap()[name], [OTHER_TXT]);
+
+        // The record is deleted with its last value
+        let response = send(&client, "/delete", camera, ip, "camera", OTHER_TXT);
+        assert_eq!(response.status(), Status::Ok);
+        assert!(records.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn register_account() {
+        let records = TxtRecords::default();
+        let client = construct(txt_provider(&records));
+
+        let response = client
+            .post("/acme-dns/register")
+            .body(r#"{"allowfrom": ["10.0.0.0/8"]}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+        let account: serde_json::Value = response.into_json().unwrap();
+        let subdomain = account["subdomain"].as_str().unwrap();
+        assert_eq!(
+            account["fulldomain"],
+            format!("{subdomain}.acme.example.com")
+        );
+        assert_eq!(account["allowfrom"], json!(["10.0.0.0/8"]));
+
+        let credentials = (
+            account["username"].as_str().unwrap(),
+            account["password"].as_str().unwrap(),
+        );
+        let response = send_update(&client, credentials, "10.1.2.3", subdomain, TXT);
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            records.lock().unwrap()[&format!("{subdomain}.acme.example.com")],
+            [TXT]
+        );
+
+        let response = client
+            .post("/acme-dns/register")
+            .body(r#"{"allowfrom": ["invalid"]}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+
+        assert_eq!(
+            client.post("/acme-dns/register").dispatch().status(),
+            Status::Created
+        );
+        assert_eq!(
+            client.get("/acme-dns/health").dispatch().status(),
+            Status::Ok
+        );
+    }
+}
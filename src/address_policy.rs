@@ -0,0 +1,178 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+//! Addresses that clients may publish
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use serde::Deserialize;
+
+/// IPv4 networks that are not reachable on the internet, see the IANA special-purpose address registry
+const SPECIAL_IPV4: &[(Ipv4Addr, u8)] = &[
+    (Ipv4Addr::new(0, 0, 0, 0), 8),
+    (Ipv4Addr::new(10, 0, 0, 0), 8),
+    (Ipv4Addr::new(100, 64, 0, 0), 10),
+    (Ipv4Addr::new(127, 0, 0, 0), 8),
+    (Ipv4Addr::new(169, 254, 0, 0), 16),
+    (Ipv4Addr::new(172, 16, 0, 0), 12),
+    (Ipv4Addr::new(192, 0, 0, 0), 24),
+    (Ipv4Addr::new(192, 0, 2, 0), 24),
+    (Ipv4Addr::new(192, 88, 99, 0), 24),
+    (Ipv4Addr::new(192, 168, 0, 0), 16),
+    (Ipv4Addr::new(198, 18, 0, 0), 15),
+    (Ipv4Addr::new(198, 51, 100, 0), 24),
+    (Ipv4Addr::new(203, 0, 113, 0), 24),
+    (Ipv4Addr::new(224, 0, 0, 0), 4),
+    (Ipv4Addr::new(240, 0, 0, 0), 4),
+];
+
+/// Networks within the global unicast range 2000::/3 that are not reachable on the internet
+const SPECIAL_IPV6: &[(Ipv6Addr, u8)] = &[
+    (Ipv6Addr::new(0x2001, 0, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0), 32),
+    (Ipv6Addr::new(0x3fff, 0, 0, 0, 0, 0, 0, 0), 20),
+];
+
+/// Whether an address is a global unicast address, which rules out private, loopback, link-local,
+/// shared (CGNAT), documentation and multicast addresses among others
+#[must_use]
+pub fn is_global(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ipv4) => !SPECIAL_IPV4.iter().any(|(network, prefix)| {
+            Ipv4Net::new(*network, *prefix).is_ok_and(|n| n.contains(&ipv4))
+        }),
+        IpAddr::V6(ipv6) => {
+            let global_unicast = Ipv6Net::new(Ipv6Addr::new(0x2000, 0, 0, 0, 0, 0, 0, 0), 3)
+                .is_ok_and(|n| n.contains(&ipv6));
+
+            global_unicast
+                && !SPECIAL_IPV6.iter().any(|(network, prefix)| {
+                    Ipv6Net::new(*network, *prefix).is_ok_and(|n| n.contains(&ipv6))
+                })
+        }
+    }
+}
+
+fn default_global_only() -> bool {
+    true
+}
+
+/// Addresses that are accepted in updates
+#[derive(Deserialize, Clone)]
+pub struct AddressPolicy {
+    /// Reject addresses that are not global unicast addresses
+    #[serde(default = "default_global_only")]
+    pub global_only: bool,
+    /// Networks that are accepted, even if they are not global; only these are accepted if there are any
+    #[serde(default)]
+    pub allow: Vec<IpNet>,
+    /// Networks that are always rejected
+    #[serde(default)]
+    pub deny: Vec<IpNet>,
+}
+
+impl Default for AddressPolicy {
+    fn default() -> Self {
+        AddressPolicy {
+            global_only: default_global_only(),
+            allow: Vec::new(),
+            deny: Vec::new(),
+        }
+    }
+}
+
+impl AddressPolicy {
+    /// Check an address, returning the reason if it is rejected
+    pub fn check(&self, ip: IpAddr) -> Result<(), String> {
+        if let Some(network) = self.deny.iter().find(|n| n.contains(&ip)) {
+            return Err(format!("Address {ip} is in the denied network {network}"));
+        }
+
+        if !self.allow.is_empty() {
+            return if self.allow.iter().any(|n| n.contains(&ip)) {
+                Ok(())
+            } else {
+                Err(format!("Address {ip} is not in an allowed network"))
+            };
+        }
+
+        if self.global_only && !is_global(ip) {
+            return Err(format!("Address {ip} is not a global unicast address"));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn global_addresses() {
+        for address in ["8.8.8.8", "1.1.1.1", "2a01:4f8::1", "2606:4700::1111"] {
+            assert!(is_global(ip(address)), "{address}");
+        }
+
+        for address in [
+            "127.0.0.1",
+            "192.168.1.1",
+            "10.1.2.3",
+            "172.31.255.255",
+            "100.64.0.1",
+            "169.254.1.1",
+            "192.0.2.1",
+            "224.0.0.1",
+            "255.255.255.255",
+            "::1",
+            "fe80::1",
+            "fd00::1",
+            "2001:db8::1",
+            "ff02::1",
+            "::ffff:8.8.8.8",
+        ] {
+            assert!(!is_global(ip(address)), "{address}");
+        }
+    }
+
+    #[test]
+    fn check_allow_and_deny_lists() {
+        let policy = AddressPolicy::default();
+        assert_eq!(policy.check(ip("8.8.8.8")), Ok(()));
+        assert_eq!(
+            policy.check(ip("100.64.0.1")),
+            Err("Address 100.64.0.1 is not a global unicast address".to_string())
+        );
+
+        let policy = AddressPolicy {
+            global_only: true,
+            allow: vec!["10.0.0.0/8".parse().unwrap()],
+            deny: vec!["10.0.1.0/24".parse().unwrap()],
+        };
+        assert_eq!(policy.check(ip("10.0.0.1")), Ok(()));
+        assert_eq!(
+            policy.check(ip("10.0.1.1")),
+            Err("Address 10.0.1.1 is in the denied network 10.0.1.0/24".to_string())
+        );
+        assert_eq!(
+            policy.check(ip("8.8.8.8")),
+            Err("Address 8.8.8.8 is not in an allowed network".to_string())
+        );
+
+        let policy = AddressPolicy {
+            global_only: false,
+            allow: Vec::new(),
+            deny: vec!["2001:db8::/32".parse().unwrap()],
+        };
+        assert_eq!(policy.check(ip("192.168.1.1")), Ok(()));
+        assert!(policy.check(ip("2001:db8::1")).is_err());
+    }
+}
@@ -0,0 +1,619 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+//! Admin API for managing users, hosts and tokens while the server is running
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use log::{info, warn};
+use rocket::{
+    delete, get,
+    http::Status,
+    patch, post, put,
+    request::{FromRequest, Outcome},
+    serde::json::Json,
+    Build, Request, Rocket, Route, State,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{
+    api::{self, error, ApiError, GroupResponse},
+    auth::Credentials,
+    config::{
+        store::{
+            find_user, generate_token, list_mut, position, token_entry, users_mut, ConfigStore,
+            EditError,
+        },
+        AddressFamily, AdminConfig, User,
+    },
+    logging,
+    security::{self, SecurityEvent},
+};
+
+/// Base path of the admin API
+pub const BASE: &str = "/api/v1/admin";
+
+/// Request guard for requests authenticated with the admin token
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(store) = request.rocket().state::<ConfigStore>() else {
+            return Outcome::Error((Status::InternalServerError, ()));
+        };
+        let Some(admin) = store.get().admin.clone() else {
+            return Outcome::Error((Status::NotFound, ()));
+        };
+
+        match Credentials::from_request(request).await {
+            Outcome::Success(Credentials::Bearer(token)) if token == admin.token => {
+                Outcome::Success(Admin)
+            }
+            _ => {
+                warn!("Invalid admin token");
+                security::log_event(
+                    SecurityEvent::InvalidToken,
+                    request.client_ip(),
+                    "admin",
+                    request.uri().path().as_str(),
+                );
+                Outcome::Error((Status::Unauthorized, ()))
+            }
+        }
+    }
+}
+
+pub fn edit_error(e: EditError) -> ApiError {
+    let status = match e {
+        EditError::NotFound(_) => Status::NotFound,
+        EditError::Conflict(_) => Status::Conflict,
+        EditError::Invalid(_) => Status::BadRequest,
+        EditError::Storage(_) => Status::InternalServerError,
+    };
+
+    error(status, &e.to_string())
+}
+
+/// Apply a change and log it
+fn edit(
+    store: &ConfigStore,
+    description: &str,
+    change: impl FnOnce(&mut Value) -> Result<(), EditError>,
+) -> Result<(), ApiError> {
+    let _context = logging::enter_request();
+    logging::set_field("user", "admin");
+
+    match store.edit(change) {
+        Ok(()) => {
+            info!("Admin API: {description}");
+            Ok(())
+        }
+        Err(e) => {
+            warn!("Admin API: Rejected to {description}: {e}");
+            Err(edit_error(e))
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct TokenResponse {
+    pub name: String,
+    pub hosts: Vec<String>,
+    pub families: Vec<String>,
+    pub expires: Option<String>,
+}
+
+/// User without any secrets
+#[derive(Serialize)]
+pub struct UserResponse {
+    pub name: String,
+    pub hosts: Vec<String>,
+    pub groups: Vec<GroupResponse>,
+    pub tokens: Vec<TokenResponse>,
+}
+
+impl From<&User> for UserResponse {
+    fn from(user: &User) -> Self {
+        UserResponse {
+            name: user.name.clone(),
+            hosts: user.domains.iter().map(|d| d.host.clone()).collect(),
+            groups: user
+                .groups
+                .iter()
+                .map(|g| GroupResponse {
+                    name: g.name.clone(),
+                    hosts: g.hosts.clone(),
+                })
+                .collect(),
+            tokens: user
+                .tokens
+                .iter()
+                .map(|t| TokenResponse {
+                    name: t.name.clone(),
+                    hosts: t.hosts.clone(),
+                    families: t.families.iter().map(ToString::to_string).collect(),
+                    expires: t.expires.and_then(|e| e.format(&Rfc3339).ok()),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[get("/users")]
+fn list_users(_admin: Admin, store: &State<ConfigStore>) -> Json<Vec<UserResponse>> {
+    Json(store.get().users.iter().map(UserResponse::from).collect())
+}
+
+#[derive(Deserialize)]
+pub struct NewUser {
+    pub name: String,
+    pub password: String,
+}
+
+#[post("/users", data = "<user>")]
+fn add_user(
+    _admin: Admin,
+    store: &State<ConfigStore>,
+    user: Json<NewUser>,
+) -> Result<Status, ApiError> {
+    edit(store, &format!("add user {}", user.name), |value| {
+        let users = users_mut(value)?;
+        if position(users, "name", &user.name).is_some() {
+            return Err(EditError::Conflict(format!(
+                "User {} already exists",
+                user.name
+            )));
+        }
+
+        users.push(json!({ "name": user.name, "password": user.password, "domains": [] }));
+        Ok(())
+    })?;
+
+    Ok(Status::Created)
+}
+
+#[derive(Deserialize)]
+pub struct UserChange {
+    pub password: Option<String>,
+}
+
+#[patch("/users/<name>", data = "<change>")]
+fn change_user(
+    _admin: Admin,
+    store: &State<ConfigStore>,
+    name: &str,
+    change: Json<UserChange>,
+) -> Result<Status, ApiError> {
+    edit(store, &format!("change user {name}"), |value| {
+        let user = find_user(value, name)?;
+        if let Some(password) = &change.password {
+            user["password"] = json!(password);
+        }
+        Ok(())
+    })?;
+
+    Ok(Status::NoContent)
+}
+
+#[delete("/users/<name>")]
+fn delete_user(_admin: Admin, store: &State<ConfigStore>, name: &str) -> Result<Status, ApiError> {
+    edit(store, &format!("delete user {name}"), |value| {
+        let users = users_mut(value)?;
+        let index = position(users, "name", name)
+            .ok_or_else(|| EditError::NotFound(format!("User {name} not found")))?;
+        users.remove(index);
+        Ok(())
+    })?;
+
+    Ok(Status::NoContent)
+}
+
+/// Check that a host is given as object with the expected name
+fn domain_entry(domain: Value, host: Option<&str>) -> Result<Value, EditError> {
+    match domain.get("host").and_then(Value::as_str) {
+        Some(name) if host.is_none_or(|h| h == name) => Ok(domain),
+        Some(name) => Err(EditError::Invalid(format!(
+            "Host {name} does not match {}",
+            host.unwrap_or_default()
+        ))),
+        None => Err(EditError::Invalid("Missing host".to_string())),
+    }
+}
+
+#[post("/users/<name>/hosts", data = "<domain>")]
+fn add_host(
+    _admin: Admin,
+    store: &State<ConfigStore>,
+    name: &str,
+    domain: Json<Value>,
+) -> Result<Status, ApiError> {
+    let domain = domain_entry(domain.into_inner(), None).map_err(edit_error)?;
+    let host = domain["host"].as_str().unwrap_or_default().to_string();
+
+    edit(store, &format!("add host {host} to user {name}"), |value| {
+        let domains = list_mut(find_user(value, name)?, "domains")?;
+        if position(domains, "host", &host).is_some() {
+            return Err(EditError::Conflict(format!(
+                "Host {host} of user {name} already exists"
+            )));
+        }
+
+        domains.push(domain);
+        Ok(())
+    })?;
+
+    Ok(Status::Created)
+}
+
+#[put("/users/<name>/hosts/<host>", data = "<domain>")]
+fn replace_host(
+    _admin: Admin,
+    store: &State<ConfigStore>,
+    name: &str,
+    host: &str,
+    domain: Json<Value>,
+) -> Result<Status, ApiError> {
+    let domain = domain_entry(domain.into_inner(), Some(host)).map_err(edit_error)?;
+
+    edit(
+        store,
+        &format!("replace host {host} of user {name}"),
+        |value| {
+            let domains = list_mut(find_user(value, name)?, "domains")?;
+            let index = position(domains, "host", host).ok_or_else(|| {
+                EditError::NotFound(format!("Host {host} of user {name} not found"))
+            })?;
+
+            domains[index] = domain;
+            Ok(())
+        },
+    )?;
+
+    Ok(Status::NoContent)
+}
+
+#[delete("/users/<name>/hosts/<host>")]
+fn delete_host(
+    _admin: Admin,
+    store: &State<ConfigStore>,
+    name: &str,
+    host: &str,
+) -> Result<Status, ApiError> {
+    edit(
+        store,
+        &format!("delete host {host} of user {name}"),
+        |value| {
+            let domains = list_mut(find_user(value, name)?, "domains")?;
+            let index = position(domains, "host", host).ok_or_else(|| {
+                EditError::NotFound(format!("Host {host} of user {name} not found"))
+            })?;
+
+            domains.remove(index);
+            Ok(())
+        },
+    )?;
+
+    Ok(Status::NoContent)
+}
+
+#[derive(Deserialize)]
+pub struct NewToken {
+    pub name: String,
+    /// Generated if not given
+    pub token: Option<String>,
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    #[serde(default)]
+    pub families: Vec<AddressFamily>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub expires: Option<OffsetDateTime>,
+}
+
+#[derive(Serialize)]
+pub struct NewTokenResponse {
+    pub name: String,
+    pub token: String,
+}
+
+#[post("/users/<name>/tokens", data = "<token>")]
+fn add_token(
+    _admin: Admin,
+    store: &State<ConfigStore>,
+    name: &str,
+    token: Json<NewToken>,
+) -> Result<(Status, Json<NewTokenResponse>), ApiError> {
+    let secret = token.token.clone().unwrap_or_else(generate_token);
+
+    edit(
+        store,
+        &format!("add token {} to user {name}", token.name),
+        |value| {
+            let tokens = list_mut(find_user(value, name)?, "tokens")?;
+            if position(tokens, "name", &token.name).is_some() {
+                return Err(EditError::Conflict(format!(
+                    "Token {} of user {name} already exists",
+                    token.name
+                )));
+            }
+
+            tokens.push(token_entry(
+                &token.name,
+                &secret,
+                &token.hosts,
+                &token.families,
+                token.expires,
+            )?);
+            Ok(())
+        },
+    )?;
+
+    Ok((
+        Status::Created,
+        Json(NewTokenResponse {
+            name: token.name.clone(),
+            token: secret,
+        }),
+    ))
+}
+
+#[delete("/users/<name>/tokens/<token>")]
+fn delete_token(
+    _admin: Admin,
+    store: &State<ConfigStore>,
+    name: &str,
+    token: &str,
+) -> Result<Status, ApiError> {
+    edit(
+        store,
+        &format!("delete token {token} of user {name}"),
+        |value| {
+            let tokens = list_mut(find_user(value, name)?, "tokens")?;
+            let index = position(tokens, "name", token).ok_or_else(|| {
+                EditError::NotFound(format!("Token {token} of user {name} not found"))
+            })?;
+
+            tokens.remove(index);
+            Ok(())
+        },
+    )?;
+
+    Ok(Status::NoContent)
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![
+        list_users,
+        add_user,
+        change_user,
+        delete_user,
+        add_host,
+        replace_host,
+        delete_host,
+        add_token,
+        delete_token
+    ]
+}
+
+/// Mount the admin API on a server
+pub fn mount(rocket: Rocket<Build>) -> Rocket<Build> {
+    rocket.mount(BASE, routes()).register(BASE, api::catchers())
+}
+
+/// Separate server for the admin API, if a port is configured for it
+#[must_use]
+pub fn listener(config: &AdminConfig) -> Option<Rocket<Build>> {
+    let port = config.port?;
+    let address = config.address.unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+    let figment = rocket::Config::figment()
+        .merge(("address", address))
+        .merge(("port", port));
+
+    Some(mount(rocket::custom(figment)))
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{
+        http::{ContentType, Header},
+        local::blocking::Client,
+    };
+
+    use crate::{
+        config::Providers,
+        history::{History, HistoryConfig},
+        keyed::KeyedEntries,
+        metrics::Metrics,
+        notifications::Notifier,
+        providers::MockProvider,
+        server,
+        ui::Sessions,
+    };
+
+    use super::*;
+
+    const ADMIN: &str = "Bearer testadmintoken";
+
+    fn construct() -> Client {
+        let store = ConfigStore::in_memory(json!({
+            "users": [{
+                "name": "test",
+                "password": "testpassword",
+                "domains": [{
+                    "provider": "MockProvider",
+                    "apitoken": "testapitoken",
+                    "host": "example.com",
+                    "zone": {"name": "example.com", "id": "idexamplecom"}
+                }]
+            }],
+            "admin": {"token": "testadmintoken"}
+        }))
+        .unwrap();
+
+        let mut mock = MockProvider::default();
+        mock.expect_get_ips().returning(|_| Ok(Vec::new()));
+
+        Client::tracked(
+            mount(server::rocket(rocket::Config::figment()))
+                .manage(store)
+                .manage(Providers {
+                    hetzner_provider: None,
+                    rfc2136_provider: None,
+                    mock_provider: Some(mock),
+                })
+                .manage(Notifier::new(&Default::default()).unwrap())
+                .manage(Metrics::default())
+                .manage(History::new(&HistoryConfig::default()).unwrap())
+                .manage(KeyedEntries::default())
+                .manage(Sessions::default()),
+        )
+        .expect("valid rocket instance")
+    }
+
+    fn users(client: &Client) -> String {
+        client
+            .get("/api/v1/admin/users")
+            .header(Header::new("Authorization", ADMIN))
+            .dispatch()
+            .into_string()
+            .unwrap()
+    }
+
+    #[test]
+    fn reject_invalid_token() {
+        let client = construct();
+
+        for authorization in ["Bearer wrong", "Basic dGVzdDp0ZXN0cGFzc3dvcmQ="] {
+            let response = client
+                .get("/api/v1/admin/users")
+                .header(Header::new("Authorization", authorization))
+                .dispatch();
+            assert_eq!(response.status(), Status::Unauthorized);
+            assert_eq!(
+                response.into_string().unwrap(),
+                r#"{"error":"Unauthorized"}"#
+            );
+        }
+    }
+
+    #[test]
+    fn manage_hosts_and_tokens() {
+        let client = construct();
+
+        let response = client
+            .post("/api/v1/admin/users/test/hosts")
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", ADMIN))
+            .body(r#"{"provider": "MockProvider", "apitoken": "testapitoken", "host": "camera.example.com", "zone": {"name": "example.com", "id": "idexamplecom"}}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+
+        let response = client
+            .post("/api/v1/admin/users/test/tokens")
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", ADMIN))
+            .body(r#"{"name": "camera", "token": "testcameratoken", "hosts": ["camera.example.com"], "families": ["ipv6"]}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+        assert_eq!(
+            response.into_string().unwrap(),
+            r#"{"name":"camera","token":"testcameratoken"}"#
+        );
+
+        assert_eq!(
+            users(&client),
+            r#"[{"name":"test","hosts":["example.com","camera.example.com"],"groups":[],"tokens":[{"name":"camera","hosts":["camera.example.com"],"families":["ipv6"],"expires":null}]}]"#
+        );
+
+        // The new token is accepted right away
+        let response = client
+            .get("/api/v1/hosts")
+            .header(Header::new("Authorization", "Bearer testcameratoken"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        // The host cannot be deleted while the token refers to it
+        let response = client
+            .delete("/api/v1/admin/users/test/hosts/camera.example.com")
+            .header(Header::new("Authorization", ADMIN))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let response = client
+            .delete("/api/v1/admin/users/test/tokens/camera")
+            .header(Header::new("Authorization", ADMIN))
+            .dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+
+        let response = client
+            .delete("/api/v1/admin/users/test/hosts/camera.example.com")
+            .header(Header::new("Authorization", ADMIN))
+            .dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+
+        assert_eq!(
+            users(&client),
+            r#"[{"name":"test","hosts":["example.com"],"groups":[],"tokens":[]}]"#
+        );
+    }
+
+    #[test]
+    fn manage_users() {
+        let client = construct();
+
+        let response = client
+            .post("/api/v1/admin/users")
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", ADMIN))
+            .body(r#"{"name": "other", "password": "otherpassword"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+
+        let response = client
+            .post("/api/v1/admin/users")
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", ADMIN))
+            .body(r#"{"name": "other", "password": "otherpassword"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Conflict);
+
+        let response = client
+            .patch("/api/v1/admin/users/other")
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", ADMIN))
+            .body(r#"{"password": "newpassword"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+
+        // The new password is used right away
+        let response = client
+            .get("/update?user=other&password=newpassword&host=example.com")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(response.into_string().unwrap(), "Invalid domain");
+
+        let response = client
+            .delete("/api/v1/admin/users/other")
+            .header(Header::new("Authorization", ADMIN))
+            .dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+
+        let response = client
+            .delete("/api/v1/admin/users/other")
+            .header(Header::new("Authorization", ADMIN))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(
+            response.into_string().unwrap(),
+            r#"{"error":"User other not found"}"#
+        );
+    }
+}
@@ -0,0 +1,630 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+//! JSON API with authentication in the Authorization header instead of the query string
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use log::{info, warn};
+use rocket::{catch, get, http::Status, post, serde::json::Json, Catcher, Request, Route, State};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+    auth::{Authenticated, Credentials},
+    config::{store::ConfigStore, Config, Providers},
+    history::{Entry, History},
+    keyed::KeyedEntries,
+    logging,
+    metrics::Metrics,
+    notifications::Notifier,
+    providers::UpdateStatus,
+    update::{worse, HostResult, RecordResult, Updater},
+};
+
+#[derive(Deserialize)]
+pub struct UpdateRequest {
+    /// Host, comma-separated list of hosts or group
+    pub host: String,
+    pub ipv4: Option<Ipv4Addr>,
+    pub ipv6: Option<Ipv6Addr>,
+    /// TTL in seconds that is set on the updated records
+    pub ttl: Option<u64>,
+    /// Client that owns the addresses in hosts with keyed records, the name of the token by default
+    pub key: Option<String>,
+    /// Take the hosts offline or bring them back online
+    pub offline: Option<bool>,
+    /// Address in the local network, set in the internal zone of hosts that have one
+    pub lanip: Option<IpAddr>,
+    /// Wait for the verification of the updated records and include its result
+    #[serde(default)]
+    pub verify: bool,
+}
+
+#[derive(Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+pub type ApiError = (Status, Json<ErrorResponse>);
+
+pub fn error(status: Status, message: &str) -> ApiError {
+    (
+        status,
+        Json(ErrorResponse {
+            error: message.to_string(),
+        }),
+    )
+}
+
+#[derive(Serialize)]
+pub struct VerificationResponse {
+    /// verified, timeout or error
+    pub result: &'static str,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct RecordResponse {
+    pub ip: IpAddr,
+    /// updated, unchanged or failed
+    pub result: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous: Option<IpAddr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification: Option<VerificationResponse>,
+}
+
+impl From<&RecordResult> for RecordResponse {
+    fn from(record: &RecordResult) -> Self {
+        let (result, previous, error) = match &record.outcome {
+            Ok(UpdateStatus::Updated { previous }) => ("updated", *previous, None),
+            Ok(UpdateStatus::Unchanged) => ("unchanged", None, None),
+            Ok(UpdateStatus::TtlUpdated) => ("ttl_updated", None, None),
+            Err(e) => ("failed", None, Some(e.clone())),
+        };
+
+        RecordResponse {
+            ip: record.new_ip,
+            result,
+            previous,
+            error,
+            verification: record
+                .verification
+                .as_ref()
+                .map(|verification| VerificationResponse {
+                    result: verification.label(),
+                    message: verification.to_string(),
+                }),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct HostUpdateResponse {
+    pub host: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipv4: Option<RecordResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipv6: Option<RecordResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lan: Option<RecordResponse>,
+}
+
+impl HostUpdateResponse {
+    fn new(host: &str, result: &HostResult) -> (Status, Self) {
+        let (status, mut lines) = result.describe();
+        let mut response = HostUpdateResponse {
+            host: host.to_string(),
+            error: None,
+            ipv4: None,
+            ipv6: None,
+            lan: None,
+        };
+
+        match result {
+            HostResult::InvalidDomain
+            | HostResult::Forbidden(_)
+            | HostResult::Offline
+            | HostResult::OfflineFailed(_)
+            | HostResult::RejectedAddress(_) => {
+                response.error = lines.pop();
+            }
+            HostResult::Updated(records) => {
+                for record in records {
+                    if record.lan {
+                        response.lan = Some(record.into());
+                    } else if record.new_ip.is_ipv4() {
+                        response.ipv4 = Some(record.into());
+                    } else {
+                        response.ipv6 = Some(record.into());
+                    }
+                }
+            }
+        }
+
+        (status, response)
+    }
+}
+
+#[derive(Serialize)]
+pub struct UpdateResponse {
+    pub hosts: Vec<HostUpdateResponse>,
+}
+
+/// Check the credentials of a request, which are required
+pub fn authenticate<'a>(
+    credentials: Option<Credentials>,
+    config: &'a Config,
+    client_ip: Option<IpAddr>,
+    host: &str,
+) -> Result<Authenticated<'a>, ApiError> {
+    let Some(credentials) = credentials else {
+        return Err(error(Status::Unauthorized, "Missing credentials"));
+    };
+    logging::set_field("user", credentials.user());
+
+    credentials
+        .authenticate(config, client_ip, host)
+        .map_err(|()| error(Status::Unauthorized, "Invalid credentials"))
+}
+
+#[post("/update", data = "<request>")]
+#[allow(clippy::too_many_arguments)]
+fn update(
+    request: Json<UpdateRequest>,
+    credentials: Option<Credentials>,
+    client_ip: Option<IpAddr>,
+    store: &State<ConfigStore>,
+    providers: &State<Providers>,
+    notifier: &State<Notifier>,
+    metrics: &State<Metrics>,
+    history: &State<History>,
+    keyed_entries: &State<KeyedEntries>,
+) -> Result<(Status, Json<UpdateResponse>), ApiError> {
+    let _context = logging::enter_request();
+    logging::set_field("host", &request.host);
+    let config = &store.get();
+
+    let authenticated = authenticate(credentials, config, client_ip, &request.host)?;
+    let user = authenticated.user;
+    logging::set_field("user", &user.name);
+
+    let addresses: Vec<IpAddr> = request
+        .ipv4
+        .map(IpAddr::V4)
+        .into_iter()
+        .chain(request.ipv6.map(IpAddr::V6))
+        .collect();
+
+    // Hosts are taken offline without addresses, they point to their maintenance addresses then
+    if addresses.is_empty() && request.lanip.is_none() && request.offline != Some(true) {
+        return Err(error(Status::BadRequest, "No IP address specified"));
+    }
+
+    info!(
+        "Received IP addresses via API: {}",
+        addresses
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    let hosts: Vec<&str> = request.host.split(',').map(str::trim).collect();
+    let hosts = user.expand_groups(&hosts);
+
+    let updater = Updater {
+        user,
+        token: authenticated.token,
+        client_ip,
+        config,
+        store,
+        providers,
+        notifier,
+        metrics,
+        history,
+        keyed_entries,
+        ttl: request.ttl,
+        key: request.key.as_deref(),
+        offline: request.offline,
+        lan_ip: request.lanip,
+        verify: request.verify,
+    };
+
+    let mut status = Status::Ok;
+    let mut response = UpdateResponse { hosts: Vec::new() };
+
+    for host in hosts {
+        let (host_status, host_response) =
+            HostUpdateResponse::new(host, &updater.update_host(host, &addresses));
+        status = worse(status, host_status);
+        response.hosts.push(host_response);
+    }
+
+    Ok((status, Json(response)))
+}
+
+#[derive(Serialize)]
+pub struct LastError {
+    #[serde(with = "time::serde::rfc3339")]
+    pub time: OffsetDateTime,
+    pub error: String,
+}
+
+#[derive(Serialize)]
+pub struct HostResponse {
+    pub host: String,
+    pub ipv4: Vec<Ipv4Addr>,
+    pub ipv6: Vec<Ipv6Addr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_update: Option<OffsetDateTime>,
+    pub last_client: Option<IpAddr>,
+    pub last_error: Option<LastError>,
+}
+
+/// Current values and latest update of the hosts that can be updated with the credentials
+pub fn host_overview(
+    authenticated: Authenticated<'_>,
+    providers: &Providers,
+    history: &History,
+) -> Vec<HostResponse> {
+    authenticated
+        .user
+        .domains
+        .iter()
+        .filter(|d| authenticated.token.is_none_or(|t| t.allows_host(&d.host)))
+        .map(|domain_config| {
+            let status = history.status(&authenticated.user.name, &domain_config.host);
+            let mut response = HostResponse {
+                host: domain_config.host.clone(),
+                ipv4: Vec::new(),
+                ipv6: Vec::new(),
+                error: None,
+                last_update: status.last_update,
+                last_client: status.last_client,
+                last_error: status
+                    .last_error
+                    .map(|(time, error)| LastError { time, error }),
+            };
+
+            match providers
+                .get(domain_config)
+                .and_then(|provider| provider.get_ips(domain_config))
+            {
+                Ok(addresses) => {
+                    for address in addresses {
+                        match address {
+                            IpAddr::V4(ipv4) => response.ipv4.push(ipv4),
+                            IpAddr::V6(ipv6) => response.ipv6.push(ipv6),
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!("Error getting records of {}: {e}", domain_config.host);
+                    response.error = Some(format!("Error: {e}"));
+                }
+            }
+
+            response
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+pub struct GroupResponse {
+    pub name: String,
+    pub hosts: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct HostsResponse {
+    pub hosts: Vec<HostResponse>,
+    pub groups: Vec<GroupResponse>,
+}
+
+#[get("/hosts")]
+fn hosts(
+    credentials: Option<Credentials>,
+    client_ip: Option<IpAddr>,
+    store: &State<ConfigStore>,
+    providers: &State<Providers>,
+    history: &State<History>,
+) -> Result<Json<HostsResponse>, ApiError> {
+    let _context = logging::enter_request();
+    let config = &store.get();
+
+    let authenticated = authenticate(credentials, config, client_ip, "-")?;
+    let user = authenticated.user;
+    logging::set_field("user", &user.name);
+
+    // Scoped tokens only see the hosts they may update, and groups of which they may update a host
+    let allowed = |host: &String| authenticated.token.is_none_or(|t| t.allows_host(host));
+    let groups = user
+        .groups
+        .iter()
+        .map(|group| GroupResponse {
+            name: group.name.clone(),
+            hosts: group.hosts.iter().filter(|h| allowed(h)).cloned().collect(),
+        })
+        .filter(|group| !group.hosts.is_empty())
+        .collect();
+
+    Ok(Json(HostsResponse {
+        hosts: host_overview(authenticated, providers, history),
+        groups,
+    }))
+}
+
+#[get("/history?<host>")]
+fn host_history(
+    host: &str,
+    credentials: Option<Credentials>,
+    client_ip: Option<IpAddr>,
+    store: &State<ConfigStore>,
+    history: &State<History>,
+) -> Result<Json<Vec<Entry>>, ApiError> {
+    let _context = logging::enter_request();
+    logging::set_field("host", host);
+    let config = &store.get();
+
+    let authenticated = authenticate(credentials, config, client_ip, host)?;
+    logging::set_field("user", &authenticated.user.name);
+
+    let allowed = authenticated.user.domains.iter().any(|d| d.host == host)
+        && authenticated.token.is_none_or(|t| t.allows_host(host));
+    if !allowed {
+        return Err(error(Status::BadRequest, "Invalid domain"));
+    }
+
+    Ok(Json(history.entries(&authenticated.user.name, host)))
+}
+
+/// Answer errors like malformed requests in JSON as well
+#[catch(default)]
+fn default_catcher(status: Status, _request: &Request) -> Json<ErrorResponse> {
+    Json(ErrorResponse {
+        error: status.reason_lossy().to_string(),
+    })
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![update, hosts, host_history]
+}
+
+pub fn catchers() -> Vec<Catcher> {
+    rocket::catchers![default_catcher]
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::{ContentType, Header};
+
+    use crate::{
+        providers::{MockProvider, RecordNotFoundError},
+        server::tests::construct,
+    };
+
+    use super::*;
+
+    const BEARER: &str = "Bearer testapitoken";
+
+    #[test]
+    fn missing_credentials() {
+        let client = construct(Some(MockProvider::default()));
+        let response = client
+            .post("/api/v1/update")
+            .header(ContentType::JSON)
+            .body(r#"{"host": "example.com", "ipv4": "192.0.2.1"}"#)
+            .dispatch();
+
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(
+            response.into_string().unwrap(),
+            r#"{"error":"Missing credentials"}"#
+        );
+    }
+
+    #[test]
+    fn invalid_token() {
+        let client = construct(Some(MockProvider::default()));
+        let response = client
+            .post("/api/v1/update")
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", "Bearer wrong"))
+            .body(r#"{"host": "example.com", "ipv4": "192.0.2.1"}"#)
+            .dispatch();
+
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn malformed_body() {
+        let client = construct(Some(MockProvider::default()));
+        let response = client
+            .post("/api/v1/update")
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", BEARER))
+            .body(r#"{"host": "example.com", "ipv4": "192.0.2.300"}"#)
+            .dispatch();
+
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        assert_eq!(
+            response.into_string().unwrap(),
+            r#"{"error":"Unprocessable Entity"}"#
+        );
+    }
+
+    #[test]
+    fn update_with_token() {
+        let mut mock = MockProvider::default();
+        mock.expect_update_ip()
+            .withf(|d, ip, ttl| d.host == "example.com" && ip.is_ipv4() && *ttl == Some(300))
+            .once()
+            .returning(|_, _, _| {
+                Ok(UpdateStatus::Updated {
+                    previous: Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))),
+                })
+            });
+        mock.expect_update_ip()
+            .withf(|d, ip, _| d.host == "example.com" && ip.is_ipv6())
+            .once()
+            .returning(|_, _, _| Ok(UpdateStatus::Unchanged));
+
+        let client = construct(Some(mock));
+        let response = client
+            .post("/api/v1/update")
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", BEARER))
+            .body(r#"{"host": "example.com", "ipv4": "192.0.2.2", "ipv6": "2001:db8::1", "ttl": 300}"#)
+            .dispatch();
+
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.into_string().unwrap(),
+            r#"{"hosts":[{"host":"example.com","ipv4":{"ip":"192.0.2.2","result":"updated","previous":"192.0.2.1"},"ipv6":{"ip":"2001:db8::1","result":"unchanged"}}]}"#
+        );
+    }
+
+    #[test]
+    fn update_with_basic_auth() {
+        let mut mock = MockProvider::default();
+        mock.expect_update_ip()
+            .withf(|d, _, ttl| d.host == "www.example.com" && ttl.is_none())
+            .once()
+            .returning(|_, _, _| Err(Box::new(RecordNotFoundError)));
+
+        let client = construct(Some(mock));
+        let response = client
+            .post("/api/v1/update")
+            .header(ContentType::JSON)
+            // test:testpassword
+            .header(Header::new(
+                "Authorization",
+                "Basic dGVzdDp0ZXN0cGFzc3dvcmQ=",
+            ))
+            .body(r#"{"host": "www.example.com, foreign.example.com", "ipv4": "192.0.2.2"}"#)
+            .dispatch();
+
+        assert_eq!(response.status(), Status::InternalServerError);
+        assert_eq!(
+            response.into_string().unwrap(),
+            r#"{"hosts":[{"host":"www.example.com","ipv4":{"ip":"192.0.2.2","result":"failed","error":"Error: Record not found"}},{"host":"foreign.example.com","error":"Invalid domain"}]}"#
+        );
+    }
+
+    #[test]
+    fn list_hosts() {
+        let mut mock = MockProvider::default();
+        mock.expect_get_ips()
+            .withf(|d| d.host == "example.com")
+            .returning(|_| {
+                Ok(vec![
+                    IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+                    IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+                ])
+            });
+        mock.expect_get_ips()
+            .withf(|d| d.host == "www.example.com")
+            .returning(|_| Err(Box::new(RecordNotFoundError)));
+
+        let client = construct(Some(mock));
+        let response = client
+            .get("/api/v1/hosts")
+            .header(Header::new("Authorization", BEARER))
+            .dispatch();
+
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.into_string().unwrap(),
+            r#"{"hosts":[{"host":"example.com","ipv4":["192.0.2.1"],"ipv6":["2001:db8::1"],"last_update":null,"last_client":null,"last_error":null},{"host":"www.example.com","ipv4":[],"ipv6":[],"error":"Error: Record not found","last_update":null,"last_client":null,"last_error":null}],"groups":[{"name":"home","hosts":["www.example.com","example.com"]}]}"#
+        );
+    }
+
+    #[test]
+    fn list_hosts_of_scoped_token() {
+        let mut mock = MockProvider::default();
+        mock.expect_get_ips()
+            .withf(|d| d.host == "www.example.com")
+            .once()
+            .returning(|_| Ok(Vec::new()));
+
+        let client = construct(Some(mock));
+        let response = client
+            .get("/api/v1/hosts")
+            .header(Header::new("Authorization", "Bearer testwwwtoken"))
+            .dispatch();
+
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.into_string().unwrap(),
+            r#"{"hosts":[{"host":"www.example.com","ipv4":[],"ipv6":[],"last_update":null,"last_client":null,"last_error":null}],"groups":[{"name":"home","hosts":["www.example.com"]}]}"#
+        );
+    }
+
+    #[test]
+    fn history_of_updates() {
+        let mut mock = MockProvider::default();
+        mock.expect_update_ip()
+            .once()
+            .returning(|_, _, _| Err(Box::new(RecordNotFoundError)));
+        mock.expect_update_ip()
+            .once()
+            .returning(|_, _, _| Ok(UpdateStatus::Unchanged));
+        mock.expect_get_ips().returning(|_| Ok(Vec::new()));
+
+        let client = construct(Some(mock));
+        for _ in 0..2 {
+            client
+                .post("/api/v1/update")
+                .header(ContentType::JSON)
+                .header(Header::new("Authorization", BEARER))
+                .header(Header::new("X-Real-IP", "198.51.100.1"))
+                .body(r#"{"host": "example.com", "ipv4": "192.0.2.2"}"#)
+                .dispatch();
+        }
+
+        let response = client
+            .get("/api/v1/history?host=example.com")
+            .header(Header::new("Authorization", BEARER))
+            .dispatch();
+        let entries: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(entries[0]["result"], "unchanged");
+        assert_eq!(entries[0]["client"], "198.51.100.1");
+        assert_eq!(entries[1]["result"], "failed");
+        assert_eq!(entries[1]["error"], "Error: Record not found");
+
+        let response = client
+            .get("/api/v1/hosts")
+            .header(Header::new("Authorization", BEARER))
+            .dispatch();
+        let hosts: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(hosts["hosts"][0]["last_client"], "198.51.100.1");
+        assert_eq!(
+            hosts["hosts"][0]["last_error"]["error"],
+            "Error: Record not found"
+        );
+        assert!(hosts["hosts"][0]["last_update"].is_string());
+        assert!(hosts["hosts"][1]["last_update"].is_null());
+
+        let response = client
+            .get("/api/v1/history?host=www.example.com")
+            .header(Header::new("Authorization", "Bearer testwwwtoken"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client
+            .get("/api/v1/history?host=example.com")
+            .header(Header::new("Authorization", "Bearer testwwwtoken"))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+}
@@ -0,0 +1,193 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+use std::net::IpAddr;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::warn;
+use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome},
+    Request,
+};
+use time::OffsetDateTime;
+
+use crate::{
+    config::{ApiToken, Config, User},
+    logging,
+    security::{self, SecurityEvent},
+};
+
+/// Authenticated user, and the token that was used instead of the password
+#[derive(Clone, Copy)]
+pub struct Authenticated<'a> {
+    pub user: &'a User,
+    /// Restricts the hosts and address families that may be updated
+    pub token: Option<&'a ApiToken>,
+}
+
+/// Reject expired tokens and remember the token in the log context
+fn check_token<'a>(
+    user: &'a User,
+    token: &'a ApiToken,
+    client: Option<IpAddr>,
+    host: &str,
+) -> Result<Authenticated<'a>, ()> {
+    if token.is_expired(OffsetDateTime::now_utc()) {
+        warn!("API token {} of user {} expired", token.name, user.name);
+        security::log_event(SecurityEvent::ExpiredToken, client, &user.name, host);
+        return Err(());
+    }
+
+    logging::set_field("token", &token.name);
+
+    Ok(Authenticated {
+        user,
+        token: Some(token),
+    })
+}
+
+/// Check username and password, where a token of the user is accepted as password as well
+pub fn get_user<'user_config_lifetime>(
+    config: &'user_config_lifetime Config,
+    username: &str,
+    password: &str,
+    client: Option<IpAddr>,
+    host: &str,
+) -> Result<Authenticated<'user_config_lifetime>, ()> {
+    let user_config = config.users.iter().find(|u| u.name == username);
+
+    if user_config.is_none() {
+        warn!("Invalid user {}", username);
+        security::log_event(SecurityEvent::InvalidUser, client, username, host);
+        return Err(());
+    }
+    let user = user_config.unwrap();
+
+    if user.password == password {
+        return Ok(Authenticated { user, token: None });
+    }
+
+    if let Some(token) = user.tokens.iter().find(|t| t.token == password) {
+        return check_token(user, token, client, host);
+    }
+
+    warn!("Wrong password for user {}", user.name);
+    security::log_event(SecurityEvent::WrongPassword, client, username, host);
+    Err(())
+}
+
+/// Find the user an API token belongs to
+pub fn get_token_user<'a>(
+    config: &'a Config,
+    token: &str,
+    client: Option<IpAddr>,
+    host: &str,
+) -> Result<Authenticated<'a>, ()> {
+    let found = config
+        .users
+        .iter()
+        .find_map(|u| u.tokens.iter().find(|t| t.token == token).map(|t| (u, t)));
+
+    let Some((user, api_token)) = found else {
+        warn!("Invalid API token");
+        security::log_event(SecurityEvent::InvalidToken, client, "-", host);
+        return Err(());
+    };
+
+    check_token(user, api_token, client, host)
+}
+
+/// Credentials from the Authorization header of a request
+#[derive(PartialEq, Eq, Debug)]
+pub enum Credentials {
+    Basic { user: String, password: String },
+    Bearer(String),
+}
+
+impl Credentials {
+    /// Parse the value of an Authorization header
+    #[must_use]
+    pub fn parse(header: &str) -> Option<Credentials> {
+        let (scheme, value) = header.trim().split_once(' ')?;
+        let value = value.trim();
+
+        if scheme.eq_ignore_ascii_case("basic") {
+            let decoded = String::from_utf8(STANDARD.decode(value).ok()?).ok()?;
+            let (user, password) = decoded.split_once(':')?;
+
+            Some(Credentials::Basic {
+                user: user.to_string(),
+                password: password.to_string(),
+            })
+        } else if scheme.eq_ignore_ascii_case("bearer") && !value.is_empty() {
+            Some(Credentials::Bearer(value.to_string()))
+        } else {
+            None
+        }
+    }
+
+    /// Name of the user for logging, as far as it is known before authentication
+    #[must_use]
+    pub fn user(&self) -> &str {
+        match self {
+            Credentials::Basic { user, .. } => user,
+            Credentials::Bearer(_) => "-",
+        }
+    }
+
+    /// Find the user the credentials belong to and check them
+    pub fn authenticate<'a>(
+        &self,
+        config: &'a Config,
+        client: Option<IpAddr>,
+        host: &str,
+    ) -> Result<Authenticated<'a>, ()> {
+        match self {
+            Credentials::Basic { user, password } => get_user(config, user, password, client, host),
+            Credentials::Bearer(token) => get_token_user(config, token, client, host),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Credentials {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request
+            .headers()
+            .get_one("Authorization")
+            .and_then(Credentials::parse)
+        {
+            Some(credentials) => Outcome::Success(credentials),
+            None => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_credentials() {
+        assert_eq!(
+            Credentials::parse("Basic dGVzdDp0ZXN0OnBhc3N3b3Jk"),
+            Some(Credentials::Basic {
+                user: "test".to_string(),
+                password: "test:password".to_string()
+            })
+        );
+        assert_eq!(
+            Credentials::parse("bearer abc123"),
+            Some(Credentials::Bearer("abc123".to_string()))
+        );
+        assert_eq!(Credentials::parse("Basic not-base64"), None);
+        assert_eq!(Credentials::parse("Bearer "), None);
+        assert_eq!(Credentials::parse("Digest abc"), None);
+    }
+}
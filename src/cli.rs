@@ -0,0 +1,387 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+//! Commands for managing the configuration from the command line
+
+use std::{error::Error, net::IpAddr, path::Path};
+
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{
+    client::{self, Client},
+    config::{
+        store::{
+            edit_file, find_user, generate_token, list_mut, position, set_offline, token_entry,
+            EditError,
+        },
+        AddressFamily, Config, MaintenanceConfig, Providers,
+    },
+    logging,
+    providers::{update_ipv4, update_ipv6},
+};
+
+pub const USAGE: &str = "\
+Usage: dyndns [command]
+
+Without a command, the server is started.
+
+Commands:
+    token list                          List the API tokens of all users
+    token add <user> <name> [options]   Create an API token and print it
+        --host <host>                   Restrict the token to a host, can be repeated
+        --family <ipv4|ipv6>            Restrict the token to an address family, can be repeated
+        --expires <timestamp>           Expire the token at an RFC 3339 timestamp, e.g. 2027-01-01T00:00:00Z
+    token revoke <user> <name>          Delete an API token
+    host offline <user> <host>          Point a host to its maintenance addresses and ignore its updates
+    host online <user> <host>           Accept updates of a host again
+    client [<config>]                   Detect the addresses of this machine and update them when they change,
+                                        configured in client.json by default
+    help                                Show this help
+";
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum Command {
+    Help,
+    TokenList,
+    TokenAdd {
+        user: String,
+        name: String,
+        hosts: Vec<String>,
+        families: Vec<AddressFamily>,
+        expires: Option<OffsetDateTime>,
+    },
+    TokenRevoke {
+        user: String,
+        name: String,
+    },
+    HostOffline {
+        user: String,
+        host: String,
+        offline: bool,
+    },
+    Client {
+        config: Option<String>,
+    },
+}
+
+fn parse_family(family: &str) -> Result<AddressFamily, String> {
+    match family {
+        "ipv4" => Ok(AddressFamily::Ipv4),
+        "ipv6" => Ok(AddressFamily::Ipv6),
+        _ => Err(format!("Invalid address family {family}")),
+    }
+}
+
+/// Parse the command line arguments without the name of the binary
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        ["help" | "--help" | "-h"] => Ok(Command::Help),
+        ["token", "list"] => Ok(Command::TokenList),
+        ["client"] => Ok(Command::Client { config: None }),
+        ["client", config] => Ok(Command::Client {
+            config: Some((*config).to_string()),
+        }),
+        ["token", "revoke", user, name] => Ok(Command::TokenRevoke {
+            user: (*user).to_string(),
+            name: (*name).to_string(),
+        }),
+        ["host", state @ ("offline" | "online"), user, host] => Ok(Command::HostOffline {
+            user: (*user).to_string(),
+            host: (*host).to_string(),
+            offline: *state == "offline",
+        }),
+        ["token", "add", user, name, options @ ..] => {
+            let mut hosts = Vec::new();
+            let mut families = Vec::new();
+            let mut expires = None;
+
+            let mut options = options.iter();
+            while let Some(option) = options.next() {
+                let value = options
+                    .next()
+                    .ok_or_else(|| format!("Missing value for {option}"))?;
+
+                match *option {
+                    "--host" => hosts.push((*value).to_string()),
+                    "--family" => families.push(parse_family(value)?),
+                    "--expires" => {
+                        expires = Some(
+                            OffsetDateTime::parse(value, &Rfc3339)
+                                .map_err(|e| format!("Invalid timestamp {value}: {e}"))?,
+                        );
+                    }
+                    _ => return Err(format!("Unknown option {option}")),
+                }
+            }
+
+            Ok(Command::TokenAdd {
+                user: (*user).to_string(),
+                name: (*name).to_string(),
+                hosts,
+                families,
+                expires,
+            })
+        }
+        _ => Err(format!("Invalid command\n\n{USAGE}")),
+    }
+}
+
+fn list_tokens(config: &Config) {
+    let now = OffsetDateTime::now_utc();
+
+    for user in &config.users {
+        for token in &user.tokens {
+            let hosts = if token.hosts.is_empty() {
+                "all hosts".to_string()
+            } else {
+                token.hosts.join(",")
+            };
+            let families = if token.families.is_empty() {
+                "ipv4,ipv6".to_string()
+            } else {
+                token
+                    .families
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            };
+            let expires = match token.expires {
+                Some(expires) if token.is_expired(now) => {
+                    format!("expired {}", expires.format(&Rfc3339).unwrap_or_default())
+                }
+                Some(expires) => {
+                    format!("expires {}", expires.format(&Rfc3339).unwrap_or_default())
+                }
+                None => "never expires".to_string(),
+            };
+
+            println!(
+                "{}\t{}\t{hosts}\t{families}\t{expires}",
+                user.name, token.name
+            );
+        }
+    }
+}
+
+/// Point the records of a host to its maintenance addresses
+fn set_maintenance_addresses(config: &Config, user: &str, host: &str) -> Result<(), String> {
+    let domain_config = config
+        .users
+        .iter()
+        .filter(|u| u.name == user)
+        .flat_map(|u| &u.domains)
+        .find(|d| d.host == host)
+        .ok_or_else(|| format!("Host {host} of user {user} not found"))?;
+    let addresses = domain_config
+        .maintenance
+        .as_ref()
+        .map(MaintenanceConfig::addresses)
+        .unwrap_or_default();
+
+    let providers = Providers::new();
+    let provider = providers.get(domain_config).map_err(|e| e.to_string())?;
+
+    for address in addresses {
+        let status = match address {
+            IpAddr::V4(ipv4) => update_ipv4(provider, &ipv4, domain_config, domain_config.ttl)?,
+            IpAddr::V6(ipv6) => update_ipv6(provider, &ipv6, domain_config, domain_config.ttl)?,
+        };
+        println!("{}", status.describe(&address));
+    }
+
+    Ok(())
+}
+
+/// Update the addresses of this machine until the process is stopped
+fn run_client(path: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let path = client::find_config(path).ok_or("Error finding client config: No config found")?;
+    let config = client::load_config(&path)?;
+    logging::init(&config.logging)?;
+
+    let mut client = Client::new(config, Providers::new())?;
+    tokio::task::block_in_place(|| client.run());
+
+    Ok(())
+}
+
+/// Run a command on the configuration file at `path`
+pub fn run(command: Command, path: &Path) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Help => print!("{USAGE}"),
+        Command::TokenList => list_tokens(&crate::load_config(path)?),
+        Command::TokenAdd {
+            user,
+            name,
+            hosts,
+            families,
+            expires,
+        } => {
+            let token = generate_token();
+
+            edit_file(path, |value| {
+                let tokens = list_mut(find_user(value, &user)?, "tokens")?;
+                if position(tokens, "name", &name).is_some() {
+                    return Err(EditError::Conflict(format!(
+                        "Token {name} of user {user} already exists"
+                    )));
+                }
+
+                tokens.push(token_entry(&name, &token, &hosts, &families, expires)?);
+
+                Ok(())
+            })?;
+
+            println!("{token}");
+            eprintln!("Restart dyndns to apply the change.");
+        }
+        Command::TokenRevoke { user, name } => {
+            edit_file(path, |value| {
+                let tokens = list_mut(find_user(value, &user)?, "tokens")?;
+                let index = position(tokens, "name", &name).ok_or_else(|| {
+                    EditError::NotFound(format!("Token {name} of user {user} not found"))
+                })?;
+                tokens.remove(index);
+
+                Ok(())
+            })?;
+
+            eprintln!("Revoked token {name} of user {user}. Restart dyndns to apply the change.");
+        }
+        Command::HostOffline {
+            user,
+            host,
+            offline,
+        } => {
+            let config = edit_file(path, |value| set_offline(value, &user, &host, offline))?;
+
+            if offline {
+                set_maintenance_addresses(&config, &user, &host)?;
+                eprintln!("Took {host} offline. Restart dyndns to apply the change.");
+            } else {
+                eprintln!("Brought {host} back online. Restart dyndns to apply the change.");
+            }
+        }
+        Command::Client { config } => run_client(config.as_deref())?,
+    }
+
+    Ok(())
+}
+
+/// Run the command given on the command line
+pub fn main(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let command = parse(args)?;
+    // These commands do not need the configuration of the server
+    if matches!(command, Command::Help | Command::Client { .. }) {
+        return run(command, Path::new(""));
+    }
+
+    let path = crate::find_config().ok_or("Error finding config: No config found")?;
+
+    run(command, &path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn args(args: &str) -> Vec<String> {
+        args.split_whitespace().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(parse(&args("token list")), Ok(Command::TokenList));
+        assert_eq!(
+            parse(&args(
+                "token add test camera --host camera.example.com --family ipv6 --expires 2027-01-01T00:00:00Z"
+            )),
+            Ok(Command::TokenAdd {
+                user: "test".to_string(),
+                name: "camera".to_string(),
+                hosts: vec!["camera.example.com".to_string()],
+                families: vec![AddressFamily::Ipv6],
+                expires: Some(datetime!(2027-01-01 00:00 UTC)),
+            })
+        );
+        assert_eq!(
+            parse(&args("token revoke test camera")),
+            Ok(Command::TokenRevoke {
+                user: "test".to_string(),
+                name: "camera".to_string(),
+            })
+        );
+        assert_eq!(
+            parse(&args("host offline test example.com")),
+            Ok(Command::HostOffline {
+                user: "test".to_string(),
+                host: "example.com".to_string(),
+                offline: true,
+            })
+        );
+        assert_eq!(
+            parse(&args("client /etc/dyndns/office.json")),
+            Ok(Command::Client {
+                config: Some("/etc/dyndns/office.json".to_string()),
+            })
+        );
+        assert!(parse(&args("host down test example.com")).is_err());
+        assert!(parse(&args("token add test camera --host")).is_err());
+        assert!(parse(&args("token add test camera --family ipx")).is_err());
+        assert!(parse(&args("token delete test camera")).is_err());
+    }
+
+    #[test]
+    fn add_and_revoke_token() {
+        let path = std::env::temp_dir().join(format!("dyndns-cli-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"{
+                "name": "test",
+                "password": "testpassword",
+                "domains": [{
+                    "provider": "MockProvider",
+                    "apitoken": "testapitoken",
+                    "host": "camera.example.com",
+                    "zone": {"name": "example.com", "id": "idexamplecom"}
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        run(
+            parse(&args("token add test camera --host camera.example.com")).unwrap(),
+            &path,
+        )
+        .unwrap();
+        let config = crate::load_config(&path).unwrap();
+        assert_eq!(config.users[0].tokens[0].name, "camera");
+        assert_eq!(config.users[0].tokens[0].token.len(), 64);
+        assert_eq!(config.users[0].tokens[0].hosts, vec!["camera.example.com"]);
+
+        // Hosts have to belong to the user
+        assert!(run(
+            parse(&args("token add test other --host other.example.com")).unwrap(),
+            &path
+        )
+        .is_err());
+        assert!(run(parse(&args("token add test camera")).unwrap(), &path).is_err());
+
+        run(parse(&args("token revoke test camera")).unwrap(), &path).unwrap();
+        assert!(crate::load_config(&path).unwrap().users[0]
+            .tokens
+            .is_empty());
+        assert!(run(parse(&args("token revoke test camera")).unwrap(), &path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,453 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+//! Client that detects the addresses of the machine it runs on and updates them when they change
+//!
+//! The addresses are sent to a dyndns server, or the records are updated directly through the providers.
+
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::BufReader,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use futures::executor::block_on;
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    address_policy::is_global,
+    config::{DomainConfig, Providers},
+    logging::LoggingConfig,
+    providers::{update_ipv4, update_ipv6},
+};
+
+fn default_interval() -> u64 {
+    300
+}
+
+/// How an address is detected
+#[derive(Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Detection {
+    /// Address the system uses for outgoing connections, if it is a global address
+    Local,
+    /// Address returned as plain text by a "what's my IP" service, e.g. `/ip` of a dyndns server
+    Url(String),
+}
+
+/// Where the addresses are sent to
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    /// JSON API of a dyndns server
+    Server(ServerTarget),
+    /// Records that are updated through their providers
+    Domains(Vec<DomainConfig>),
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ServerTarget {
+    /// Base URL of the server, e.g. `https://dyndns.example.com`
+    pub url: String,
+    pub user: String,
+    /// Password or API token of the user
+    pub password: String,
+    /// Host, comma-separated list of hosts or group that is updated
+    pub host: String,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ClientConfig {
+    /// Seconds between two checks of the addresses
+    #[serde(default = "default_interval")]
+    pub interval_secs: u64,
+    /// Detection of the IPv4 address, which is not updated if not set
+    pub ipv4: Option<Detection>,
+    /// Detection of the IPv6 address, which is not updated if not set
+    pub ipv6: Option<Detection>,
+    pub target: Target,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+}
+
+/// Find the configuration of the client, given on the command line or in one of the default places
+pub fn find_config(path: Option<&str>) -> Option<PathBuf> {
+    if let Some(path) = path {
+        return Some(PathBuf::from(path));
+    }
+
+    ["./client.json", "/etc/dyndns/client.json"]
+        .iter()
+        .map(PathBuf::from)
+        .find(|p| p.is_file())
+}
+
+pub fn load_config(path: &Path) -> Result<ClientConfig, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(path)?);
+
+    Ok(serde_json::from_reader(reader)?)
+}
+
+/// Address of the interface the system would send packets to the internet from
+///
+/// Connecting a UDP socket only selects the route and source address, no packet is sent.
+fn routed_address(ipv6: bool) -> Option<IpAddr> {
+    let (bind, target): (SocketAddr, SocketAddr) = if ipv6 {
+        (
+            (Ipv6Addr::UNSPECIFIED, 0).into(),
+            (Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1), 9).into(),
+        )
+    } else {
+        (
+            (Ipv4Addr::UNSPECIFIED, 0).into(),
+            (Ipv4Addr::new(192, 0, 2, 1), 9).into(),
+        )
+    };
+
+    let socket = UdpSocket::bind(bind).ok()?;
+    socket.connect(target).ok()?;
+
+    Some(socket.local_addr().ok()?.ip())
+}
+
+/// Flags of addresses that are temporary (privacy extensions), failed duplicate address detection,
+/// are deprecated or tentative, see `IFA_F_*` in the Linux headers
+const UNSTABLE_IPV6_FLAGS: u32 = 0x01 | 0x08 | 0x20 | 0x40;
+
+/// Global IPv6 addresses of the interfaces that do not change, from the content of `/proc/net/if_inet6`
+fn stable_ipv6_addresses(if_inet6: &str) -> Vec<Ipv6Addr> {
+    if_inet6
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let ip = Ipv6Addr::from(u128::from_str_radix(fields.first()?, 16).ok()?);
+            let flags = u32::from_str_radix(fields.get(4)?, 16).ok()?;
+
+            (flags & UNSTABLE_IPV6_FLAGS == 0 && is_global(ip.into())).then_some(ip)
+        })
+        .collect()
+}
+
+/// Global address of the machine for the given family
+///
+/// With privacy extensions, outgoing connections use temporary IPv6 addresses that expire within a day,
+/// so a stable address of the interfaces is taken instead where the system lists them.
+fn local_address(ipv6: bool) -> Option<IpAddr> {
+    let routed = routed_address(ipv6);
+
+    if ipv6 {
+        if let Ok(if_inet6) = fs::read_to_string("/proc/net/if_inet6") {
+            let stable = stable_ipv6_addresses(&if_inet6);
+
+            return routed
+                .filter(|ip| stable.iter().any(|s| IpAddr::V6(*s) == *ip))
+                .or(stable.first().map(|ip| IpAddr::V6(*ip)));
+        }
+    }
+
+    // Behind NAT, the local address cannot be reached from the internet
+    routed.filter(|ip| is_global(*ip))
+}
+
+/// Addresses that were detected, or sent last
+#[derive(PartialEq, Eq, Clone, Copy, Default, Debug)]
+pub struct Addresses {
+    pub ipv4: Option<Ipv4Addr>,
+    pub ipv6: Option<Ipv6Addr>,
+}
+
+pub struct Client {
+    config: ClientConfig,
+    providers: Providers,
+    http: reqwest::Client,
+    /// HTTP clients whose connections go out over IPv4 and IPv6 respectively, for detecting the addresses
+    http_ipv4: reqwest::Client,
+    http_ipv6: reqwest::Client,
+    /// Addresses that were sent successfully
+    last: Addresses,
+}
+
+impl Client {
+    pub fn new(config: ClientConfig, providers: Providers) -> Result<Client, Box<dyn Error>> {
+        let http = |bind: Option<IpAddr>| {
+            reqwest::Client::builder()
+                .local_address(bind)
+                .timeout(Duration::from_secs(30))
+                .build()
+        };
+
+        Ok(Client {
+            config,
+            providers,
+            http: http(None)?,
+            http_ipv4: http(Some(Ipv4Addr::UNSPECIFIED.into()))?,
+            http_ipv6: http(Some(Ipv6Addr::UNSPECIFIED.into()))?,
+            last: Addresses::default(),
+        })
+    }
+
+    fn detect(&self, detection: &Detection, ipv6: bool) -> Result<IpAddr, Box<dyn Error>> {
+        let family = if ipv6 { "IPv6" } else { "IPv4" };
+
+        let ip = match detection {
+            Detection::Local => local_address(ipv6)
+                .ok_or_else(|| format!("No global {family} address on the local interfaces"))?,
+            Detection::Url(url) => {
+                let http = if ipv6 {
+                    &self.http_ipv6
+                } else {
+                    &self.http_ipv4
+                };
+                let body = block_on(async {
+                    http.get(url).send().await?.error_for_status()?.text().await
+                })?;
+
+                body.trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid address from {url}: {}", body.trim()))?
+            }
+        };
+
+        if ip.is_ipv6() == ipv6 {
+            Ok(ip)
+        } else {
+            Err(format!("Detected {ip} instead of an {family} address").into())
+        }
+    }
+
+    fn send(&self, addresses: Addresses) -> Result<(), Box<dyn Error>> {
+        match &self.config.target {
+            Target::Server(server) => {
+                let url = format!("{}/api/v1/update", server.url.trim_end_matches('/'));
+
+                let response = block_on(async {
+                    let response = self
+                        .http
+                        .post(&url)
+                        .basic_auth(&server.user, Some(&server.password))
+                        .json(&json!({
+                            "host": server.host,
+                            "ipv4": addresses.ipv4,
+                            "ipv6": addresses.ipv6,
+                        }))
+                        .send()
+                        .await?;
+                    let status = response.status();
+                    response.text().await.map(|body| (status, body))
+                });
+                let (status, body) = response?;
+
+                if !status.is_success() {
+                    return Err(format!("Server responded with {status}: {body}").into());
+                }
+            }
+            Target::Domains(domains) => {
+                for domain_config in domains {
+                    let provider = self.providers.get(domain_config)?;
+
+                    if let Some(ipv4) = &addresses.ipv4 {
+                        let status = update_ipv4(provider, ipv4, domain_config, domain_config.ttl)?;
+                        info!(
+                            "{}: {}",
+                            domain_config.host,
+                            status.describe(&(*ipv4).into())
+                        );
+                    }
+                    if let Some(ipv6) = &addresses.ipv6 {
+                        let status = update_ipv6(provider, ipv6, domain_config, domain_config.ttl)?;
+                        info!(
+                            "{}: {}",
+                            domain_config.host,
+                            status.describe(&(*ipv6).into())
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Send an address if it changed since the address of its family was sent last, returning whether it was sent
+    ///
+    /// Each family is sent on its own, so losing the address of one family does not hold back the other.
+    pub fn update(&mut self, ip: IpAddr) -> Result<bool, Box<dyn Error>> {
+        let addresses = match ip {
+            IpAddr::V4(ipv4) if self.last.ipv4 != Some(ipv4) => Addresses {
+                ipv4: Some(ipv4),
+                ipv6: None,
+            },
+            IpAddr::V6(ipv6) if self.last.ipv6 != Some(ipv6) => Addresses {
+                ipv4: None,
+                ipv6: Some(ipv6),
+            },
+            _ => return Ok(false),
+        };
+
+        self.send(addresses)?;
+        self.last.ipv4 = addresses.ipv4.or(self.last.ipv4);
+        self.last.ipv6 = addresses.ipv6.or(self.last.ipv6);
+
+        Ok(true)
+    }
+
+    /// Detect and send the addresses on every interval, until the process is stopped
+    pub fn run(&mut self) {
+        loop {
+            let families = [
+                (self.config.ipv4.clone(), false),
+                (self.config.ipv6.clone(), true),
+            ];
+
+            for (detection, ipv6) in families {
+                let Some(detection) = detection else {
+                    continue;
+                };
+                let family = if ipv6 { "IPv6" } else { "IPv4" };
+
+                match self
+                    .detect(&detection, ipv6)
+                    .and_then(|ip| Ok((ip, self.update(ip)?)))
+                {
+                    Ok((ip, true)) => info!("Sent {family} address {ip}"),
+                    Ok((_, false)) => {}
+                    // Failed updates are retried on the next interval
+                    Err(error) => warn!("Error updating the {family} address: {error}"),
+                }
+            }
+
+            std::thread::sleep(Duration::from_secs(self.config.interval_secs));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        providers::{MockProvider, UpdateStatus},
+        server::tests::test_config,
+    };
+
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let config: ClientConfig = serde_json::from_str(
+            r#"{
+                "ipv4": {"url": "https://dyndns.example.com/ip4"},
+                "ipv6": "local",
+                "target": {
+                    "server": {
+                        "url": "https://dyndns.example.com",
+                        "user": "test",
+                        "password": "testapitoken",
+                        "host": "home"
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.interval_secs, 300);
+        assert_eq!(
+            config.ipv4,
+            Some(Detection::Url("https://dyndns.example.com/ip4".to_string()))
+        );
+        assert_eq!(config.ipv6, Some(Detection::Local));
+        assert!(matches!(config.target, Target::Server(s) if s.host == "home"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn update_only_changed_addresses() {
+        let mut mock = MockProvider::default();
+        mock.expect_update_ip()
+            .withf(|d, ip, _| d.host == "example.com" && ip.to_string() == "192.0.2.1")
+            .once()
+            .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
+        mock.expect_update_ip()
+            .withf(|d, ip, _| d.host == "example.com" && ip.to_string() == "192.0.2.2")
+            .once()
+            .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
+
+        let config = ClientConfig {
+            interval_secs: 60,
+            ipv4: Some(Detection::Local),
+            ipv6: None,
+            target: Target::Domains(vec![test_config().users[0].domains[0].clone()]),
+            logging: LoggingConfig::default(),
+        };
+        let providers = Providers {
+            hetzner_provider: None,
+            rfc2136_provider: None,
+            mock_provider: Some(mock),
+        };
+        let mut client = Client::new(config, providers).unwrap();
+
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+        assert!(client.update(ip("192.0.2.1")).unwrap());
+        assert!(!client.update(ip("192.0.2.1")).unwrap());
+        assert!(client.update(ip("192.0.2.2")).unwrap());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn update_families_separately() {
+        let mut mock = MockProvider::default();
+        mock.expect_update_ip()
+            .withf(|_, ip, _| ip.is_ipv4())
+            .times(2)
+            .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
+        mock.expect_update_ip()
+            .withf(|_, ip, _| ip.to_string() == "2001:db8::1")
+            .once()
+            .returning(|_, _, _| Err("Provider unavailable".into()));
+        mock.expect_update_ip()
+            .withf(|_, ip, _| ip.to_string() == "2001:db8::2")
+            .once()
+            .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
+
+        let config = ClientConfig {
+            interval_secs: 60,
+            ipv4: Some(Detection::Local),
+            ipv6: Some(Detection::Local),
+            target: Target::Domains(vec![test_config().users[0].domains[0].clone()]),
+            logging: LoggingConfig::default(),
+        };
+        let providers = Providers {
+            hetzner_provider: None,
+            rfc2136_provider: None,
+            mock_provider: Some(mock),
+        };
+        let mut client = Client::new(config, providers).unwrap();
+
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+        assert!(client.update(ip("2001:db8::1")).is_err());
+        assert!(client.update(ip("192.0.2.1")).unwrap());
+        assert!(client.update(ip("2001:db8::2")).unwrap());
+        assert!(!client.update(ip("192.0.2.1")).unwrap());
+        assert!(client.update(ip("192.0.2.2")).unwrap());
+    }
+
+    #[test]
+    fn prefer_stable_ipv6_addresses() {
+        let if_inet6 = "\
+2a0104f8000000000000000000000001 02 40 00 00 eth0
+2a0104f80000000012345678abcdef01 02 40 00 01 eth0
+2a0104f8000000000000000000000002 02 40 00 20 eth0
+fe800000000000000000000000000001 02 40 20 80 eth0
+00000000000000000000000000000001 01 80 10 80 lo
+";
+
+        assert_eq!(
+            stable_ipv6_addresses(if_inet6),
+            vec!["2a01:4f8::1".parse::<Ipv6Addr>().unwrap()]
+        );
+    }
+}
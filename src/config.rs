@@ -2,13 +2,39 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
+pub mod store;
+
+use std::{
+    error::Error,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
 use serde::Deserialize;
+use time::OffsetDateTime;
 
-use crate::providers::{hetzner::HetznerProvider, MockProvider};
+use crate::{
+    acme_dns::AcmeDnsConfig,
+    address_policy::AddressPolicy,
+    history::HistoryConfig,
+    keyed::KeyedConfig,
+    leases::LeaseConfig,
+    logging::LoggingConfig,
+    notifications::NotificationConfig,
+    providers::{
+        hetzner::HetznerProvider,
+        rfc2136::{Rfc2136Config, Rfc2136Provider},
+        MockProvider, Provider, ProviderNotConfiguredError,
+    },
+    reconcile::ReconcileConfig,
+    tls::TlsConfig,
+    verification::VerificationConfig,
+};
 
-#[derive(PartialEq, Eq, Deserialize, strum_macros::Display, Clone, Copy)]
+#[derive(PartialEq, Eq, Deserialize, strum_macros::Display, Clone, Copy, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum ProviderType {
     HetznerProvider,
+    Rfc2136Provider,
     MockProvider,
 }
 
@@ -21,9 +47,107 @@ pub struct Zone {
 #[derive(Deserialize, Clone)]
 pub struct DomainConfig {
     pub provider: ProviderType,
+    /// API token of the provider, not needed for RFC 2136
+    #[serde(default)]
     pub apitoken: String,
     pub host: String,
     pub zone: Zone,
+    /// TTL in seconds that every update sets on the records, even if the address did not change
+    pub ttl: Option<u64>,
+    /// Keep one address per client in the records instead of a single one
+    pub keyed: Option<KeyedConfig>,
+    /// Remove the records or set them to a fallback if the host stops sending updates
+    pub lease: Option<LeaseConfig>,
+    /// Addresses the records point to while the host is offline
+    pub maintenance: Option<MaintenanceConfig>,
+    /// Updates are ignored until the host is brought back online
+    #[serde(default)]
+    pub offline: bool,
+    /// Addresses accepted in updates of this host, replaces the global policy
+    pub address_policy: Option<AddressPolicy>,
+    /// Nameserver of the zone for the Rfc2136Provider
+    pub rfc2136: Option<Rfc2136Config>,
+    /// Record in an internal zone that is set to the LAN address of the host
+    pub lan: Option<Box<DomainConfig>>,
+}
+
+/// Addresses of a host in maintenance mode, e.g. of a status page
+#[derive(Deserialize, Clone)]
+pub struct MaintenanceConfig {
+    pub ipv4: Option<Ipv4Addr>,
+    pub ipv6: Option<Ipv6Addr>,
+}
+
+impl MaintenanceConfig {
+    #[must_use]
+    pub fn addresses(&self) -> Vec<IpAddr> {
+        self.ipv4
+            .map(IpAddr::V4)
+            .into_iter()
+            .chain(self.ipv6.map(IpAddr::V6))
+            .collect()
+    }
+}
+
+/// Name that stands for several hosts of a user, which are all updated together
+#[derive(Deserialize, Clone)]
+pub struct HostGroup {
+    pub name: String,
+    /// Hosts of the group, each of them has to be configured in the domains of the user
+    pub hosts: Vec<String>,
+}
+
+#[derive(PartialEq, Eq, Deserialize, Clone, Copy, Debug, strum_macros::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+}
+
+impl AddressFamily {
+    #[must_use]
+    pub fn of(ip: &IpAddr) -> AddressFamily {
+        if ip.is_ipv4() {
+            AddressFamily::Ipv4
+        } else {
+            AddressFamily::Ipv6
+        }
+    }
+}
+
+/// Secret that authenticates a user instead of the password, optionally restricted to some hosts
+#[derive(Deserialize, Clone)]
+pub struct ApiToken {
+    /// Name for identifying the token, e.g. the device that uses it
+    pub name: String,
+    pub token: String,
+    /// Hosts that may be updated with the token, all hosts of the user if empty
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// Address families that may be updated with the token, both if empty
+    #[serde(default)]
+    pub families: Vec<AddressFamily>,
+    /// Time after which the token is not accepted anymore
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub expires: Option<OffsetDateTime>,
+}
+
+impl ApiToken {
+    #[must_use]
+    pub fn allows_host(&self, host: &str) -> bool {
+        self.hosts.is_empty() || self.hosts.iter().any(|h| h == host)
+    }
+
+    #[must_use]
+    pub fn allows_family(&self, family: AddressFamily) -> bool {
+        self.families.is_empty() || self.families.contains(&family)
+    }
+
+    #[must_use]
+    pub fn is_expired(&self, now: OffsetDateTime) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
 }
 
 #[derive(Deserialize, Clone)]
@@ -31,18 +155,200 @@ pub struct User {
     pub name: String,
     pub password: String,
     pub domains: Vec<DomainConfig>,
+    #[serde(default)]
+    pub groups: Vec<HostGroup>,
+    #[serde(default)]
+    pub tokens: Vec<ApiToken>,
+}
+
+impl User {
+    /// Replace names of groups by their hosts, keeping the order
+    #[must_use]
+    pub fn expand_groups<'a>(&'a self, hosts: &[&'a str]) -> Vec<&'a str> {
+        hosts
+            .iter()
+            .flat_map(|host| {
+                let is_domain = self.domains.iter().any(|d| d.host == *host);
+
+                match self.groups.iter().find(|g| g.name == *host) {
+                    Some(group) if !is_domain => group.hosts.iter().map(String::as_str).collect(),
+                    _ => vec![*host],
+                }
+            })
+            .collect()
+    }
 }
 
+/// Access to the admin API
 #[derive(Deserialize, Clone)]
+pub struct AdminConfig {
+    /// Bearer token that authenticates the admin
+    pub token: String,
+    /// Address of a separate listener for the admin API, 127.0.0.1 by default
+    pub address: Option<IpAddr>,
+    /// Port of a separate listener for the admin API, which is served by the main listener if not set
+    pub port: Option<u16>,
+}
+
+#[derive(Deserialize, Clone, Default)]
 pub struct Config {
     pub users: Vec<User>,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub notifications: NotificationConfig,
+    /// Check that the nameservers serve new addresses after updates
+    pub verification: Option<VerificationConfig>,
+    /// Manage users, hosts and tokens while the server is running
+    pub admin: Option<AdminConfig>,
+    #[serde(default)]
+    pub history: HistoryConfig,
+    /// Serve HTTPS instead of HTTP
+    pub tls: Option<TlsConfig>,
+    /// API compatible with acme-dns for answering DNS-01 challenges
+    pub acme_dns: Option<AcmeDnsConfig>,
+    /// Addresses accepted in updates, only global unicast addresses by default
+    #[serde(default)]
+    pub address_policy: AddressPolicy,
+    /// Set records again that were changed outside of dyndns
+    pub reconcile: Option<ReconcileConfig>,
+}
+
+impl Config {
+    /// Check references between parts of the configuration
+    pub fn validate(&self) -> Result<(), String> {
+        for user in &self.users {
+            if let Some(domain) = user.domains.iter().find(|d| {
+                d.offline
+                    && d.maintenance
+                        .as_ref()
+                        .is_none_or(|m| m.addresses().is_empty())
+            }) {
+                return Err(format!(
+                    "Host {} is offline, but has no maintenance address",
+                    domain.host
+                ));
+            }
+
+            for domain in user
+                .domains
+                .iter()
+                .flat_map(|d| std::iter::once(d).chain(d.lan.as_deref()))
+            {
+                if domain.provider == ProviderType::Rfc2136Provider && domain.rfc2136.is_none() {
+                    return Err(format!(
+                        "Host {} uses the Rfc2136Provider, but has no rfc2136 server",
+                        domain.host
+                    ));
+                }
+            }
+
+            for group in &user.groups {
+                if let Some(host) = group
+                    .hosts
+                    .iter()
+                    .find(|h| !user.domains.iter().any(|d| &d.host == *h))
+                {
+                    return Err(format!(
+                        "Host {host} of group {} is not a domain of user {}",
+                        group.name, user.name
+                    ));
+                }
+            }
+
+            for token in &user.tokens {
+                if let Some(host) = token
+                    .hosts
+                    .iter()
+                    .find(|h| !user.domains.iter().any(|d| &d.host == *h))
+                {
+                    return Err(format!(
+                        "Host {host} of token {} is not a domain of user {}",
+                        token.name, user.name
+                    ));
+                }
+            }
+        }
+
+        // Bearer tokens are sent without a username, so they have to identify the user
+        let mut tokens = self
+            .users
+            .iter()
+            .flat_map(|u| u.tokens.iter().map(|t| t.token.as_str()))
+            .collect::<Vec<_>>();
+        tokens.sort_unstable();
+        if tokens.windows(2).any(|w| w[0] == w[1]) {
+            return Err("API tokens have to be unique".to_string());
+        }
+
+        if let Some(acme) = self.tls.as_ref().and_then(|tls| tls.acme.as_ref()) {
+            if acme.domains.is_empty() {
+                return Err("ACME needs at least one domain".to_string());
+            }
+            if let Some(domain) = acme.domains.iter().find(|d| self.zone_domain(d).is_none()) {
+                return Err(format!(
+                    "Domain {domain} for ACME is not in the zone of any configured domain"
+                ));
+            }
+        }
+
+        if let Some(acme_dns) = &self.acme_dns {
+            acme_dns.validate(self)?;
+        }
+
+        Ok(())
+    }
+
+    /// Configured domain whose zone contains `name`, which gives access to the records of that zone
+    ///
+    /// Wildcards are allowed, and if zones are nested, the innermost one is used.
+    #[must_use]
+    pub fn zone_domain(&self, name: &str) -> Option<&DomainConfig> {
+        let name = name.strip_prefix("*.").unwrap_or(name);
+
+        self.users
+            .iter()
+            .flat_map(|u| &u.domains)
+            .filter(|d| in_zone(name, &d.zone.name))
+            .max_by_key(|d| d.zone.name.len())
+    }
+}
+
+/// Whether `name` is `zone` itself or one of its subdomains
+#[must_use]
+pub fn in_zone(name: &str, zone: &str) -> bool {
+    name == zone || name.strip_suffix(zone).is_some_and(|s| s.ends_with('.'))
 }
 
 pub struct Providers {
     pub hetzner_provider: Option<HetznerProvider>,
+    pub rfc2136_provider: Option<Rfc2136Provider>,
     pub mock_provider: Option<MockProvider>,
 }
 
+impl Providers {
+    /// Providers for talking to the actual DNS APIs
+    #[must_use]
+    pub fn new() -> Providers {
+        Providers {
+            hetzner_provider: Some(HetznerProvider::new()),
+            rfc2136_provider: Some(Rfc2136Provider::new()),
+            mock_provider: None,
+        }
+    }
+
+    /// Provider that manages the records of a domain
+    pub fn get(&self, domain_config: &DomainConfig) -> Result<&dyn Provider, Box<dyn Error>> {
+        let provider: Option<&dyn Provider> = match domain_config.provider {
+            ProviderType::HetznerProvider => self.hetzner_provider.as_ref().map(|p| p as _),
+            ProviderType::Rfc2136Provider => self.rfc2136_provider.as_ref().map(|p| p as _),
+            ProviderType::MockProvider => self.mock_provider.as_ref().map(|p| p as _),
+        };
+
+        provider.ok_or_else(|| Box::new(ProviderNotConfiguredError(domain_config.provider)) as _)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -67,29 +373,189 @@ mod tests {
 
         assert_eq!(u.name, "testtest");
         assert_eq!(u.password, "testpassword");
+        assert!(u.groups.is_empty());
     }
 
-    // #[test]
-    // fn parse_config() {
-    //     let data = r#"[{
-    //         "name": "testtest",
-    //         "password": "testpassword",
-    //         "domains": [{
-    //             "provider": "HetznerProvider",
-    //             "apitoken": "testapitoken",
-    //             "host": "test.example.com",
-    //             "zone": {
-    //                 "name": "example.com",
-    //                 "id": "idexamplecom"
-    //             }
-    //         }]
-    //     }]"#;
-
-    //     let u: Config = serde_json::from_str(data).unwrap();
-
-    //     assert_eq!(u.users.len(), 1);
-
-    //     assert_eq!(u.users[0].name, "testtest");
-    //     assert_eq!(u.users[0].password, "testpassword");
-    // }
+    #[test]
+    fn expand_groups() {
+        let data = r#"{
+            "name": "testtest",
+            "password": "testpassword",
+            "domains": [{
+                "provider": "HetznerProvider",
+                "apitoken": "testapitoken",
+                "host": "home.example.com",
+                "zone": {
+                    "name": "example.com",
+                    "id": "idexamplecom"
+                }
+            }, {
+                "provider": "MockProvider",
+                "apitoken": "testapitoken",
+                "host": "example.net",
+                "zone": {
+                    "name": "example.net",
+                    "id": "idexamplenet"
+                }
+            }],
+            "groups": [{
+                "name": "home",
+                "hosts": ["home.example.com", "example.net"]
+            }]
+        }"#;
+
+        let u: User = serde_json::from_str(data).unwrap();
+
+        assert_eq!(
+            u.expand_groups(&["example.net", "home", "other"]),
+            vec!["example.net", "home.example.com", "example.net", "other"]
+        );
+
+        let mut config = Config {
+            users: vec![u],
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        config.users[0].groups[0]
+            .hosts
+            .push("vpn.example.org".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn parse_token() {
+        let data = r#"{
+            "name": "camera",
+            "token": "secret",
+            "hosts": ["camera.example.com"],
+            "families": ["ipv6"],
+            "expires": "2026-01-01T00:00:00Z"
+        }"#;
+
+        let t: ApiToken = serde_json::from_str(data).unwrap();
+
+        assert!(t.allows_host("camera.example.com"));
+        assert!(!t.allows_host("example.com"));
+        assert!(t.allows_family(AddressFamily::Ipv6));
+        assert!(!t.allows_family(AddressFamily::Ipv4));
+        assert!(!t.is_expired(time::macros::datetime!(2025-12-31 23:59 UTC)));
+        assert!(t.is_expired(time::macros::datetime!(2026-01-01 00:00 UTC)));
+
+        let t: ApiToken = serde_json::from_str(r#"{"name": "all", "token": "secret"}"#).unwrap();
+
+        assert!(t.allows_host("example.com"));
+        assert!(t.allows_family(AddressFamily::Ipv4));
+        assert!(!t.is_expired(OffsetDateTime::now_utc()));
+    }
+
+    #[test]
+    fn parse_config() {
+        let data = r#"{
+            "users": [{
+                "name": "testtest",
+                "password": "testpassword",
+                "domains": [{
+                    "provider": "HetznerProvider",
+                    "apitoken": "testapitoken",
+                    "host": "test.example.com",
+                    "zone": {
+                        "name": "example.com",
+                        "id": "idexamplecom"
+                    }
+                }]
+            }],
+            "logging": {
+                "format": "json",
+                "level": "info"
+            }
+        }"#;
+
+        let u: Config = serde_json::from_str(data).unwrap();
+
+        assert_eq!(u.users.len(), 1);
+
+        assert_eq!(u.users[0].name, "testtest");
+        assert_eq!(u.users[0].password, "testpassword");
+
+        assert!(u.logging.format == crate::logging::LogFormat::Json);
+        assert_eq!(u.logging.level, log::LevelFilter::Info);
+    }
+
+    #[test]
+    fn parse_lan_record() {
+        let data = r#"{
+            "name": "testtest",
+            "password": "testpassword",
+            "domains": [{
+                "provider": "HetznerProvider",
+                "apitoken": "testapitoken",
+                "host": "nas.example.com",
+                "zone": {"name": "example.com", "id": "idexamplecom"},
+                "lan": {
+                    "provider": "Rfc2136Provider",
+                    "host": "nas.example.com",
+                    "zone": {"name": "example.com", "id": ""},
+                    "rfc2136": {
+                        "server": "192.168.1.2:53",
+                        "key": {"name": "dyndns", "secret": "dGVzdHNlY3JldA=="}
+                    }
+                }
+            }]
+        }"#;
+
+        let mut config = Config {
+            users: vec![serde_json::from_str(data).unwrap()],
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        let lan = config.users[0].domains[0].lan.as_mut().unwrap();
+        assert!(lan.provider == ProviderType::Rfc2136Provider);
+        lan.rfc2136 = None;
+        assert_eq!(
+            config.validate(),
+            Err(
+                "Host nas.example.com uses the Rfc2136Provider, but has no rfc2136 server"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn find_zone_domain() {
+        let data = r#"{
+            "name": "testtest",
+            "password": "testpassword",
+            "domains": [{
+                "provider": "HetznerProvider",
+                "apitoken": "testapitoken",
+                "host": "home.example.com",
+                "zone": {
+                    "name": "example.com",
+                    "id": "idexamplecom"
+                }
+            }, {
+                "provider": "MockProvider",
+                "apitoken": "testapitoken",
+                "host": "vpn.dev.example.com",
+                "zone": {
+                    "name": "dev.example.com",
+                    "id": "iddevexamplecom"
+                }
+            }]
+        }"#;
+
+        let config = Config {
+            users: vec![serde_json::from_str(data).unwrap()],
+            ..Default::default()
+        };
+
+        let zone = |name| config.zone_domain(name).map(|d| d.zone.id.as_str());
+        assert_eq!(zone("example.com"), Some("idexamplecom"));
+        assert_eq!(zone("dyndns.example.com"), Some("idexamplecom"));
+        assert_eq!(zone("*.dev.example.com"), Some("iddevexamplecom"));
+        assert_eq!(zone("badexample.com"), None);
+        assert_eq!(zone("example.org"), None);
+    }
 }
@@ -0,0 +1,378 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+//! Configuration that can be changed while the server is running
+
+use std::{
+    error::Error,
+    fmt::Display,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+};
+
+use serde_json::{json, Value};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use super::{AddressFamily, Config};
+
+/// Reason why a change of the configuration was rejected
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum EditError {
+    NotFound(String),
+    Conflict(String),
+    Invalid(String),
+    /// The configuration file could not be read or written
+    Storage(String),
+}
+
+impl Error for EditError {}
+
+impl Display for EditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EditError::NotFound(message)
+            | EditError::Conflict(message)
+            | EditError::Invalid(message) => write!(f, "{message}"),
+            EditError::Storage(message) => write!(f, "Error accessing configuration: {message}"),
+        }
+    }
+}
+
+/// List of users in a configuration file
+///
+/// Files of earlier versions, which contain a single user, are converted to a list of users.
+pub fn users_mut(value: &mut Value) -> Result<&mut Vec<Value>, EditError> {
+    if value.get("users").is_none() {
+        *value = json!({ "users": [value.take()] });
+    }
+
+    value["users"]
+        .as_array_mut()
+        .ok_or_else(|| EditError::Invalid("Invalid users".to_string()))
+}
+
+/// Find a user in a configuration file
+pub fn find_user<'a>(value: &'a mut Value, user: &str) -> Result<&'a mut Value, EditError> {
+    users_mut(value)?
+        .iter_mut()
+        .find(|u| u.get("name").and_then(Value::as_str) == Some(user))
+        .ok_or_else(|| EditError::NotFound(format!("User {user} not found")))
+}
+
+/// List in a section of a configuration file, e.g. the domains or tokens of a user, which is created if it is missing
+pub fn list_mut<'a>(section: &'a mut Value, key: &str) -> Result<&'a mut Vec<Value>, EditError> {
+    section
+        .as_object_mut()
+        .ok_or_else(|| EditError::Invalid(format!("Invalid section for {key}")))?
+        .entry(key)
+        .or_insert_with(|| json!([]))
+        .as_array_mut()
+        .ok_or_else(|| EditError::Invalid(format!("Invalid {key}")))
+}
+
+/// Take a host of a user offline or bring it back online
+pub fn set_offline(
+    value: &mut Value,
+    user: &str,
+    host: &str,
+    offline: bool,
+) -> Result<(), EditError> {
+    let domains = list_mut(find_user(value, user)?, "domains")?;
+    let domain = position(domains, "host", host)
+        .and_then(|index| domains[index].as_object_mut())
+        .ok_or_else(|| EditError::NotFound(format!("Host {host} of user {user} not found")))?;
+
+    if offline {
+        domain.insert("offline".to_string(), json!(true));
+    } else {
+        domain.remove("offline");
+    }
+
+    Ok(())
+}
+
+/// Position of the entry in `list` whose `key` is `name`
+pub fn position(list: &[Value], key: &str, name: &str) -> Option<usize> {
+    list.iter()
+        .position(|entry| entry.get(key).and_then(Value::as_str) == Some(name))
+}
+
+/// Entry of an API token in a configuration file
+pub fn token_entry(
+    name: &str,
+    token: &str,
+    hosts: &[String],
+    families: &[AddressFamily],
+    expires: Option<OffsetDateTime>,
+) -> Result<Value, EditError> {
+    let mut entry = json!({ "name": name, "token": token });
+
+    if !hosts.is_empty() {
+        entry["hosts"] = json!(hosts);
+    }
+    if !families.is_empty() {
+        entry["families"] = json!(families.iter().map(ToString::to_string).collect::<Vec<_>>());
+    }
+    if let Some(expires) = expires {
+        entry["expires"] = json!(expires
+            .format(&Rfc3339)
+            .map_err(|e| EditError::Invalid(e.to_string()))?);
+    }
+
+    Ok(entry)
+}
+
+/// Generate a random token of 32 bytes in hex
+#[must_use]
+pub fn generate_token() -> String {
+    use rand::Rng;
+
+    rand::rng()
+        .random::<[u8; 32]>()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn storage_error(error: impl Display) -> EditError {
+    EditError::Storage(error.to_string())
+}
+
+/// Apply a change to the content of a configuration file and check the result
+fn apply(
+    value: &mut Value,
+    edit: impl FnOnce(&mut Value) -> Result<(), EditError>,
+) -> Result<Config, EditError> {
+    edit(value)?;
+
+    crate::parse_config(value.clone()).map_err(|e| EditError::Invalid(e.to_string()))
+}
+
+/// Apply a change to a configuration file, which is only written if the result is valid
+pub fn edit_file(
+    path: &Path,
+    edit: impl FnOnce(&mut Value) -> Result<(), EditError>,
+) -> Result<Config, EditError> {
+    let mut value: Value = serde_json::from_str(&fs::read_to_string(path).map_err(storage_error)?)
+        .map_err(storage_error)?;
+
+    let config = apply(&mut value, edit)?;
+
+    let content = serde_json::to_string_pretty(&value).map_err(storage_error)? + "\n";
+    replace_file(path, &content).map_err(storage_error)?;
+
+    Ok(config)
+}
+
+/// Replace the content of a file without ever leaving it truncated
+///
+/// The content is written to a temporary file next to it with the same permissions,
+/// which is then renamed over the original. Files that do not exist yet are created private.
+pub fn replace_file(path: &Path, content: &str) -> std::io::Result<()> {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".tmp");
+    let temp_path = path.with_file_name(file_name);
+
+    // The configuration contains passwords, so the file is private unless the original had other permissions
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let result = (|| {
+        let mut file = options.open(&temp_path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        match fs::metadata(path) {
+            Ok(metadata) => file.set_permissions(metadata.permissions())?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+
+        fs::rename(&temp_path, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    result
+}
+
+struct Inner {
+    current: RwLock<Arc<Config>>,
+    /// File the configuration is written back to
+    path: Option<PathBuf>,
+    /// Content of the configuration if there is no file, only used in tests
+    value: Mutex<Option<Value>>,
+}
+
+/// Configuration shared by all requests, which is replaced as a whole when it is changed
+#[derive(Clone)]
+pub struct ConfigStore {
+    inner: Arc<Inner>,
+}
+
+impl ConfigStore {
+    fn with(config: Config, path: Option<PathBuf>, value: Option<Value>) -> Self {
+        ConfigStore {
+            inner: Arc::new(Inner {
+                current: RwLock::new(Arc::new(config)),
+                path,
+                value: Mutex::new(value),
+            }),
+        }
+    }
+
+    /// Configuration that is written back to the file at `path` when it is changed
+    #[must_use]
+    pub fn new(config: Config, path: PathBuf) -> Self {
+        Self::with(config, Some(path), None)
+    }
+
+    /// Configuration that cannot be changed
+    #[cfg(test)]
+    #[must_use]
+    pub fn fixed(config: Config) -> Self {
+        Self::with(config, None, None)
+    }
+
+    /// Configuration that is only changed in memory
+    #[cfg(test)]
+    pub fn in_memory(value: Value) -> Result<Self, Box<dyn Error>> {
+        let config = crate::parse_config(value.clone())?;
+
+        Ok(Self::with(config, None, Some(value)))
+    }
+
+    /// Current configuration, which stays unchanged for the holder of the returned value
+    #[must_use]
+    pub fn get(&self) -> Arc<Config> {
+        self.inner.current.read().unwrap().clone()
+    }
+
+    /// Change the configuration, write it back and apply it to further requests
+    ///
+    /// The file is read again before the change, so changes made to it in the meantime are kept and applied as well.
+    pub fn edit(
+        &self,
+        edit: impl FnOnce(&mut Value) -> Result<(), EditError>,
+    ) -> Result<(), EditError> {
+        // Holding the lock for the content makes concurrent changes wait for each other
+        let mut value = self.inner.value.lock().unwrap();
+
+        let config = match (&self.inner.path, value.as_mut()) {
+            (Some(path), _) => edit_file(path, edit)?,
+            (None, Some(value)) => {
+                let mut changed = value.clone();
+                let config = apply(&mut changed, edit)?;
+                *value = changed;
+                config
+            }
+            (None, None) => {
+                return Err(EditError::Invalid(
+                    "The configuration cannot be changed".to_string(),
+                ))
+            }
+        };
+
+        *self.inner.current.write().unwrap() = Arc::new(config);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(name: &str) -> Value {
+        json!({
+            "name": name,
+            "password": "testpassword",
+            "domains": [{
+                "provider": "MockProvider",
+                "apitoken": "testapitoken",
+                "host": format!("{name}.example.com"),
+                "zone": {"name": "example.com", "id": "idexamplecom"}
+            }]
+        })
+    }
+
+    #[test]
+    fn convert_single_user() {
+        let mut value = user("test");
+
+        users_mut(&mut value).unwrap().push(user("other"));
+
+        assert_eq!(value, json!({ "users": [user("test"), user("other")] }));
+    }
+
+    #[test]
+    fn apply_valid_changes() {
+        let store = ConfigStore::in_memory(user("test")).unwrap();
+        let before = store.get();
+
+        store
+            .edit(|value| {
+                users_mut(value)?.push(user("other"));
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(before.users.len(), 1);
+        assert_eq!(store.get().users.len(), 2);
+
+        // Tokens may only refer to hosts of their user
+        let result = store.edit(|value| {
+            let user = find_user(value, "other")?;
+            let entry = token_entry("t", "secret", &["test.example.com".to_string()], &[], None)?;
+            list_mut(user, "tokens")?.push(entry);
+            Ok(())
+        });
+
+        assert!(result.is_err());
+        assert!(store.get().users[1].tokens.is_empty());
+    }
+
+    #[test]
+    fn write_changes_to_file() {
+        let path = std::env::temp_dir().join(format!("dyndns-store-{}.json", std::process::id()));
+        fs::write(&path, serde_json::to_string(&user("test")).unwrap()).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+
+        edit_file(&path, |value| {
+            users_mut(value)?.push(user("other"));
+            Ok(())
+        })
+        .unwrap();
+
+        let value: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(value, json!({ "users": [user("test"), user("other")] }));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o640);
+        }
+        assert!(!path.with_extension("json.tmp").exists());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reject_changes_to_fixed_config() {
+        let store = ConfigStore::fixed(Config::default());
+
+        assert!(store.edit(|_| Ok(())).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+//! Minimal DNS client for looking up A and AAAA records over UDP
+
+use std::{
+    error::Error,
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_AAAA: u16 = 28;
+pub const CLASS_IN: u16 = 1;
+
+#[derive(Debug)]
+pub struct DnsError(String);
+
+impl Error for DnsError {}
+
+impl Display for DnsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DNS error: {}", self.0)
+    }
+}
+
+pub fn error(message: &str) -> Box<dyn Error> {
+    Box::new(DnsError(message.to_string()))
+}
+
+/// Append a domain name in wire format
+pub fn encode_name(packet: &mut Vec<u8>, name: &str) -> Result<(), Box<dyn Error>> {
+    for label in name
+        .trim_end_matches('.')
+        .split('.')
+        .filter(|l| !l.is_empty())
+    {
+        if label.len() > 63 {
+            return Err(error("Label too long"));
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+
+    Ok(())
+}
+
+/// Build a query for a single record of the given type
+pub fn build_query(id: u16, name: &str, record_type: u16) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut packet = Vec::with_capacity(512);
+
+    packet.extend_from_slice(&id.to_be_bytes());
+    // Recursion desired, ignored by authoritative servers
+    packet.extend_from_slice(&0x0100u16.to_be_bytes());
+    // One question, no other sections
+    packet.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    encode_name(&mut packet, name)?;
+    packet.extend_from_slice(&record_type.to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    Ok(packet)
+}
+
+fn read_u16(packet: &[u8], offset: usize) -> Result<u16, Box<dyn Error>> {
+    packet
+        .get(offset..offset + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| error("Truncated packet"))
+}
+
+/// Return the offset right after the (possibly compressed) name at `offset`
+pub fn skip_name(packet: &[u8], mut offset: usize) -> Result<usize, Box<dyn Error>> {
+    loop {
+        let length = *packet.get(offset).ok_or_else(|| error("Truncated name"))?;
+
+        match length {
+            0 => return Ok(offset + 1),
+            // A pointer ends the name
+            l if l & 0xC0 == 0xC0 => return Ok(offset + 2),
+            l => offset += 1 + l as usize,
+        }
+    }
+}
+
+/// A resource record of a response, with its data still in wire format
+pub struct ResourceRecord<'a> {
+    pub record_type: u16,
+    pub ttl: u32,
+    pub data: &'a [u8],
+}
+
+/// Check the header of a response and return its answer records
+pub fn parse_answers(packet: &[u8], id: u16) -> Result<Vec<ResourceRecord<'_>>, Box<dyn Error>> {
+    if read_u16(packet, 0)? != id {
+        return Err(error("Response ID does not match query"));
+    }
+
+    let flags = read_u16(packet, 2)?;
+    if flags & 0x8000 == 0 {
+        return Err(error("Not a response"));
+    }
+    if flags & 0x0200 != 0 {
+        return Err(error("Response truncated"));
+    }
+    match flags & 0x000F {
+        0 => {}
+        3 => return Ok(Vec::new()),
+        rcode => return Err(error(&format!("Server responded with RCODE {rcode}"))),
+    }
+
+    let questions = read_u16(packet, 4)?;
+    let answers = read_u16(packet, 6)?;
+
+    let mut offset = 12;
+    for _ in 0..questions {
+        offset = skip_name(packet, offset)? + 4;
+    }
+
+    let mut records = Vec::new();
+    for _ in 0..answers {
+        offset = skip_name(packet, offset)?;
+        let record_type = read_u16(packet, offset)?;
+        let ttl = u32::from(read_u16(packet, offset + 4)?) << 16
+            | u32::from(read_u16(packet, offset + 6)?);
+        let length = read_u16(packet, offset + 8)? as usize;
+        offset += 10;
+
+        let data = packet
+            .get(offset..offset + length)
+            .ok_or_else(|| error("Truncated record"))?;
+        records.push(ResourceRecord {
+            record_type,
+            ttl,
+            data,
+        });
+        offset += length;
+    }
+
+    Ok(records)
+}
+
+/// Send a packet to a server and wait for the response
+pub fn exchange(
+    server: SocketAddr,
+    packet: &[u8],
+    timeout: Duration,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let bind: SocketAddr = if server.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(bind)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.connect(server)?;
+    socket.send(packet)?;
+
+    let mut buffer = vec![0; 4096];
+    let length = socket.recv(&mut buffer)?;
+    buffer.truncate(length);
+
+    Ok(buffer)
+}
+
+/// Look up the addresses of a name of type A or AAAA
+pub fn lookup(
+    server: SocketAddr,
+    name: &str,
+    record_type: u16,
+    timeout: Duration,
+) -> Result<Vec<IpAddr>, Box<dyn Error>> {
+    let id = rand::random();
+    let response = exchange(server, &build_query(id, name, record_type)?, timeout)?;
+
+    Ok(parse_answers(&response, id)?
+        .into_iter()
+        .filter(|r| r.record_type == record_type)
+        .filter_map(|r| match r.data.len() {
+            4 => Some(IpAddr::from(<[u8; 4]>::try_from(r.data).ok()?)),
+            16 => Some(IpAddr::from(<[u8; 16]>::try_from(r.data).ok()?)),
+            _ => None,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::thread;
+
+    use super::*;
+
+    /// Build a response to `query` that answers with the given addresses
+    pub fn answer(query: &[u8], addresses: &[IpAddr]) -> Vec<u8> {
+        let mut response = query.to_vec();
+        // Response, authoritative answer
+        response[2] = 0x84;
+        response[3] = 0x00;
+        response[6..8].copy_from_slice(&(addresses.len() as u16).to_be_bytes());
+
+        for address in addresses {
+            // Pointer to the name in the question
+            response.extend_from_slice(&[0xC0, 12]);
+            let (record_type, data) = match address {
+                IpAddr::V4(ip) => (TYPE_A, ip.octets().to_vec()),
+                IpAddr::V6(ip) => (TYPE_AAAA, ip.octets().to_vec()),
+            };
+            response.extend_from_slice(&record_type.to_be_bytes());
+            response.extend_from_slice(&CLASS_IN.to_be_bytes());
+            response.extend_from_slice(&60u32.to_be_bytes());
+            response.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            response.extend_from_slice(&data);
+        }
+
+        response
+    }
+
+    /// Local DNS stand-in that answers every query with the addresses returned by `answers`
+    pub fn serve(answers: impl Fn(usize) -> Vec<IpAddr> + Send + 'static) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let mut buffer = [0; 512];
+            for query in 0.. {
+                let Ok((length, peer)) = socket.recv_from(&mut buffer) else {
+                    return;
+                };
+                let _ = socket.send_to(&answer(&buffer[..length], &answers(query)), peer);
+            }
+        });
+
+        address
+    }
+
+    #[test]
+    fn encode_query() {
+        let query = build_query(0x1234, "test.example.com.", TYPE_AAAA).unwrap();
+
+        assert_eq!(
+            query,
+            b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+              \x04test\x07example\x03com\x00\x00\x1c\x00\x01"
+        );
+    }
+
+    #[test]
+    fn lookup_a() {
+        let server = serve(|_| vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))]);
+
+        let addresses = lookup(server, "test.example.com", TYPE_A, Duration::from_secs(1)).unwrap();
+
+        assert_eq!(addresses, vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))]);
+    }
+
+    #[test]
+    fn reject_wrong_id() {
+        let query = build_query(1, "example.com", TYPE_A).unwrap();
+        let response = answer(&query, &[]);
+
+        assert!(parse_answers(&response, 2).is_err());
+        assert!(parse_answers(&response, 1).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,242 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+//! History of the updates of every host
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    error::Error,
+    fs,
+    net::IpAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::config::store::replace_file;
+
+fn default_entries() -> usize {
+    100
+}
+
+#[derive(Deserialize, Clone)]
+pub struct HistoryConfig {
+    /// File the history is kept in across restarts, only kept in memory if not set
+    pub path: Option<PathBuf>,
+    /// Number of entries that are kept per host
+    #[serde(default = "default_entries")]
+    pub entries: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            path: None,
+            entries: default_entries(),
+        }
+    }
+}
+
+/// Result of a single record update
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Entry {
+    #[serde(with = "time::serde::rfc3339")]
+    pub time: OffsetDateTime,
+    pub user: String,
+    pub host: String,
+    pub record_type: String,
+    pub ip: IpAddr,
+    /// Address of the client that sent the update
+    pub client: Option<IpAddr>,
+    /// updated, unchanged or failed
+    pub result: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Whether the record is in the internal zone of the host
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub lan: bool,
+}
+
+/// Summary of the history of a host
+#[derive(PartialEq, Eq, Debug)]
+pub struct HostStatus {
+    pub last_update: Option<OffsetDateTime>,
+    pub last_client: Option<IpAddr>,
+    /// Latest error with its time, even if there were successful updates afterwards
+    pub last_error: Option<(OffsetDateTime, String)>,
+}
+
+/// Entries by user and host, as several users may have hosts of the same name
+type Users = BTreeMap<String, BTreeMap<String, VecDeque<Entry>>>;
+
+/// Latest updates of every host, newest last
+///
+/// Clones share their entries.
+#[derive(Clone)]
+pub struct History {
+    config: HistoryConfig,
+    users: Arc<Mutex<Users>>,
+}
+
+impl History {
+    /// Create the history, reading the entries from its file if there is one
+    pub fn new(config: &HistoryConfig) -> Result<History, Box<dyn Error>> {
+        let users = match &config.path {
+            Some(path) if path.exists() => serde_json::from_str(&fs::read_to_string(path)?)?,
+            _ => Users::new(),
+        };
+
+        Ok(History {
+            config: config.clone(),
+            users: Arc::new(Mutex::new(users)),
+        })
+    }
+
+    /// Add an entry and write the history to its file
+    pub fn record(&self, entry: Entry) {
+        let mut users = self.users.lock().unwrap();
+
+        let entries = users
+            .entry(entry.user.clone())
+            .or_default()
+            .entry(entry.host.clone())
+            .or_default();
+        entries.push_back(entry);
+        while entries.len() > self.config.entries.max(1) {
+            entries.pop_front();
+        }
+
+        if let Some(path) = &self.config.path {
+            let result = serde_json::to_string(&*users)
+                .map_err(|e| e.to_string())
+                .and_then(|content| replace_file(path, &content).map_err(|e| e.to_string()));
+
+            if let Err(e) = result {
+                warn!("Error writing history to {}: {e}", path.display());
+            }
+        }
+    }
+
+    /// Entries of a host of a user, newest first
+    #[must_use]
+    pub fn entries(&self, user: &str, host: &str) -> Vec<Entry> {
+        self.users
+            .lock()
+            .unwrap()
+            .get(user)
+            .and_then(|hosts| hosts.get(host))
+            .map(|entries| entries.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn status(&self, user: &str, host: &str) -> HostStatus {
+        let entries = self.entries(user, host);
+        let last = entries.first();
+
+        HostStatus {
+            last_update: last.map(|e| e.time),
+            last_client: last.and_then(|e| e.client),
+            last_error: entries
+                .iter()
+                .find_map(|e| e.error.as_ref().map(|error| (e.time, error.clone()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn entry(minute: u8, error: Option<&str>) -> Entry {
+        Entry {
+            time: datetime!(2026-01-01 00:00 UTC)
+                .replace_minute(minute)
+                .unwrap(),
+            user: "test".to_string(),
+            host: "example.com".to_string(),
+            record_type: "A".to_string(),
+            ip: IpAddr::V4(Ipv4Addr::new(192, 0, 2, minute)),
+            client: Some(IpAddr::V4(Ipv4Addr::new(198, 51, 100, minute))),
+            result: if error.is_some() { "failed" } else { "updated" }.to_string(),
+            error: error.map(ToString::to_string),
+            lan: false,
+        }
+    }
+
+    #[test]
+    fn keep_latest_entries() {
+        let history = History::new(&HistoryConfig {
+            path: None,
+            entries: 2,
+        })
+        .unwrap();
+
+        history.record(entry(1, None));
+        history.record(entry(2, Some("Error: Record not found")));
+        history.record(entry(3, None));
+
+        assert_eq!(
+            history.entries("test", "example.com"),
+            vec![entry(3, None), entry(2, Some("Error: Record not found"))]
+        );
+        assert_eq!(
+            history.status("test", "example.com"),
+            HostStatus {
+                last_update: Some(datetime!(2026-01-01 00:03 UTC)),
+                last_client: Some(IpAddr::V4(Ipv4Addr::new(198, 51, 100, 3))),
+                last_error: Some((
+                    datetime!(2026-01-01 00:02 UTC),
+                    "Error: Record not found".to_string()
+                )),
+            }
+        );
+        assert_eq!(history.status("test", "www.example.com").last_update, None);
+    }
+
+    #[test]
+    fn separate_users_with_same_host() {
+        let history = History::new(&HistoryConfig::default()).unwrap();
+
+        history.record(entry(1, None));
+        history.record(Entry {
+            user: "other".to_string(),
+            ..entry(2, Some("Error: Record not found"))
+        });
+
+        assert_eq!(history.entries("test", "example.com"), vec![entry(1, None)]);
+        assert_eq!(history.status("test", "example.com").last_error, None);
+        assert_eq!(history.entries("other", "example.com").len(), 1);
+    }
+
+    #[test]
+    fn persist_entries() {
+        let path = std::env::temp_dir().join(format!("dyndns-history-{}.json", std::process::id()));
+        let config = HistoryConfig {
+            path: Some(path.clone()),
+            entries: 10,
+        };
+
+        History::new(&config).unwrap().record(entry(1, None));
+
+        assert_eq!(
+            History::new(&config)
+                .unwrap()
+                .entries("test", "example.com"),
+            vec![entry(1, None)]
+        );
+        assert!(!path.with_extension("json.tmp").exists());
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,267 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+//! Endpoints for the httpreq DNS provider of lego and Caddy, which answers DNS-01 challenges
+//!
+//! Users can create challenge records in the zones of their domains without access to the API token of the provider.
+
+use std::net::IpAddr;
+
+use log::{info, warn};
+use rocket::{http::Status, post, serde::json::Json, Route, State};
+use serde::Deserialize;
+
+use crate::{
+    api::{authenticate, error, ApiError},
+    auth::{Authenticated, Credentials},
+    config::{in_zone, store::ConfigStore, DomainConfig, Providers},
+    logging,
+    providers::{add_txt, remove_txt},
+};
+
+const CHALLENGE_PREFIX: &str = "_acme-challenge.";
+
+#[derive(Deserialize)]
+pub struct ChallengeRequest {
+    /// Name of the record, usually with a trailing dot
+    pub fqdn: String,
+    pub value: String,
+}
+
+/// Domain through which the user may manage the challenge record `name`
+///
+/// Tokens restricted to some hosts may only create the challenge records of those hosts,
+/// otherwise every name in the zones of the domains of the user is allowed.
+fn challenge_domain<'a>(authenticated: Authenticated<'a>, name: &str) -> Option<&'a DomainConfig> {
+    let target = name.strip_prefix(CHALLENGE_PREFIX)?;
+
+    authenticated
+        .user
+        .domains
+        .iter()
+        .filter(|d| match authenticated.token {
+            Some(token) if !token.hosts.is_empty() => {
+                token.allows_host(&d.host) && d.host == target
+            }
+            _ => in_zone(target, &d.zone.name),
+        })
+        .max_by_key(|d| d.zone.name.len())
+}
+
+/// Add `request.value` to the challenge record, or remove it if `present` is false
+fn change(
+    request: &ChallengeRequest,
+    present: bool,
+    credentials: Option<Credentials>,
+    client_ip: Option<IpAddr>,
+    store: &ConfigStore,
+    providers: &Providers,
+) -> Result<Status, ApiError> {
+    let _context = logging::enter_request();
+
+    let name = request.fqdn.trim_end_matches('.').to_ascii_lowercase();
+    logging::set_field("host", &name);
+
+    let config = store.get();
+    let authenticated = authenticate(credentials, &config, client_ip, &name)?;
+    logging::set_field("user", &authenticated.user.name);
+
+    let Some(zone_domain) = challenge_domain(authenticated, &name) else {
+        warn!(
+            "User {} may not change the record {name}",
+            authenticated.user.name
+        );
+        return Err(error(
+            Status::Forbidden,
+            &format!("Not allowed to change the record {name}"),
+        ));
+    };
+    let domain = DomainConfig {
+        host: name.clone(),
+        ..zone_domain.clone()
+    };
+
+    if let Err(e) = providers.get(&domain).and_then(|provider| {
+        if present {
+            add_txt(provider, &domain, &request.value, None)
+        } else {
+            remove_txt(provider, &domain, &request.value)
+        }
+    }) {
+        warn!("Error changing TXT record {name}: {e}");
+        return Err(error(
+            Status::InternalServerError,
+            &format!("Error changing TXT record: {e}"),
+        ));
+    }
+
+    if present {
+        info!("Created challenge record {name}");
+    } else {
+        info!("Removed challenge record {name}");
+    }
+
+    Ok(Status::Ok)
+}
+
+#[post("/present", data = "<request>")]
+fn present(
+    request: Json<ChallengeRequest>,
+    credentials: Option<Credentials>,
+    client_ip: Option<IpAddr>,
+    store: &State<ConfigStore>,
+    providers: &State<Providers>,
+) -> Result<Status, ApiError> {
+    change(&request, true, credentials, client_ip, store, providers)
+}
+
+#[post("/cleanup", data = "<request>")]
+fn cleanup(
+    request: Json<ChallengeRequest>,
+    credentials: Option<Credentials>,
+    client_ip: Option<IpAddr>,
+    store: &State<ConfigStore>,
+    providers: &State<Providers>,
+) -> Result<Status, ApiError> {
+    change(&request, false, credentials, client_ip, store, providers)
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![present, cleanup]
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{
+        http::{ContentType, Header},
+        local::blocking::{Client, LocalResponse},
+    };
+
+    use crate::{
+        providers::{
+            tests::{txt_provider, txt_records, TxtRecords},
+            MockProvider,
+        },
+        server::tests::{construct_with_config, test_config},
+    };
+
+    use super::*;
+
+    /// Basic authentication of user test with password testpassword
+    const BASIC: &str = "Basic dGVzdDp0ZXN0cGFzc3dvcmQ=";
+    /// Basic authentication of user test with the token for www.example.com
+    const WWW_TOKEN: &str = "Basic dGVzdDp0ZXN0d3d3dG9rZW4=";
+
+    /// Test client with the domains of the test user in the zone example.com
+    fn construct(mock_provider: MockProvider) -> Client {
+        let mut config = test_config();
+        for domain in &mut config.users[0].domains {
+            domain.zone.name = "example.com".to_string();
+        }
+
+        construct_with_config(config, Some(mock_provider))
+    }
+
+    fn send<'c>(
+        client: &'c Client,
+        path: &str,
+        authorization: &str,
+        fqdn: &str,
+        value: &str,
+    ) -> LocalResponse<'c> {
+        client
+            .post(format!("/httpreq{path}"))
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", authorization.to_string()))
+            .body(format!(r#"{{"fqdn": "{fqdn}", "value": "{value}"}}"#))
+            .dispatch()
+    }
+
+    #[test]
+    fn present_and_cleanup() {
+        // The record already holds the value of a challenge that was presented before a restart
+        let name = "_acme-challenge.nas.example.com";
+        let records = txt_records(name, &["existing"]);
+        let client = construct(txt_provider(&records));
+        let fqdn = "_acme-challenge.nas.example.com.";
+
+        for value in ["first", "second", "third"] {
+            assert_eq!(
+                send(&client, "/present", BASIC, fqdn, value).status(),
+                Status::Ok
+            );
+        }
+        assert_eq!(
+            records.lock().unwrap()[name],
+            ["existing", "first", "second", "third"]
+        );
+
+        assert_eq!(
+            send(&client, "/cleanup", BASIC, fqdn, "first").status(),
+            Status::Ok
+        );
+        assert_eq!(
+            records.lock().unwrap()[name],
+            ["existing", "second", "third"]
+        );
+    }
+
+    #[test]
+    fn reject_names_outside_zones() {
+        let client = construct(MockProvider::default());
+
+        // Only challenge records can be created
+        let response = send(&client, "/present", BASIC, "nas.example.com.", "value");
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = send(
+            &client,
+            "/present",
+            BASIC,
+            "_acme-challenge.example.org.",
+            "value",
+        );
+        assert_eq!(response.status(), Status::Forbidden);
+
+        // Tokens for some hosts can only be used for their challenge records
+        let response = send(
+            &client,
+            "/present",
+            WWW_TOKEN,
+            "_acme-challenge.nas.example.com.",
+            "value",
+        );
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = send(
+            &client,
+            "/present",
+            "Basic dGVzdDp3cm9uZ3Bhc3N3b3Jk",
+            "_acme-challenge.nas.example.com.",
+            "value",
+        );
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn restrict_tokens_to_their_hosts() {
+        let records = TxtRecords::default();
+        let client = construct(txt_provider(&records));
+
+        let response = send(
+            &client,
+            "/present",
+            WWW_TOKEN,
+            "_acme-challenge.www.example.com.",
+            "value",
+        );
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            records.lock().unwrap()["_acme-challenge.www.example.com"],
+            ["value"]
+        );
+    }
+}
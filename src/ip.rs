@@ -0,0 +1,152 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+//! Endpoints that return the address of the client, for scripts and the client mode
+//!
+//! Behind a reverse proxy, the address is taken from the X-Real-IP header like for updates.
+
+use std::net::IpAddr;
+
+use rocket::{
+    get,
+    http::{Accept, ContentType, Status},
+    Route,
+};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct AddressResponse {
+    ip: IpAddr,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// Address of the client as plain text, or as JSON if the client prefers it
+fn respond(
+    client_ip: Option<IpAddr>,
+    accept: Option<&Accept>,
+    family: Option<bool>,
+) -> (Status, (ContentType, String)) {
+    let json = accept.is_some_and(|a| a.preferred().is_json());
+
+    // Dual-stack listeners see IPv4 clients as IPv4-mapped IPv6 addresses
+    let result = match (client_ip.map(|ip| ip.to_canonical()), family) {
+        (None, _) => Err((Status::InternalServerError, "Client address unknown")),
+        (Some(ip), Some(ipv6)) if ip.is_ipv6() != ipv6 => Err((
+            Status::NotFound,
+            if ipv6 {
+                "Not connected over IPv6"
+            } else {
+                "Not connected over IPv4"
+            },
+        )),
+        (Some(ip), _) => Ok(ip),
+    };
+
+    match (result, json) {
+        (Ok(ip), false) => (Status::Ok, (ContentType::Plain, ip.to_string())),
+        (Ok(ip), true) => (
+            Status::Ok,
+            (
+                ContentType::JSON,
+                serde_json::to_string(&AddressResponse { ip }).unwrap_or_default(),
+            ),
+        ),
+        (Err((status, error)), false) => (status, (ContentType::Plain, error.to_string())),
+        (Err((status, error)), true) => (
+            status,
+            (
+                ContentType::JSON,
+                serde_json::to_string(&ErrorResponse {
+                    error: error.to_string(),
+                })
+                .unwrap_or_default(),
+            ),
+        ),
+    }
+}
+
+#[get("/ip")]
+fn ip(client_ip: Option<IpAddr>, accept: Option<&Accept>) -> (Status, (ContentType, String)) {
+    respond(client_ip, accept, None)
+}
+
+#[get("/ip4")]
+fn ip4(client_ip: Option<IpAddr>, accept: Option<&Accept>) -> (Status, (ContentType, String)) {
+    respond(client_ip, accept, Some(false))
+}
+
+#[get("/ip6")]
+fn ip6(client_ip: Option<IpAddr>, accept: Option<&Accept>) -> (Status, (ContentType, String)) {
+    respond(client_ip, accept, Some(true))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![ip, ip4, ip6]
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{http::Header, local::blocking::Client};
+
+    use crate::server::tests::{construct_with_config, test_config};
+
+    use super::*;
+
+    fn get(client: &Client, path: &str, client_ip: &str, json: bool) -> (Status, String) {
+        let mut request = client
+            .get(path)
+            .header(Header::new("X-Real-IP", client_ip.to_string()));
+        if json {
+            request = request.header(Accept::JSON);
+        }
+        let response = request.dispatch();
+
+        (response.status(), response.into_string().unwrap())
+    }
+
+    #[test]
+    fn return_client_address() {
+        let client = construct_with_config(test_config(), None);
+
+        assert_eq!(
+            get(&client, "/ip", "198.51.100.1", false),
+            (Status::Ok, "198.51.100.1".to_string())
+        );
+        assert_eq!(
+            get(&client, "/ip", "2001:db8::1", true),
+            (Status::Ok, r#"{"ip":"2001:db8::1"}"#.to_string())
+        );
+        assert_eq!(
+            get(&client, "/ip4", "::ffff:198.51.100.1", false),
+            (Status::Ok, "198.51.100.1".to_string())
+        );
+        assert_eq!(
+            get(&client, "/ip6", "2001:db8::1", false),
+            (Status::Ok, "2001:db8::1".to_string())
+        );
+    }
+
+    #[test]
+    fn reject_other_family() {
+        let client = construct_with_config(test_config(), None);
+
+        assert_eq!(
+            get(&client, "/ip4", "2001:db8::1", false),
+            (Status::NotFound, "Not connected over IPv4".to_string())
+        );
+        assert_eq!(
+            get(&client, "/ip6", "198.51.100.1", true),
+            (
+                Status::NotFound,
+                r#"{"error":"Not connected over IPv6"}"#.to_string()
+            )
+        );
+    }
+}
@@ -0,0 +1,320 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+//! Records with several addresses, each of which belongs to one client
+//!
+//! Every client updates its own entry, identified by a key, e.g. one router per uplink.
+//! Entries that are not renewed within the lease are removed from the record.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+use serde::Deserialize;
+use time::{Duration, OffsetDateTime};
+
+use crate::providers::RecordType;
+
+fn default_lease() -> u64 {
+    86400
+}
+
+#[derive(Deserialize, Clone)]
+pub struct KeyedConfig {
+    /// Seconds after the last update of a client until its address is removed
+    #[serde(default = "default_lease")]
+    pub lease: u64,
+}
+
+impl KeyedConfig {
+    #[must_use]
+    pub fn expires(&self, now: OffsetDateTime) -> OffsetDateTime {
+        now + Duration::seconds(i64::try_from(self.lease).unwrap_or(i64::MAX))
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    /// Client that owns the address, unknown for addresses that were in the record before
+    key: Option<String>,
+    ip: IpAddr,
+    expires: OffsetDateTime,
+}
+
+/// Entries by user, host and record type, as several users may have hosts of the same name
+type Records = HashMap<(String, String, RecordType), Vec<Entry>>;
+
+/// Entries of a record that expired
+#[derive(PartialEq, Eq, Debug)]
+pub struct ExpiredEntries {
+    /// User the host belongs to
+    pub user: String,
+    pub host: String,
+    pub record_type: RecordType,
+    pub removed: Vec<IpAddr>,
+    /// Addresses the record should hold now
+    pub remaining: Vec<IpAddr>,
+}
+
+/// Entries of all keyed records, by user, host and record type
+///
+/// Clones share their entries. They are only kept in memory, after a restart the addresses in the records
+/// are taken over without a key and expire unless a client claims them by updating to the same address.
+#[derive(Clone, Default)]
+pub struct KeyedEntries {
+    records: Arc<Mutex<Records>>,
+}
+
+impl KeyedEntries {
+    /// Whether the entries of a record are known, otherwise they have to be loaded first
+    #[must_use]
+    pub fn contains(&self, user: &str, host: &str, record_type: RecordType) -> bool {
+        self.records.lock().unwrap().contains_key(&(
+            user.to_string(),
+            host.to_string(),
+            record_type,
+        ))
+    }
+
+    /// Take over the current addresses of a record, which expire at `expires`
+    pub fn load(
+        &self,
+        user: &str,
+        host: &str,
+        record_type: RecordType,
+        ips: &[IpAddr],
+        expires: OffsetDateTime,
+    ) {
+        self.records
+            .lock()
+            .unwrap()
+            .entry((user.to_string(), host.to_string(), record_type))
+            .or_insert_with(|| {
+                ips.iter()
+                    .map(|ip| Entry {
+                        key: None,
+                        ip: *ip,
+                        expires,
+                    })
+                    .collect()
+            });
+    }
+
+    /// Set the address of the client `key` and remove expired entries
+    ///
+    /// Returns the previous address of the client and the addresses the record should hold.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set(
+        &self,
+        user: &str,
+        host: &str,
+        record_type: RecordType,
+        key: &str,
+        ip: IpAddr,
+        expires: OffsetDateTime,
+        now: OffsetDateTime,
+    ) -> (Option<IpAddr>, Vec<IpAddr>) {
+        let mut records = self.records.lock().unwrap();
+        let entries = records
+            .entry((user.to_string(), host.to_string(), record_type))
+            .or_default();
+
+        let previous = entries
+            .iter()
+            .find(|e| e.key.as_deref() == Some(key))
+            .map(|e| e.ip);
+
+        entries.retain(|e| {
+            e.expires > now && e.key.as_deref() != Some(key) && !(e.key.is_none() && e.ip == ip)
+        });
+        entries.push(Entry {
+            key: Some(key.to_string()),
+            ip,
+            expires,
+        });
+
+        (previous, addresses(entries))
+    }
+
+    /// Remove the entries that expired before `now` from all records
+    pub fn expire(&self, now: OffsetDateTime) -> Vec<ExpiredEntries> {
+        let mut expired = Vec::new();
+
+        for ((user, host, record_type), entries) in self.records.lock().unwrap().iter_mut() {
+            let (removed, kept): (Vec<Entry>, Vec<Entry>) =
+                entries.drain(..).partition(|e| e.expires <= now);
+            *entries = kept;
+
+            if !removed.is_empty() {
+                let remaining = addresses(entries);
+                expired.push(ExpiredEntries {
+                    user: user.clone(),
+                    host: host.clone(),
+                    record_type: *record_type,
+                    // Another client may still use the address
+                    removed: addresses(&removed)
+                        .into_iter()
+                        .filter(|ip| !remaining.contains(ip))
+                        .collect(),
+                    remaining,
+                });
+            }
+        }
+
+        expired
+    }
+}
+
+/// Addresses of the entries without duplicates, in the order they were added
+fn addresses(entries: &[Entry]) -> Vec<IpAddr> {
+    let mut addresses = Vec::new();
+    for entry in entries {
+        if !addresses.contains(&entry.ip) {
+            addresses.push(entry.ip);
+        }
+    }
+
+    addresses
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use time::macros::datetime;
+
+    use super::*;
+
+    const USER: &str = "test";
+    const HOST: &str = "home.example.com";
+    const START: OffsetDateTime = datetime!(2026-01-01 00:00 UTC);
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(192, 0, 2, last))
+    }
+
+    /// Set the address of `key` in the A record of `HOST` with a lease of one hour
+    fn set(
+        entries: &KeyedEntries,
+        key: &str,
+        ip: IpAddr,
+        minutes: i64,
+    ) -> (Option<IpAddr>, Vec<IpAddr>) {
+        let now = START + Duration::minutes(minutes);
+        let config = KeyedConfig { lease: 3600 };
+
+        entries.set(USER, HOST, RecordType::A, key, ip, config.expires(now), now)
+    }
+
+    #[test]
+    fn one_address_per_key() {
+        let entries = KeyedEntries::default();
+
+        assert_eq!(set(&entries, "uplink1", ip(1), 0), (None, vec![ip(1)]));
+        assert_eq!(
+            set(&entries, "uplink2", ip(2), 0),
+            (None, vec![ip(1), ip(2)])
+        );
+        assert_eq!(
+            set(&entries, "uplink1", ip(3), 0),
+            (Some(ip(1)), vec![ip(2), ip(3)])
+        );
+
+        // Records of other hosts are separate, even if another user has a host of the same name
+        let expires = START + Duration::hours(1);
+        assert_eq!(
+            entries.set(
+                USER,
+                "www.example.com",
+                RecordType::A,
+                "uplink1",
+                ip(4),
+                expires,
+                START
+            ),
+            (None, vec![ip(4)])
+        );
+        assert_eq!(
+            entries.set(
+                "other",
+                HOST,
+                RecordType::A,
+                "uplink1",
+                ip(5),
+                expires,
+                START
+            ),
+            (None, vec![ip(5)])
+        );
+    }
+
+    #[test]
+    fn remove_expired_entries() {
+        let entries = KeyedEntries::default();
+
+        set(&entries, "uplink1", ip(1), 0);
+        set(&entries, "uplink2", ip(2), 30);
+
+        assert_eq!(
+            set(&entries, "uplink2", ip(2), 61),
+            (Some(ip(2)), vec![ip(2)])
+        );
+    }
+
+    #[test]
+    fn expire_entries_without_update() {
+        let entries = KeyedEntries::default();
+
+        set(&entries, "uplink1", ip(1), 0);
+        set(&entries, "uplink2", ip(2), 30);
+
+        assert!(entries.expire(START + Duration::minutes(59)).is_empty());
+        assert_eq!(
+            entries.expire(START + Duration::minutes(61)),
+            vec![ExpiredEntries {
+                user: USER.to_string(),
+                host: HOST.to_string(),
+                record_type: RecordType::A,
+                removed: vec![ip(1)],
+                remaining: vec![ip(2)],
+            }]
+        );
+        assert!(entries.expire(START + Duration::minutes(61)).is_empty());
+    }
+
+    #[test]
+    fn claim_loaded_addresses() {
+        let entries = KeyedEntries::default();
+
+        assert!(!entries.contains(USER, HOST, RecordType::A));
+        entries.load(
+            USER,
+            HOST,
+            RecordType::A,
+            &[ip(1), ip(2)],
+            START + Duration::hours(1),
+        );
+        assert!(entries.contains(USER, HOST, RecordType::A));
+        assert!(!entries.contains("other", HOST, RecordType::A));
+
+        // The unknown owner of an address is found once it updates to the same address
+        assert_eq!(
+            set(&entries, "uplink2", ip(2), 0),
+            (None, vec![ip(1), ip(2)])
+        );
+        assert_eq!(
+            set(&entries, "uplink2", ip(3), 0),
+            (Some(ip(2)), vec![ip(1), ip(3)])
+        );
+
+        assert_eq!(
+            set(&entries, "uplink2", ip(3), 61),
+            (Some(ip(3)), vec![ip(3)])
+        );
+    }
+}
@@ -0,0 +1,515 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+//! Records that are removed or set to a fallback when their host stops sending updates
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::Duration,
+};
+
+use log::{info, warn};
+use serde::Deserialize;
+use time::OffsetDateTime;
+
+use crate::{
+    config::{store::ConfigStore, DomainConfig, Providers, User},
+    history::History,
+    keyed::{ExpiredEntries, KeyedEntries},
+    notifications::{Notification, Notifier},
+    providers::{RecordSet, RecordType, UpdateStatus},
+};
+
+/// Interval in which the leases are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize, Clone)]
+pub struct LeaseConfig {
+    /// Seconds after the last successful update until the lease expires
+    pub duration: u64,
+    /// Address the A record is set to when the lease expires, the record is deleted if not set
+    pub fallback_ipv4: Option<Ipv4Addr>,
+    /// Address the AAAA record is set to when the lease expires, the record is deleted if not set
+    pub fallback_ipv6: Option<Ipv6Addr>,
+}
+
+impl LeaseConfig {
+    fn fallback(&self, record_type: RecordType) -> Option<IpAddr> {
+        if record_type == RecordType::A {
+            self.fallback_ipv4.map(IpAddr::V4)
+        } else {
+            self.fallback_ipv6.map(IpAddr::V6)
+        }
+    }
+
+    /// End of a lease that started at `start`
+    #[must_use]
+    pub fn expires(&self, start: OffsetDateTime) -> OffsetDateTime {
+        start + time::Duration::seconds(i64::try_from(self.duration).unwrap_or(i64::MAX))
+    }
+}
+
+/// Expires the leases of hosts and the entries of keyed records
+///
+/// A lease covers all records of a host and is renewed by every successful update, whatever its address family.
+/// The last successful update of a host is taken from the history. Hosts without one, e.g. because the history
+/// is not kept across restarts, start their lease when they are first seen.
+pub struct Leases {
+    store: ConfigStore,
+    providers: Providers,
+    notifier: Notifier,
+    history: History,
+    keyed_entries: KeyedEntries,
+    first_seen: HashMap<String, OffsetDateTime>,
+    /// Start of the last lease that expired, so every lease expires only once
+    expired: HashMap<(String, RecordType), OffsetDateTime>,
+}
+
+impl Leases {
+    #[must_use]
+    pub fn new(
+        store: ConfigStore,
+        providers: Providers,
+        notifier: Notifier,
+        history: History,
+        keyed_entries: KeyedEntries,
+    ) -> Self {
+        Leases {
+            store,
+            providers,
+            notifier,
+            history,
+            keyed_entries,
+            first_seen: HashMap::new(),
+            expired: HashMap::new(),
+        }
+    }
+
+    /// Act on everything that expired before `now`
+    pub fn check(&mut self, now: OffsetDateTime) {
+        let config = self.store.get();
+
+        for user in &config.users {
+            for domain_config in &user.domains {
+                // Hosts in maintenance mode keep their addresses
+                let Some(lease) = domain_config
+                    .lease
+                    .as_ref()
+                    .filter(|_| !domain_config.offline)
+                else {
+                    continue;
+                };
+
+                self.check_host(user, domain_config, lease, now);
+            }
+        }
+
+        for expired in self.keyed_entries.expire(now) {
+            let domain = config
+                .users
+                .iter()
+                .find(|u| u.name == expired.user)
+                .and_then(|u| {
+                    u.domains
+                        .iter()
+                        .find(|d| d.host == expired.host)
+                        .map(|d| (u, d))
+                });
+
+            if let Some((user, domain_config)) = domain {
+                self.expire_keyed(user, domain_config, &expired);
+            }
+        }
+    }
+
+    fn check_host(
+        &mut self,
+        user: &User,
+        domain_config: &DomainConfig,
+        lease: &LeaseConfig,
+        now: OffsetDateTime,
+    ) {
+        let host = &domain_config.host;
+        let updates: Vec<_> = self
+            .history
+            .entries(&user.name, host)
+            .into_iter()
+            .filter(|e| e.result != "failed" && !e.lan)
+            .collect();
+
+        let start = match updates.first() {
+            Some(entry) => entry.time,
+            None => *self.first_seen.entry(host.clone()).or_insert(now),
+        };
+
+        if now < lease.expires(start) {
+            return;
+        }
+
+        for record_type in [RecordType::A, RecordType::AAAA] {
+            let key = (host.clone(), record_type);
+            if self.expired.get(&key) == Some(&start) {
+                continue;
+            }
+
+            // Records of a family the host never sent are not its own, unless nothing is known about the host
+            let last_update = updates
+                .iter()
+                .find(|e| e.record_type == record_type.to_string());
+            if last_update.is_none() && !updates.is_empty() {
+                continue;
+            }
+
+            let last_ip = last_update.map(|e| e.ip);
+            match self.expire(user, domain_config, lease, record_type, last_ip) {
+                Ok(()) => {
+                    self.expired.insert(key, start);
+                }
+                Err(error) => warn!("Error expiring the {record_type} record of {host}: {error}"),
+            }
+        }
+    }
+
+    /// Set a record to its fallback or delete it
+    fn expire(
+        &self,
+        user: &User,
+        domain_config: &DomainConfig,
+        lease: &LeaseConfig,
+        record_type: RecordType,
+        last_ip: Option<IpAddr>,
+    ) -> Result<(), Box<dyn Error>> {
+        let provider = self.providers.get(domain_config)?;
+        let fallback = lease.fallback(record_type);
+
+        let changed = match fallback {
+            Some(fallback) => {
+                let record_set = RecordSet {
+                    record_type,
+                    values: vec![fallback.to_string()],
+                    ttl: domain_config.ttl,
+                };
+                provider.upsert_record_set(domain_config, &record_set)? != UpdateStatus::Unchanged
+            }
+            None if provider
+                .get_record_set(domain_config, record_type)?
+                .is_some() =>
+            {
+                provider.delete_record_set(domain_config, record_type)?;
+                true
+            }
+            None => false,
+        };
+
+        if changed {
+            let notification = Notification::lease_expired(
+                &user.name,
+                &domain_config.host,
+                record_type,
+                last_ip,
+                fallback,
+            );
+            info!("{}", notification.text);
+            self.notifier.notify(&notification);
+        }
+
+        Ok(())
+    }
+
+    /// Remove the expired addresses from a keyed record
+    fn expire_keyed(&self, user: &User, domain_config: &DomainConfig, expired: &ExpiredEntries) {
+        let result = self.providers.get(domain_config).and_then(|provider| {
+            if expired.remaining.is_empty() {
+                provider.delete_record_set(domain_config, expired.record_type)
+            } else {
+                let record_set = RecordSet {
+                    record_type: expired.record_type,
+                    values: expired.remaining.iter().map(ToString::to_string).collect(),
+                    ttl: domain_config.ttl,
+                };
+                provider
+                    .upsert_record_set(domain_config, &record_set)
+                    .map(drop)
+            }
+        });
+
+        if let Err(error) = result {
+            warn!(
+                "Error removing expired addresses from the {} record of {}: {error}",
+                expired.record_type, domain_config.host
+            );
+            return;
+        }
+
+        for ip in &expired.removed {
+            let notification = Notification::lease_expired(
+                &user.name,
+                &domain_config.host,
+                expired.record_type,
+                Some(*ip),
+                None,
+            );
+            info!("{}", notification.text);
+            self.notifier.notify(&notification);
+        }
+    }
+}
+
+/// Check the leases periodically
+pub async fn watch(mut leases: Leases) {
+    loop {
+        tokio::time::sleep(CHECK_INTERVAL).await;
+
+        leases.check(OffsetDateTime::now_utc());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use crate::{
+        history::{Entry, HistoryConfig},
+        notifications::NotificationConfig,
+        providers::MockProvider,
+        server::tests::test_config,
+    };
+
+    use super::*;
+
+    const START: OffsetDateTime = datetime!(2026-01-01 00:00 UTC);
+
+    fn minutes(minutes: i64) -> OffsetDateTime {
+        START + time::Duration::minutes(minutes)
+    }
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    fn a_record(values: &[&str]) -> RecordSet {
+        RecordSet {
+            record_type: RecordType::A,
+            values: values.iter().map(ToString::to_string).collect(),
+            ttl: None,
+        }
+    }
+
+    fn update(history: &History, time: OffsetDateTime, address: &str) {
+        let ip = ip(address);
+        history.record(Entry {
+            time,
+            user: "test".to_string(),
+            host: "example.com".to_string(),
+            record_type: if ip.is_ipv4() { "A" } else { "AAAA" }.to_string(),
+            ip,
+            client: None,
+            result: "updated".to_string(),
+            error: None,
+            lan: false,
+        });
+    }
+
+    /// Leases with `lease` for example.com
+    fn leases(
+        mock: MockProvider,
+        lease: Option<LeaseConfig>,
+        history: &History,
+        keyed_entries: &KeyedEntries,
+    ) -> Leases {
+        let mut config = test_config();
+        config.users[0].domains[0].lease = lease;
+
+        Leases::new(
+            ConfigStore::fixed(config),
+            Providers {
+                hetzner_provider: None,
+                rfc2136_provider: None,
+                mock_provider: Some(mock),
+            },
+            Notifier::new(&NotificationConfig::default()).unwrap(),
+            history.clone(),
+            keyed_entries.clone(),
+        )
+    }
+
+    #[test]
+    fn expire_hosts_without_updates() {
+        let mut mock = MockProvider::default();
+        mock.expect_upsert_record_set()
+            .withf(|d, r| d.host == "example.com" && *r == a_record(&["192.0.2.99"]))
+            .times(2)
+            .returning(|_, _| Ok(UpdateStatus::Updated { previous: None }));
+        // The host never sent an IPv6 address, so its AAAA record is left alone
+        mock.expect_get_record_set().never();
+        mock.expect_delete_record_set().never();
+
+        let history = History::new(&HistoryConfig::default()).unwrap();
+        update(&history, START, "192.0.2.1");
+
+        let lease = LeaseConfig {
+            duration: 3600,
+            fallback_ipv4: Some(Ipv4Addr::new(192, 0, 2, 99)),
+            fallback_ipv6: None,
+        };
+        let mut leases = leases(mock, Some(lease), &history, &KeyedEntries::default());
+        leases.check(minutes(30));
+        leases.check(minutes(61));
+        leases.check(minutes(91));
+        leases.check(minutes(120));
+
+        // Another update starts a new lease
+        update(&history, minutes(120), "192.0.2.2");
+        leases.check(minutes(150));
+        leases.check(minutes(181));
+    }
+
+    #[test]
+    fn renew_lease_with_any_family() {
+        let mut mock = MockProvider::default();
+        mock.expect_upsert_record_set()
+            .withf(|d, r| d.host == "example.com" && *r == a_record(&["192.0.2.99"]))
+            .once()
+            .returning(|_, _| Ok(UpdateStatus::Updated { previous: None }));
+        mock.expect_get_record_set()
+            .withf(|d, record_type| d.host == "example.com" && *record_type == RecordType::AAAA)
+            .once()
+            .returning(|_, _| {
+                Ok(Some(RecordSet {
+                    record_type: RecordType::AAAA,
+                    values: vec!["2001:db8::1".to_string()],
+                    ttl: None,
+                }))
+            });
+        mock.expect_delete_record_set()
+            .withf(|d, record_type| d.host == "example.com" && *record_type == RecordType::AAAA)
+            .once()
+            .returning(|_, _| Ok(()));
+
+        let history = History::new(&HistoryConfig::default()).unwrap();
+        update(&history, START, "192.0.2.1");
+        update(&history, minutes(50), "2001:db8::1");
+
+        let lease = LeaseConfig {
+            duration: 3600,
+            fallback_ipv4: Some(Ipv4Addr::new(192, 0, 2, 99)),
+            fallback_ipv6: None,
+        };
+        let mut leases = leases(mock, Some(lease), &history, &KeyedEntries::default());
+        // The IPv6 update renewed the lease of the A record as well
+        leases.check(minutes(61));
+        leases.check(minutes(111));
+        leases.check(minutes(120));
+    }
+
+    #[test]
+    fn start_lease_of_unknown_hosts_when_first_seen() {
+        let mut mock = MockProvider::default();
+        mock.expect_get_record_set()
+            .withf(|d, _| d.host == "example.com")
+            .times(2)
+            .returning(|_, _| Ok(None));
+        mock.expect_delete_record_set().never();
+
+        let history = History::new(&HistoryConfig::default()).unwrap();
+        let lease = LeaseConfig {
+            duration: 3600,
+            fallback_ipv4: None,
+            fallback_ipv6: None,
+        };
+        let mut leases = leases(mock, Some(lease), &history, &KeyedEntries::default());
+        leases.check(minutes(0));
+        leases.check(minutes(59));
+        leases.check(minutes(60));
+        leases.check(minutes(90));
+    }
+
+    #[test]
+    fn remove_expired_keyed_entries() {
+        let mut mock = MockProvider::default();
+        mock.expect_upsert_record_set()
+            .withf(|d, r| d.host == "www.example.com" && *r == a_record(&["192.0.2.2"]))
+            .once()
+            .returning(|_, _| Ok(UpdateStatus::Updated { previous: None }));
+        mock.expect_delete_record_set()
+            .withf(|d, record_type| d.host == "www.example.com" && *record_type == RecordType::A)
+            .once()
+            .returning(|_, _| Ok(()));
+
+        let keyed_entries = KeyedEntries::default();
+        let host = "www.example.com";
+        keyed_entries.set(
+            "test",
+            host,
+            RecordType::A,
+            "uplink1",
+            ip("192.0.2.1"),
+            minutes(60),
+            START,
+        );
+        keyed_entries.set(
+            "test",
+            host,
+            RecordType::A,
+            "uplink2",
+            ip("192.0.2.2"),
+            minutes(90),
+            START,
+        );
+
+        let history = History::new(&HistoryConfig::default()).unwrap();
+        let mut leases = leases(mock, None, &history, &keyed_entries);
+        leases.check(minutes(30));
+        leases.check(minutes(60));
+        leases.check(minutes(90));
+    }
+
+    #[test]
+    fn expire_keyed_entries_of_owning_user() {
+        let mut mock = MockProvider::default();
+        mock.expect_delete_record_set()
+            .withf(|d, record_type| {
+                d.host == "www.example.com"
+                    && d.zone.id == "otherzoneid"
+                    && *record_type == RecordType::A
+            })
+            .once()
+            .returning(|_, _| Ok(()));
+
+        // Another user with a host of the same name in a different zone
+        let mut config = test_config();
+        let mut other = config.users[0].clone();
+        other.name = "other".to_string();
+        other.domains[1].zone.id = "otherzoneid".to_string();
+        config.users.push(other);
+
+        let keyed_entries = KeyedEntries::default();
+        keyed_entries.set(
+            "other",
+            "www.example.com",
+            RecordType::A,
+            "uplink1",
+            ip("192.0.2.1"),
+            minutes(60),
+            START,
+        );
+
+        let mut leases = Leases::new(
+            ConfigStore::fixed(config),
+            Providers {
+                hetzner_provider: None,
+                rfc2136_provider: None,
+                mock_provider: Some(mock),
+            },
+            Notifier::new(&NotificationConfig::default()).unwrap(),
+            History::new(&HistoryConfig::default()).unwrap(),
+            keyed_entries,
+        );
+        leases.check(minutes(60));
+    }
+}
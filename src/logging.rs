@@ -0,0 +1,231 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt::Display,
+    io::{stdout, Write},
+};
+
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use simple_logger::SimpleLogger;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+#[derive(PartialEq, Eq, Deserialize, Clone, Copy, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines as printed by `SimpleLogger`
+    #[default]
+    Text,
+    /// One JSON object per line, including the fields of the current request
+    Json,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct LoggingConfig {
+    #[serde(default)]
+    pub format: LogFormat,
+    #[serde(default = "default_level")]
+    pub level: LevelFilter,
+}
+
+fn default_level() -> LevelFilter {
+    LevelFilter::Warn
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            format: LogFormat::default(),
+            level: default_level(),
+        }
+    }
+}
+
+/// Fields that are attached to every log line emitted while handling a request
+struct RequestContext {
+    request_id: String,
+    fields: BTreeMap<&'static str, String>,
+}
+
+thread_local! {
+    static CONTEXT: RefCell<Option<RequestContext>> = const { RefCell::new(None) };
+}
+
+/// Removes the request context of the current thread when dropped
+pub struct ContextGuard {
+    _private: (),
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        CONTEXT.with(|c| c.borrow_mut().take());
+    }
+}
+
+/// Start a new request context with a fresh request ID on the current thread
+///
+/// Handlers are synchronous and providers block in place, so every log line
+/// of a request is emitted on the thread that entered the context.
+#[must_use]
+pub fn enter_request() -> ContextGuard {
+    let request_id = format!("{:016x}", rand::rng().random::<u64>());
+
+    CONTEXT.with(|c| {
+        *c.borrow_mut() = Some(RequestContext {
+            request_id,
+            fields: BTreeMap::new(),
+        });
+    });
+
+    ContextGuard { _private: () }
+}
+
+/// Attach a field to all following log lines of the current request
+pub fn set_field(key: &'static str, value: impl Display) {
+    CONTEXT.with(|c| {
+        if let Some(context) = c.borrow_mut().as_mut() {
+            context.fields.insert(key, value.to_string());
+        }
+    });
+}
+
+struct JsonLogger {
+    level: LevelFilter,
+}
+
+fn format_json(timestamp: OffsetDateTime, record: &Record) -> String {
+    let mut line = Map::new();
+
+    line.insert(
+        "timestamp".to_string(),
+        Value::from(timestamp.format(&Rfc3339).unwrap_or_default()),
+    );
+    line.insert("level".to_string(), Value::from(record.level().as_str()));
+    line.insert("target".to_string(), Value::from(record.target()));
+    line.insert(
+        "message".to_string(),
+        Value::from(record.args().to_string()),
+    );
+
+    CONTEXT.with(|c| {
+        if let Some(context) = c.borrow().as_ref() {
+            line.insert(
+                "request_id".to_string(),
+                Value::from(context.request_id.as_str()),
+            );
+            for (key, value) in &context.fields {
+                line.insert((*key).to_string(), Value::from(value.as_str()));
+            }
+        }
+    });
+
+    Value::Object(line).to_string()
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = format_json(OffsetDateTime::now_utc(), record);
+        let _ = writeln!(stdout().lock(), "{line}");
+    }
+
+    fn flush(&self) {
+        let _ = stdout().flush();
+    }
+}
+
+/// Install the global logger according to the configuration
+pub fn init(config: &LoggingConfig) -> Result<(), SetLoggerError> {
+    match config.format {
+        LogFormat::Text => SimpleLogger::new().with_level(config.level).init(),
+        LogFormat::Json => {
+            log::set_boxed_logger(Box::new(JsonLogger {
+                level: config.level,
+            }))?;
+            log::set_max_level(config.level);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use log::Level;
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn format(message: &str) -> Value {
+        let line = format_json(
+            datetime!(2026-01-02 03:04:05 UTC),
+            &Record::builder()
+                .args(format_args!("{message}"))
+                .level(Level::Info)
+                .target("dyndns::server")
+                .build(),
+        );
+
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[test]
+    fn json_without_request() {
+        let line = format("Created new Hetzner Provider");
+
+        assert_eq!(line["timestamp"], "2026-01-02T03:04:05Z");
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["target"], "dyndns::server");
+        assert_eq!(line["message"], "Created new Hetzner Provider");
+        assert!(line.get("request_id").is_none());
+    }
+
+    #[test]
+    fn json_with_request_fields() {
+        let guard = enter_request();
+        set_field("user", "test");
+        set_field("host", "example.com");
+
+        let first = format("first");
+        set_field("record_type", "A");
+        let second = format("second");
+
+        assert!(first["request_id"].is_string());
+        assert_eq!(first["request_id"], second["request_id"]);
+        assert_eq!(second["user"], "test");
+        assert_eq!(second["host"], "example.com");
+        assert!(first.get("record_type").is_none());
+        assert_eq!(second["record_type"], "A");
+
+        drop(guard);
+        assert!(format("third").get("request_id").is_none());
+    }
+
+    #[test]
+    fn request_ids_differ() {
+        let first = {
+            let _guard = enter_request();
+            format("first")["request_id"].clone()
+        };
+        let second = {
+            let _guard = enter_request();
+            format("second")["request_id"].clone()
+        };
+
+        assert_ne!(first, second);
+    }
+}
@@ -9,14 +9,43 @@ use std::{
     fs::File,
     io::BufReader,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
-use config::{Config, Providers};
-use providers::hetzner::HetznerProvider;
-use simple_logger::SimpleLogger;
+use config::{store::ConfigStore, Config, Providers};
+use history::History;
+use keyed::KeyedEntries;
+use leases::Leases;
+use metrics::Metrics;
+use notifications::Notifier;
+use reconcile::Reconciler;
+use ui::Sessions;
+mod acme;
+mod acme_dns;
+mod address_policy;
+mod admin;
+mod api;
+mod auth;
+mod cli;
+mod client;
 mod config;
+mod dns;
+mod history;
+mod httpreq;
+mod ip;
+mod keyed;
+mod leases;
+mod logging;
+mod metrics;
+mod notifications;
 pub mod providers;
+mod reconcile;
+mod security;
 mod server;
+mod tls;
+mod ui;
+mod update;
+mod verification;
 
 /// Find first path for the configuration where a file is present
 fn find_config() -> Option<PathBuf> {
@@ -31,21 +60,43 @@ fn find_config() -> Option<PathBuf> {
     None
 }
 
-// @TODO this should read in a config file containing multiple users
-// For now, this will not work, but all other functions expect a Vec of users,
-// therefore this will be packed up into an array of exactly one user.
-fn load_config(path: &Path) -> Result<config::User, Box<dyn Error>> {
+/// Read the configuration file
+///
+/// The file either contains the whole configuration with a list of users,
+/// or, as in earlier versions, exactly one user.
+fn load_config(path: &Path) -> Result<Config, Box<dyn Error>> {
     let reader = BufReader::new(File::open(path)?);
 
-    Ok(serde_json::from_reader(reader)?)
+    parse_config(serde_json::from_reader(reader)?)
+}
+
+/// Parse and validate the content of a configuration file
+fn parse_config(value: serde_json::Value) -> Result<Config, Box<dyn Error>> {
+    let config = if value.get("users").is_some() {
+        serde_json::from_value(value)?
+    } else {
+        let user: config::User = serde_json::from_value(value)?;
+        Config {
+            users: vec![user],
+            ..Default::default()
+        }
+    };
+
+    config.validate()?;
+
+    Ok(config)
 }
 
 #[tokio::main]
-pub async fn main() -> Result<(), rocket::Error> {
-    SimpleLogger::new()
-        .with_level(log::LevelFilter::Warn)
-        .init()
-        .unwrap();
+pub async fn main() -> Result<(), Box<rocket::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(error) = cli::main(&args) {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
     let path = match find_config() {
         Some(path) => path,
@@ -53,20 +104,121 @@ pub async fn main() -> Result<(), rocket::Error> {
     };
 
     let config = match load_config(&path) {
-        Ok(user) => Config { users: vec![user] },
+        Ok(config) => config,
         Err(error) => panic!("Error reading config: {error}"),
     };
 
-    let providers = Providers {
-        hetzner_provider: Some(HetznerProvider::new()),
-        mock_provider: None,
+    logging::init(&config.logging).unwrap();
+
+    let notifier = match Notifier::new(&config.notifications) {
+        Ok(notifier) => notifier,
+        Err(error) => panic!("Error setting up notifications: {error}"),
+    };
+
+    let history = match History::new(&config.history) {
+        Ok(history) => history,
+        Err(error) => panic!("Error reading history: {error}"),
+    };
+
+    let figment = match &config.tls {
+        Some(tls) => tls::figment(tls),
+        None => rocket::Config::figment(),
+    };
+    let server_config = rocket::Config::from(&figment);
+    let tls_config = config.tls.clone();
+    let redirect_listener = tls_config
+        .as_ref()
+        .and_then(|tls| tls::redirect_listener(tls, server_config.address, server_config.port));
+
+    let admin_listener = config.admin.as_ref().and_then(admin::listener);
+    let mount_admin = config.admin.is_some() && admin_listener.is_none();
+
+    // Changes made through the admin API are written back to the file and applied to both servers
+    let store = ConfigStore::new(config, path);
+    let metrics = Metrics::default();
+    let keyed_entries = KeyedEntries::default();
+    let sessions = Sessions::default();
+
+    if let Some((tls, acme)) = tls_config
+        .as_ref()
+        .and_then(|tls| Some((tls, tls.acme.as_ref()?)))
+    {
+        // The server cannot start without a certificate, later renewals happen in the background
+        if !tls.certs.exists() {
+            if let Err(error) = acme::obtain(acme, tls, &store.get(), &Providers::new()).await {
+                panic!("Error obtaining certificate: {error}");
+            }
+        }
+
+        tokio::spawn(acme::renew(
+            acme.clone(),
+            tls.clone(),
+            store.clone(),
+            Providers::new(),
+        ));
+    }
+
+    tokio::spawn(leases::watch(Leases::new(
+        store.clone(),
+        Providers::new(),
+        notifier.clone(),
+        history.clone(),
+        keyed_entries.clone(),
+    )));
+
+    if let Some(reconcile) = store.get().reconcile.clone() {
+        tokio::spawn(reconcile::watch(
+            Reconciler::new(
+                store.clone(),
+                Providers::new(),
+                history.clone(),
+                metrics.clone(),
+            ),
+            Duration::from_secs(reconcile.interval_secs),
+        ));
+    }
+
+    // The server is built again when it is restarted for a new certificate, sharing the state
+    let build = || {
+        let mut server = server::rocket(figment.clone());
+        if mount_admin {
+            server = admin::mount(server);
+        }
+
+        server
+            .manage(store.clone())
+            .manage(Providers::new())
+            .manage(notifier.clone())
+            .manage(metrics.clone())
+            .manage(history.clone())
+            .manage(keyed_entries.clone())
+            .manage(sessions.clone())
+    };
+
+    let server = async {
+        match &tls_config {
+            Some(tls) => tls::serve(tls, build).await,
+            None => build().launch().await.map(drop),
+        }
+    };
+    let admin_listener = async {
+        match admin_listener {
+            Some(admin_listener) => admin_listener
+                .manage(store.clone())
+                .launch()
+                .await
+                .map(drop),
+            None => Ok(()),
+        }
+    };
+    let redirect_listener = async {
+        match redirect_listener {
+            Some(redirect_listener) => redirect_listener.launch().await.map(drop),
+            None => Ok(()),
+        }
     };
 
-    server::rocket()
-        .manage(config)
-        .manage(providers)
-        .launch()
-        .await?;
+    tokio::try_join!(server, admin_listener, redirect_listener)?;
 
     Ok(())
 }
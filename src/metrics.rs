@@ -0,0 +1,124 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+};
+
+use rocket::{get, State};
+
+type Labels = Vec<(&'static str, String)>;
+/// Help text and values by labels of a metric
+type Metric = (&'static str, BTreeMap<Labels, f64>);
+
+/// Counters and sums exposed in the Prometheus text format
+///
+/// Clones share their values.
+#[derive(Default, Clone)]
+pub struct Metrics {
+    values: Arc<Mutex<BTreeMap<&'static str, Metric>>>,
+}
+
+impl Metrics {
+    fn add(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)],
+        value: f64,
+    ) {
+        let labels = labels
+            .iter()
+            .map(|(key, value)| (*key, (*value).to_string()))
+            .collect();
+
+        let mut values = self.values.lock().unwrap();
+        let (_, series) = values
+            .entry(name)
+            .or_insert_with(|| (help, BTreeMap::new()));
+        *series.entry(labels).or_insert(0.0) += value;
+    }
+
+    /// Increase a counter by one
+    pub fn increment(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)],
+    ) {
+        self.add(name, help, labels, 1.0);
+    }
+
+    /// Increase a counter by an arbitrary amount, e.g. a duration in seconds
+    pub fn increase(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)],
+        value: f64,
+    ) {
+        self.add(name, help, labels, value);
+    }
+
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+
+        for (name, (help, series)) in self.values.lock().unwrap().iter() {
+            let _ = writeln!(output, "# HELP {name} {help}");
+            let _ = writeln!(output, "# TYPE {name} counter");
+
+            for (labels, value) in series {
+                let labels = labels
+                    .iter()
+                    .map(|(key, value)| format!("{key}={value:?}"))
+                    .collect::<Vec<_>>()
+                    .join(",");
+
+                if labels.is_empty() {
+                    let _ = writeln!(output, "{name} {value}");
+                } else {
+                    let _ = writeln!(output, "{name}{{{labels}}} {value}");
+                }
+            }
+        }
+
+        output
+    }
+}
+
+#[get("/metrics")]
+pub fn metrics(metrics: &State<Metrics>) -> String {
+    metrics.render()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_counters() {
+        let metrics = Metrics::default();
+
+        metrics.increment("dyndns_updates_total", "Updates", &[("result", "updated")]);
+        metrics.increment("dyndns_updates_total", "Updates", &[("result", "updated")]);
+        metrics.increment("dyndns_updates_total", "Updates", &[("result", "failed")]);
+        metrics.increase("dyndns_seconds_total", "Seconds", &[], 1.5);
+
+        assert_eq!(
+            metrics.render(),
+            "# HELP dyndns_seconds_total Seconds\n\
+             # TYPE dyndns_seconds_total counter\n\
+             dyndns_seconds_total 1.5\n\
+             # HELP dyndns_updates_total Updates\n\
+             # TYPE dyndns_updates_total counter\n\
+             dyndns_updates_total{result=\"failed\"} 1\n\
+             dyndns_updates_total{result=\"updated\"} 2\n"
+        );
+    }
+}
@@ -0,0 +1,372 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+use std::{error::Error, net::IpAddr, sync::Arc, time::Duration};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use smtp::{SmtpConfig, SmtpNotifier};
+
+use crate::providers::RecordType;
+
+pub mod smtp;
+
+#[derive(PartialEq, Eq, Deserialize, Serialize, Clone, Copy, Debug, strum_macros::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum EventKind {
+    /// The address of a record has actually been changed
+    IpChanged,
+    /// The record already held the requested address
+    IpUnchanged,
+    /// Updating a record failed
+    UpdateFailed,
+    /// No update arrived within the lease of a host, so its record was deleted or set to the fallback
+    LeaseExpired,
+}
+
+fn default_events() -> Vec<EventKind> {
+    vec![
+        EventKind::IpChanged,
+        EventKind::UpdateFailed,
+        EventKind::LeaseExpired,
+    ]
+}
+
+fn default_retries() -> u32 {
+    5
+}
+
+fn default_retry_delay_ms() -> u64 {
+    1000
+}
+
+#[derive(Deserialize, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Events that are sent to this webhook, all but `IpUnchanged` by default
+    #[serde(default = "default_events")]
+    pub events: Vec<EventKind>,
+    /// Number of retries after the first failed attempt
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    /// Delay before the first retry, doubled with every further retry
+    #[serde(default = "default_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+}
+
+#[derive(Deserialize, Clone, Default)]
+pub struct NotificationConfig {
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    pub smtp: Option<SmtpConfig>,
+}
+
+/// Payload that is posted to webhooks
+#[derive(Serialize, Clone, Debug)]
+pub struct Notification {
+    pub event: EventKind,
+    pub user: String,
+    pub host: String,
+    pub record_type: String,
+    pub old_ip: Option<IpAddr>,
+    /// Address the record holds now, missing if it has been deleted
+    pub new_ip: Option<IpAddr>,
+    pub result: String,
+    /// Human-readable summary, which is also the field shown by Slack-compatible webhooks
+    pub text: String,
+}
+
+impl Notification {
+    #[must_use]
+    pub fn ip_changed(user: &str, host: &str, old_ip: Option<IpAddr>, new_ip: IpAddr) -> Self {
+        let old = old_ip.map_or_else(|| "<unknown>".to_string(), |ip| ip.to_string());
+
+        Notification {
+            event: EventKind::IpChanged,
+            user: user.to_string(),
+            host: host.to_string(),
+            record_type: record_type(new_ip),
+            old_ip,
+            new_ip: Some(new_ip),
+            result: "updated".to_string(),
+            text: format!("{host} changed from {old} to {new_ip}"),
+        }
+    }
+
+    #[must_use]
+    pub fn ip_unchanged(user: &str, host: &str, ip: IpAddr) -> Self {
+        Notification {
+            event: EventKind::IpUnchanged,
+            user: user.to_string(),
+            host: host.to_string(),
+            record_type: record_type(ip),
+            old_ip: Some(ip),
+            new_ip: Some(ip),
+            result: "unchanged".to_string(),
+            text: format!("{host} is already set to {ip}"),
+        }
+    }
+
+    #[must_use]
+    pub fn update_failed(user: &str, host: &str, new_ip: IpAddr, error: &str) -> Self {
+        Notification {
+            event: EventKind::UpdateFailed,
+            user: user.to_string(),
+            host: host.to_string(),
+            record_type: record_type(new_ip),
+            old_ip: None,
+            new_ip: Some(new_ip),
+            result: error.to_string(),
+            text: format!("Updating {host} to {new_ip} failed: {error}"),
+        }
+    }
+
+    #[must_use]
+    pub fn lease_expired(
+        user: &str,
+        host: &str,
+        record_type: RecordType,
+        old_ip: Option<IpAddr>,
+        fallback: Option<IpAddr>,
+    ) -> Self {
+        let old = old_ip.map_or_else(|| "<unknown>".to_string(), |ip| ip.to_string());
+
+        Notification {
+            event: EventKind::LeaseExpired,
+            user: user.to_string(),
+            host: host.to_string(),
+            record_type: record_type.to_string(),
+            old_ip,
+            new_ip: fallback,
+            result: if fallback.is_some() {
+                "fallback"
+            } else {
+                "deleted"
+            }
+            .to_string(),
+            text: match fallback {
+                Some(fallback) => {
+                    format!("Lease of {host} expired, changed from {old} to {fallback}")
+                }
+                None => format!("Lease of {host} expired, removed {old}"),
+            },
+        }
+    }
+}
+
+fn record_type(ip: IpAddr) -> String {
+    if ip.is_ipv4() { "A" } else { "AAAA" }.to_string()
+}
+
+/// Sends notifications about updates to all configured targets
+///
+/// Clones share the state of the mail notifications.
+#[derive(Clone)]
+pub struct Notifier {
+    config: NotificationConfig,
+    client: reqwest::Client,
+    smtp: Option<Arc<SmtpNotifier>>,
+}
+
+impl Notifier {
+    pub fn new(config: &NotificationConfig) -> Result<Notifier, Box<dyn Error>> {
+        Ok(Notifier {
+            config: config.clone(),
+            client: reqwest::Client::new(),
+            smtp: config
+                .smtp
+                .as_ref()
+                .map(SmtpNotifier::new)
+                .transpose()?
+                .map(Arc::new),
+        })
+    }
+
+    /// Report the result of an update, sending notifications in the background
+    ///
+    /// This has to be called from within the Tokio runtime, which is the case for request handlers.
+    pub fn notify(&self, notification: &Notification) {
+        if let Some(smtp) = &self.smtp {
+            smtp.notify(notification);
+        }
+
+        for webhook in &self.config.webhooks {
+            if !webhook.events.contains(&notification.event) {
+                continue;
+            }
+
+            let client = self.client.clone();
+            let webhook = webhook.clone();
+            let notification = notification.clone();
+
+            tokio::spawn(async move {
+                let _ = send_webhook(&client, &webhook, &notification).await;
+            });
+        }
+    }
+}
+
+/// Post a notification to a webhook, retrying with exponential backoff
+async fn send_webhook(
+    client: &reqwest::Client,
+    webhook: &WebhookConfig,
+    notification: &Notification,
+) -> Result<(), ()> {
+    let mut delay = Duration::from_millis(webhook.retry_delay_ms);
+
+    for attempt in 0..=webhook.retries {
+        if attempt > 0 {
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+
+        match client.post(&webhook.url).json(notification).send().await {
+            Ok(response) if response.status().is_success() => {
+                info!(
+                    "Sent {} notification for {} to webhook {}",
+                    notification.event, notification.host, webhook.url
+                );
+                return Ok(());
+            }
+            Ok(response) => warn!(
+                "Webhook {} responded with {} (attempt {} of {})",
+                webhook.url,
+                response.status(),
+                attempt + 1,
+                webhook.retries + 1
+            ),
+            Err(error) => warn!(
+                "Error sending to webhook {}: {error} (attempt {} of {})",
+                webhook.url,
+                attempt + 1,
+                webhook.retries + 1
+            ),
+        }
+    }
+
+    warn!(
+        "Giving up sending {} notification for {} to webhook {}",
+        notification.event, notification.host, webhook.url
+    );
+    Err(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::{Ipv4Addr, TcpListener},
+        sync::mpsc,
+        thread,
+    };
+
+    use super::*;
+
+    /// Accept `statuses.len()` requests, answering them with the given status codes,
+    /// and forward the received bodies
+    fn listen(statuses: Vec<u16>) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                sender.send(String::from_utf8(body).unwrap()).unwrap();
+
+                let _ = write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {status} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                );
+            }
+        });
+
+        (url, receiver)
+    }
+
+    fn webhook(url: String, retries: u32) -> WebhookConfig {
+        WebhookConfig {
+            url,
+            events: default_events(),
+            retries,
+            retry_delay_ms: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn send_ip_changed() {
+        let (url, bodies) = listen(vec![200]);
+        let notification = Notification::ip_changed(
+            "test",
+            "example.com",
+            Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))),
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)),
+        );
+
+        let result = send_webhook(&reqwest::Client::new(), &webhook(url, 0), &notification).await;
+        assert_eq!(result, Ok(()));
+
+        let body: serde_json::Value = serde_json::from_str(&bodies.recv().unwrap()).unwrap();
+        assert_eq!(body["event"], "ip_changed");
+        assert_eq!(body["host"], "example.com");
+        assert_eq!(body["record_type"], "A");
+        assert_eq!(body["old_ip"], "192.0.2.1");
+        assert_eq!(body["new_ip"], "192.0.2.2");
+        assert_eq!(
+            body["text"],
+            "example.com changed from 192.0.2.1 to 192.0.2.2"
+        );
+    }
+
+    #[tokio::test]
+    async fn retry_after_error() {
+        let (url, bodies) = listen(vec![500, 503, 200]);
+        let notification = Notification::update_failed(
+            "test",
+            "example.com",
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)),
+            "Error: Record not found",
+        );
+
+        let result = send_webhook(&reqwest::Client::new(), &webhook(url, 2), &notification).await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(bodies.iter().take(3).count(), 3);
+    }
+
+    #[tokio::test]
+    async fn give_up_after_retries() {
+        let (url, _bodies) = listen(vec![500, 500]);
+        let notification = Notification::update_failed(
+            "test",
+            "example.com",
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)),
+            "Error: Record not found",
+        );
+
+        let result = send_webhook(&reqwest::Client::new(), &webhook(url, 1), &notification).await;
+        assert_eq!(result, Err(()));
+    }
+}
@@ -0,0 +1,337 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+use std::{collections::HashMap, error::Error, sync::Mutex};
+
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+use log::{info, warn};
+use serde::Deserialize;
+
+use super::{EventKind, Notification};
+
+#[derive(PartialEq, Eq, Deserialize, Clone, Copy, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Connect in plain text and upgrade the connection using STARTTLS (usually port 587)
+    #[default]
+    Starttls,
+    /// Connect using TLS right away (usually port 465)
+    Tls,
+    /// Do not encrypt the connection at all, only useful for local mail servers
+    None,
+}
+
+fn default_failure_threshold() -> u32 {
+    3
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SmtpConfig {
+    pub server: String,
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// Number of failed updates in a row after which a mail is sent
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+}
+
+/// Mail that has to be sent after an update result
+#[derive(PartialEq, Eq, Debug)]
+enum Alert {
+    Failing { failures: u32 },
+    Recovered { failures: u32 },
+}
+
+/// Counts failed updates in a row per host and record type
+struct FailureTracker {
+    threshold: u32,
+    failures: Mutex<HashMap<(String, String), u32>>,
+}
+
+impl FailureTracker {
+    fn new(threshold: u32) -> Self {
+        FailureTracker {
+            threshold: threshold.max(1),
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn record(&self, notification: &Notification) -> Option<Alert> {
+        let key = (notification.host.clone(), notification.record_type.clone());
+        let mut failures = self.failures.lock().unwrap();
+
+        match notification.event {
+            EventKind::UpdateFailed => {
+                let count = failures.entry(key).or_insert(0);
+                *count += 1;
+
+                // Only send one mail when the threshold is reached, not for every further failure
+                (*count == self.threshold).then_some(Alert::Failing { failures: *count })
+            }
+            EventKind::IpChanged | EventKind::IpUnchanged => {
+                let count = failures.remove(&key).unwrap_or(0);
+
+                (count >= self.threshold).then_some(Alert::Recovered { failures: count })
+            }
+            // Not the result of an update, so it neither fails nor recovers the host
+            EventKind::LeaseExpired => None,
+        }
+    }
+}
+
+/// Sends mails when updates of a host keep failing and when they succeed again
+pub struct SmtpNotifier {
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    tracker: FailureTracker,
+}
+
+impl SmtpNotifier {
+    pub fn new(config: &SmtpConfig) -> Result<SmtpNotifier, Box<dyn Error>> {
+        let mut builder = match config.security {
+            SmtpSecurity::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.server)?
+            }
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.server)?,
+            SmtpSecurity::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.server)
+            }
+        };
+
+        if let Some(port) = config.port {
+            builder = builder.port(port);
+        }
+
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(SmtpNotifier {
+            from: config.from.parse()?,
+            to: config
+                .to
+                .iter()
+                .map(|to| to.parse())
+                .collect::<Result<_, _>>()?,
+            transport: builder.build(),
+            tracker: FailureTracker::new(config.failure_threshold),
+        })
+    }
+
+    fn compose(
+        &self,
+        notification: &Notification,
+        alert: &Alert,
+    ) -> Result<Message, Box<dyn Error>> {
+        let name = format!("{} ({})", notification.host, notification.record_type);
+        let address = notification
+            .new_ip
+            .map_or_else(|| "-".to_string(), |ip| ip.to_string());
+
+        let (subject, body) = match alert {
+            Alert::Failing { failures } => (
+                format!("[dyndns] Updating {name} failed"),
+                format!(
+                    "Updating {name} to {address} failed {failures} times in a row.\n\nLast error: {}\n",
+                    notification.result
+                ),
+            ),
+            Alert::Recovered { failures } => (
+                format!("[dyndns] {name} updated again"),
+                format!(
+                    "{name} has been updated successfully after {failures} failed attempts.\n\nCurrent address: {address}\n"
+                ),
+            ),
+        };
+
+        let mut message = Message::builder().from(self.from.clone()).subject(subject);
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+
+        Ok(message.body(body)?)
+    }
+
+    /// Record the result of an update and send a mail in the background if necessary
+    pub fn notify(&self, notification: &Notification) {
+        let Some(alert) = self.tracker.record(notification) else {
+            return;
+        };
+
+        let message = match self.compose(notification, &alert) {
+            Ok(message) => message,
+            Err(error) => {
+                warn!("Error composing mail for {}: {error}", notification.host);
+                return;
+            }
+        };
+
+        let transport = self.transport.clone();
+        let host = notification.host.clone();
+
+        tokio::spawn(async move {
+            let _ = send(&transport, message, &host).await;
+        });
+    }
+}
+
+async fn send(
+    transport: &AsyncSmtpTransport<Tokio1Executor>,
+    message: Message,
+    host: &str,
+) -> Result<(), ()> {
+    match transport.send(message).await {
+        Ok(_) => {
+            info!("Sent notification mail for {host}");
+            Ok(())
+        }
+        Err(error) => {
+            warn!("Error sending notification mail for {host}: {error}");
+            Err(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::{IpAddr, Ipv4Addr, TcpListener},
+        sync::mpsc,
+        thread,
+    };
+
+    use super::*;
+
+    fn failed() -> Notification {
+        Notification::update_failed(
+            "test",
+            "example.com",
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)),
+            "Error: Record not found",
+        )
+    }
+
+    fn changed() -> Notification {
+        Notification::ip_changed(
+            "test",
+            "example.com",
+            None,
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)),
+        )
+    }
+
+    #[test]
+    fn alert_once_at_threshold() {
+        let tracker = FailureTracker::new(2);
+
+        assert_eq!(tracker.record(&failed()), None);
+        assert_eq!(
+            tracker.record(&failed()),
+            Some(Alert::Failing { failures: 2 })
+        );
+        assert_eq!(tracker.record(&failed()), None);
+        assert_eq!(
+            tracker.record(&changed()),
+            Some(Alert::Recovered { failures: 3 })
+        );
+        assert_eq!(tracker.record(&changed()), None);
+    }
+
+    #[test]
+    fn no_recovery_below_threshold() {
+        let tracker = FailureTracker::new(3);
+
+        assert_eq!(tracker.record(&failed()), None);
+        assert_eq!(tracker.record(&failed()), None);
+        assert_eq!(tracker.record(&changed()), None);
+        assert_eq!(tracker.record(&failed()), None);
+    }
+
+    /// Minimal SMTP server that accepts a single mail and forwards its content
+    fn smtp_sink() -> (u16, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let _ = write!(reader.get_mut(), "220 localhost ESMTP\r\n");
+
+            let mut data = String::new();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+
+                let reply = if in_data {
+                    if line == ".\r\n" {
+                        in_data = false;
+                        "250 OK\r\n"
+                    } else {
+                        data.push_str(&line);
+                        continue;
+                    }
+                } else if line.starts_with("EHLO") {
+                    "250 localhost\r\n"
+                } else if line.starts_with("DATA") {
+                    in_data = true;
+                    "354 End data with <CR><LF>.<CR><LF>\r\n"
+                } else if line.starts_with("QUIT") {
+                    let _ = write!(reader.get_mut(), "221 Bye\r\n");
+                    break;
+                } else {
+                    "250 OK\r\n"
+                };
+                let _ = write!(reader.get_mut(), "{reply}");
+            }
+
+            sender.send(data).unwrap();
+        });
+
+        (port, receiver)
+    }
+
+    #[tokio::test]
+    async fn send_failure_mail() {
+        let (port, mails) = smtp_sink();
+        let notifier = SmtpNotifier::new(&SmtpConfig {
+            server: "127.0.0.1".to_string(),
+            port: Some(port),
+            security: SmtpSecurity::None,
+            username: None,
+            password: None,
+            from: "dyndns <dyndns@example.com>".to_string(),
+            to: vec!["admin@example.com".to_string()],
+            failure_threshold: 1,
+        })
+        .unwrap();
+
+        let alert = notifier.tracker.record(&failed()).unwrap();
+        let message = notifier.compose(&failed(), &alert).unwrap();
+        assert_eq!(
+            send(&notifier.transport, message, "example.com").await,
+            Ok(())
+        );
+
+        let mail = mails.recv().unwrap();
+        assert!(mail.contains("Subject: [dyndns] Updating example.com (A) failed"));
+        assert!(mail.contains("To: admin@example.com"));
+        assert!(mail.contains("Last error: Error: Record not found"));
+    }
+}
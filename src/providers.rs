@@ -8,13 +8,19 @@ use std::{
     error::Error,
     fmt::Display,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::Mutex,
 };
 
 use mockall::automock;
+use serde::{Deserialize, Serialize};
 
-use crate::config::DomainConfig;
+use crate::{
+    config::{DomainConfig, ProviderType},
+    logging,
+};
 
 pub mod hetzner;
+pub mod rfc2136;
 
 #[derive(Debug)]
 pub struct RecordNotFoundError;
@@ -27,49 +33,340 @@ impl Display for RecordNotFoundError {
     }
 }
 
+#[derive(Debug)]
+pub struct ProviderNotConfiguredError(pub ProviderType);
+
+impl Error for ProviderNotConfiguredError {}
+
+impl Display for ProviderNotConfiguredError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Provider {} is not available", self.0)
+    }
+}
+
+#[derive(Debug)]
+pub struct UnsupportedRecordTypeError(pub RecordType);
+
+impl Error for UnsupportedRecordTypeError {}
+
+impl Display for UnsupportedRecordTypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Records of type {} are not supported", self.0)
+    }
+}
+
+#[derive(
+    PartialEq, Eq, Hash, Debug, Clone, Copy, Deserialize, Serialize, strum_macros::Display,
+)]
+#[allow(clippy::min_ident_chars)]
+pub enum RecordType {
+    A,
+    AAAA,
+    NS,
+    MX,
+    CNAME,
+    RP,
+    TXT,
+    SOA,
+    HINFO,
+    SRV,
+    DANE,
+    TLSA,
+    DS,
+    CAA,
+}
+
+/// All records of one type of a host
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct RecordSet {
+    pub record_type: RecordType,
+    /// Values in the presentation format, e.g. `10 mail.example.com.` for MX, but without the quotes of TXT
+    pub values: Vec<String>,
+    /// TTL of the records, the default of the provider if not set
+    pub ttl: Option<u64>,
+}
+
+/// Outcome of a successful call to [`Provider::update_ip`]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum UpdateStatus {
+    /// The record has been changed; `previous` holds its old value if it was an IP address
+    Updated { previous: Option<IpAddr> },
+    /// The record already held the requested value
+    Unchanged,
+    /// The record already held the requested address, only its TTL has been changed
+    TtlUpdated,
+}
+
+impl UpdateStatus {
+    /// Human-readable description of the status for a given address
+    #[must_use]
+    pub fn describe(&self, new_ip: &IpAddr) -> String {
+        let family = if new_ip.is_ipv4() { "IPv4" } else { "IPv6" };
+
+        match self {
+            UpdateStatus::Updated { .. } => format!("Updated {family} successfully"),
+            UpdateStatus::Unchanged => format!("{family} already set correctly"),
+            UpdateStatus::TtlUpdated => format!("Updated TTL of {family} successfully"),
+        }
+    }
+}
+
 #[automock]
 pub trait Provider {
+    /// Set the A or AAAA record of a host to `new_ip`, and its TTL to `ttl` if given
     fn update_ip(
         &self,
         domain_config: &DomainConfig,
         new_ip: IpAddr,
-    ) -> Result<bool, Box<dyn Error>>;
+        ttl: Option<u64>,
+    ) -> Result<UpdateStatus, Box<dyn Error>>;
+
+    /// Current values of the A and AAAA records of a host
+    fn get_ips(&self, domain_config: &DomainConfig) -> Result<Vec<IpAddr>, Box<dyn Error>>;
+
+    /// Record types that can be managed with the record set methods
+    fn record_types(&self) -> &'static [RecordType];
+
+    /// Records of type `record_type` of the host of `domain_config`, if there are any
+    fn get_record_set(
+        &self,
+        domain_config: &DomainConfig,
+        record_type: RecordType,
+    ) -> Result<Option<RecordSet>, Box<dyn Error>>;
+
+    /// Replace the records of the type of `record_set` of the host of `domain_config`, creating them if necessary
+    fn upsert_record_set(
+        &self,
+        domain_config: &DomainConfig,
+        record_set: &RecordSet,
+    ) -> Result<UpdateStatus, Box<dyn Error>>;
+
+    /// Delete all records of type `record_type` of the host of `domain_config`
+    fn delete_record_set(
+        &self,
+        domain_config: &DomainConfig,
+        record_type: RecordType,
+    ) -> Result<(), Box<dyn Error>>;
+
+    /// Hostnames of the authoritative nameservers of the zone of `domain_config`
+    fn nameservers(&self, domain_config: &DomainConfig) -> Result<Vec<String>, Box<dyn Error>>;
 }
 
 pub fn update_ipv4(
     provider: &dyn Provider,
     new_ip: &Ipv4Addr,
     domain_config: &DomainConfig,
-) -> Result<String, String> {
-    let updated = provider.update_ip(domain_config, IpAddr::V4(*new_ip));
-
-    match updated {
-        Ok(update_status) => {
-            if update_status {
-                Ok("Updated IPv4 successfully".to_string())
-            } else {
-                Ok("IPv4 already set correctly".to_string())
-            }
-        }
-        Err(error) => Err(format!("Error: {error}")),
-    }
+    ttl: Option<u64>,
+) -> Result<UpdateStatus, String> {
+    logging::set_field("record_type", "A");
+    provider
+        .update_ip(domain_config, IpAddr::V4(*new_ip), ttl)
+        .map_err(|error| format!("Error: {error}"))
 }
 
 pub fn update_ipv6(
     provider: &dyn Provider,
     new_ip: &Ipv6Addr,
     domain_config: &DomainConfig,
-) -> Result<String, String> {
-    let updated = provider.update_ip(domain_config, IpAddr::V6(*new_ip));
-
-    match updated {
-        Ok(update_status) => {
-            if update_status {
-                Ok("Updated IPv6 successfully".to_string())
-            } else {
-                Ok("IPv6 already set correctly".to_string())
-            }
-        }
-        Err(error) => Err(format!("Error: {error}")),
+    ttl: Option<u64>,
+) -> Result<UpdateStatus, String> {
+    logging::set_field("record_type", "AAAA");
+    provider
+        .update_ip(domain_config, IpAddr::V6(*new_ip), ttl)
+        .map_err(|error| format!("Error: {error}"))
+}
+
+/// Fail with [`UnsupportedRecordTypeError`] if `provider` cannot manage records of `record_type`
+pub fn check_record_type(
+    provider: &dyn Provider,
+    record_type: RecordType,
+) -> Result<(), UnsupportedRecordTypeError> {
+    if provider.record_types().contains(&record_type) {
+        Ok(())
+    } else {
+        Err(UnsupportedRecordTypeError(record_type))
+    }
+}
+
+/// Set the TXT records of the host of `domain_config` to `values`, deleting them if `values` is empty
+pub fn set_txt(
+    provider: &dyn Provider,
+    domain_config: &DomainConfig,
+    values: &[String],
+) -> Result<(), Box<dyn Error>> {
+    logging::set_field("record_type", "TXT");
+    check_record_type(provider, RecordType::TXT)?;
+
+    if values.is_empty() {
+        provider.delete_record_set(domain_config, RecordType::TXT)
+    } else {
+        provider
+            .upsert_record_set(
+                domain_config,
+                &RecordSet {
+                    record_type: RecordType::TXT,
+                    values: values.to_vec(),
+                    ttl: Some(60),
+                },
+            )
+            .map(drop)
+    }
+}
+
+/// Serializes the changes of TXT records, which read the current values from the provider before writing them back
+static TXT_CHANGES: Mutex<()> = Mutex::new(());
+
+/// Add `value` to the TXT records of the host of `domain_config`
+///
+/// The current values are read from the provider, so values of other challenges stay in place.
+/// With `limit` set, the first values are removed until at most `limit` values are left.
+pub fn add_txt(
+    provider: &dyn Provider,
+    domain_config: &DomainConfig,
+    value: &str,
+    limit: Option<usize>,
+) -> Result<(), Box<dyn Error>> {
+    let _guard = TXT_CHANGES.lock().unwrap();
+
+    let mut values = current_txt(provider, domain_config)?;
+    values.retain(|v| v != value);
+    values.push(value.to_string());
+    if let Some(limit) = limit {
+        values.drain(..values.len().saturating_sub(limit));
+    }
+
+    set_txt(provider, domain_config, &values)
+}
+
+/// Remove `value` from the TXT records of the host of `domain_config`, keeping all other values
+pub fn remove_txt(
+    provider: &dyn Provider,
+    domain_config: &DomainConfig,
+    value: &str,
+) -> Result<(), Box<dyn Error>> {
+    let _guard = TXT_CHANGES.lock().unwrap();
+
+    let mut values = current_txt(provider, domain_config)?;
+    let count = values.len();
+    values.retain(|v| v != value);
+    if values.len() == count {
+        return Ok(());
+    }
+
+    set_txt(provider, domain_config, &values)
+}
+
+fn current_txt(
+    provider: &dyn Provider,
+    domain_config: &DomainConfig,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    check_record_type(provider, RecordType::TXT)?;
+
+    Ok(provider
+        .get_record_set(domain_config, RecordType::TXT)?
+        .map(|record_set| record_set.values)
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use crate::server::tests::test_config;
+
+    use super::*;
+
+    pub type TxtRecords = Arc<Mutex<HashMap<String, Vec<String>>>>;
+
+    /// Provider that keeps the TXT records in `records` by host
+    pub fn txt_provider(records: &TxtRecords) -> MockProvider {
+        let mut mock = MockProvider::default();
+        mock.expect_record_types()
+            .return_const(&[RecordType::A, RecordType::AAAA, RecordType::TXT][..]);
+
+        let current = Arc::clone(records);
+        mock.expect_get_record_set()
+            .returning(move |d, record_type| {
+                let values = current.lock().unwrap().get(&d.host).cloned();
+                Ok(values.map(|values| RecordSet {
+                    record_type,
+                    values,
+                    ttl: Some(60),
+                }))
+            });
+        let current = Arc::clone(records);
+        mock.expect_upsert_record_set()
+            .returning(move |d, record_set| {
+                current
+                    .lock()
+                    .unwrap()
+                    .insert(d.host.clone(), record_set.values.clone());
+                Ok(UpdateStatus::Updated { previous: None })
+            });
+        let current = Arc::clone(records);
+        mock.expect_delete_record_set().returning(move |d, _| {
+            current.lock().unwrap().remove(&d.host);
+            Ok(())
+        });
+
+        mock
+    }
+
+    /// TXT records with `values` for `host`
+    pub fn txt_records(host: &str, values: &[&str]) -> TxtRecords {
+        let values = values.iter().map(ToString::to_string).collect();
+        Arc::new(Mutex::new(HashMap::from([(host.to_string(), values)])))
+    }
+
+    #[test]
+    fn add_txt_values() {
+        let domain_config = &test_config().users[0].domains[0];
+        let records = txt_records("example.com", &["existing"]);
+        let provider = txt_provider(&records);
+
+        add_txt(&provider, domain_config, "first", None).unwrap();
+        add_txt(&provider, domain_config, "second", None).unwrap();
+        add_txt(&provider, domain_config, "first", None).unwrap();
+        assert_eq!(
+            records.lock().unwrap()["example.com"],
+            ["existing", "second", "first"]
+        );
+
+        // The first values are dropped beyond the limit
+        add_txt(&provider, domain_config, "third", Some(2)).unwrap();
+        assert_eq!(records.lock().unwrap()["example.com"], ["first", "third"]);
+    }
+
+    #[test]
+    fn reject_unsupported_record_types() {
+        let domain_config = &test_config().users[0].domains[0];
+        let mut mock = MockProvider::default();
+        mock.expect_record_types()
+            .return_const(&[RecordType::A, RecordType::AAAA][..]);
+        mock.expect_get_record_set().never();
+        mock.expect_upsert_record_set().never();
+
+        let error = add_txt(&mock, domain_config, "value", None).unwrap_err();
+        assert!(error.is::<UnsupportedRecordTypeError>());
+        let error = set_txt(&mock, domain_config, &["value".to_string()]).unwrap_err();
+        assert!(error.is::<UnsupportedRecordTypeError>());
+    }
+
+    #[test]
+    fn remove_txt_values() {
+        let domain_config = &test_config().users[0].domains[0];
+        let records = txt_records("example.com", &["first", "second"]);
+        let provider = txt_provider(&records);
+
+        remove_txt(&provider, domain_config, "unknown").unwrap();
+        remove_txt(&provider, domain_config, "first").unwrap();
+        assert_eq!(records.lock().unwrap()["example.com"], ["second"]);
+
+        // The record is deleted with its last value
+        remove_txt(&provider, domain_config, "second").unwrap();
+        assert!(records.lock().unwrap().is_empty());
     }
 }
@@ -7,38 +7,40 @@ use std::{
     convert::Infallible,
     error::Error,
     fmt::{self},
+    net::IpAddr,
 };
 
 use futures::executor::block_on;
 use log::info;
 use serde::{Deserialize, Serialize};
 
+use super::{check_record_type, RecordNotFoundError, RecordSet, RecordType, UpdateStatus};
 use crate::config::{DomainConfig, Zone};
 
+/// Record types that can be changed through the API, the SOA record is managed by Hetzner
+const RECORD_TYPES: &[RecordType] = &[
+    RecordType::A,
+    RecordType::AAAA,
+    RecordType::NS,
+    RecordType::MX,
+    RecordType::CNAME,
+    RecordType::RP,
+    RecordType::TXT,
+    RecordType::HINFO,
+    RecordType::SRV,
+    RecordType::DANE,
+    RecordType::TLSA,
+    RecordType::DS,
+    RecordType::CAA,
+];
+
+const API_URL: &str = "https://dns.hetzner.com/api/v1";
+
 #[derive(Deserialize)]
 struct Zones {
     zones: Vec<Zone>,
 }
 
-#[derive(PartialEq, Eq, Deserialize, Serialize, strum_macros::Display)]
-#[allow(clippy::min_ident_chars)]
-pub enum RecordType {
-    A,
-    AAAA,
-    NS,
-    MX,
-    CNAME,
-    RP,
-    TXT,
-    SOA,
-    HINFO,
-    SRV,
-    DANE,
-    TLSA,
-    DS,
-    CAA,
-}
-
 #[derive(Deserialize, Serialize)]
 pub struct Record {
     #[serde(rename = "type")]
@@ -65,6 +67,17 @@ impl fmt::Display for Record {
     }
 }
 
+/// Record that is yet to be created, so it does not have an ID
+#[derive(Serialize)]
+struct NewRecord<'a> {
+    #[serde(rename = "type")]
+    record_type: RecordType,
+    zone_id: &'a str,
+    name: &'a str,
+    value: String,
+    ttl: Option<u64>,
+}
+
 #[derive(Deserialize)]
 struct Records {
     records: Vec<Record>,
@@ -75,17 +88,25 @@ struct ResponseRecord {
     record: Record,
 }
 
-#[derive(Default)]
 pub struct HetznerProvider {
     client: reqwest::Client,
+    /// Base URL of the DNS API, only changed in tests
+    api_url: String,
+}
+
+impl Default for HetznerProvider {
+    fn default() -> Self {
+        HetznerProvider {
+            client: reqwest::Client::new(),
+            api_url: API_URL.to_string(),
+        }
+    }
 }
 
 impl HetznerProvider {
     #[must_use]
     pub fn new() -> HetznerProvider {
-        let p = HetznerProvider {
-            client: reqwest::Client::new(),
-        };
+        let p = HetznerProvider::default();
         info!("Created new Hetzner Provider");
 
         p
@@ -94,7 +115,7 @@ impl HetznerProvider {
     pub async fn get_zones(&self, apitoken: &str) -> Result<Vec<Zone>, Infallible> {
         let response = self
             .client
-            .get("https://dns.hetzner.com/api/v1/zones")
+            .get(format!("{}/zones", self.api_url))
             .header("Auth-API-Token", apitoken)
             .send()
             .await
@@ -115,17 +136,17 @@ impl HetznerProvider {
         &self,
         apitoken: &str,
         zone: &Zone,
-    ) -> Result<Vec<Record>, Infallible> {
+    ) -> Result<Vec<Record>, reqwest::Error> {
         let response = self
             .client
-            .get("https://dns.hetzner.com/api/v1/records")
+            .get(format!("{}/records", self.api_url))
             .query(&[("zone_id", zone.id.as_str())])
             .header("Auth-API-Token", apitoken)
             .send()
-            .await
-            .unwrap();
+            .await?
+            .error_for_status()?;
 
-        let records = response.json::<Records>().await.unwrap().records;
+        let records = response.json::<Records>().await?.records;
 
         info!("Received {} records", records.len());
 
@@ -136,25 +157,92 @@ impl HetznerProvider {
         &self,
         apitoken: &str,
         record: &Record,
-    ) -> Result<Record, Infallible> {
+    ) -> Result<Record, reqwest::Error> {
         let response = self
             .client
-            .put(format!(
-                "https://dns.hetzner.com/api/v1/records/{}",
-                record.id
-            ))
+            .put(format!("{}/records/{}", self.api_url, record.id))
             .header("Auth-API-Token", apitoken)
             .json(record)
             .send()
-            .await
-            .unwrap();
+            .await?
+            .error_for_status()?;
 
-        let new_record = response.json::<ResponseRecord>().await.unwrap().record;
+        let new_record = response.json::<ResponseRecord>().await?.record;
 
         info!("Successfully updated record {}", new_record.id);
 
         Ok(new_record)
     }
+
+    async fn create_record(
+        &self,
+        apitoken: &str,
+        record: &NewRecord<'_>,
+    ) -> Result<Record, reqwest::Error> {
+        let response = self
+            .client
+            .post(format!("{}/records", self.api_url))
+            .header("Auth-API-Token", apitoken)
+            .json(record)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let new_record = response.json::<ResponseRecord>().await?.record;
+
+        info!("Successfully created record {}", new_record.id);
+
+        Ok(new_record)
+    }
+
+    async fn delete_record(&self, apitoken: &str, record: &Record) -> Result<(), reqwest::Error> {
+        self.client
+            .delete(format!("{}/records/{}", self.api_url, record.id))
+            .header("Auth-API-Token", apitoken)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        info!("Successfully deleted record {}", record.id);
+
+        Ok(())
+    }
+}
+
+/// Value of a record without the quotes of TXT records in the zone file format
+fn unquote(record_type: RecordType, value: &str) -> &str {
+    if record_type != RecordType::TXT {
+        return value;
+    }
+
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// Value of a record as expected by the API, with TXT values in quotes
+fn quote(record_type: RecordType, value: &str) -> String {
+    if record_type == RecordType::TXT {
+        format!("\"{value}\"")
+    } else {
+        value.to_string()
+    }
+}
+
+/// Name of the record of a host relative to its zone
+fn record_name(domain_config: &DomainConfig) -> &str {
+    // Split domain into subdomain and zone (if applicable)
+    if let Some(subdomain) = domain_config
+        .host
+        .strip_suffix(domain_config.zone.name.as_str())
+    {
+        // Strip last remaining dot from subdomain
+        subdomain.strip_suffix('.').unwrap_or(subdomain)
+    } else {
+        // Use the whole domain, which is denoted by @ in DNS
+        "@"
+    }
 }
 
 impl super::Provider for HetznerProvider {
@@ -162,18 +250,9 @@ impl super::Provider for HetznerProvider {
         &self,
         domain_config: &DomainConfig,
         new_ip: std::net::IpAddr,
-    ) -> Result<bool, Box<dyn Error>> {
-        // Split domain into subdomain and zone (if applicable)
-        let update_record_name = if let Some(subdomain) = domain_config
-            .host
-            .strip_suffix(domain_config.zone.name.as_str())
-        {
-            // Strip last remaining dot from subdomain
-            subdomain.strip_suffix('.').unwrap_or(subdomain)
-        } else {
-            // Use the whole domain, which is denoted by @ in DNS
-            "@"
-        };
+        ttl: Option<u64>,
+    ) -> Result<super::UpdateStatus, Box<dyn Error>> {
+        let update_record_name = record_name(domain_config);
 
         // Determine type of record to update (A for IPv4 or AAAA for IPv6)
         let update_record_type = if new_ip.is_ipv4() {
@@ -192,42 +271,474 @@ impl super::Provider for HetznerProvider {
                 // Get all records of specified zone
                 let records = self
                     .get_records(&domain_config.apitoken, &domain_config.zone)
-                    .await
-                    .unwrap();
+                    .await?;
 
                 // Find the record with matching type and name
                 let record = records
                     .into_iter()
                     .find(|r| r.name == update_record_name && r.record_type == update_record_type)
-                    .unwrap_or_else(|| {
-                        panic!(
-                            "No matching record (name: {update_record_name}, type: {update_record_type})"
-                        )
-                    });
-
-                // If the value is already correct, skip the update
-                if record.value == new_ip.to_string() {
+                    .ok_or(RecordNotFoundError)?;
+
+                // If the value (and the TTL, if requested) is already correct, skip the update
+                if record.value == new_ip.to_string()
+                    && ttl.is_none_or(|ttl| record.ttl == Some(ttl))
+                {
                     info!(
                         "Record \"{update_record_name}\" of type {update_record_type} in zone {} (ID: {}) does not need to be updated",
                         domain_config.zone.name,
                         domain_config.zone.id
                     );
-                    return Ok(false);
+                    return Ok(super::UpdateStatus::Unchanged);
                 }
 
+                let previous = record.value.parse().ok();
+
                 // Create the updated record
                 let new_record = Record {
                     value: new_ip.to_string(),
+                    ttl: ttl.or(record.ttl),
                     ..record
                 };
 
                 // Update the record
-                let _ = self
-                    .update_record(&domain_config.apitoken, &new_record)
-                    .await;
+                self.update_record(&domain_config.apitoken, &new_record)
+                    .await?;
+
+                Ok(super::UpdateStatus::Updated { previous })
+            })
+        })
+    }
+
+    fn get_ips(&self, domain_config: &DomainConfig) -> Result<Vec<IpAddr>, Box<dyn Error>> {
+        let name = record_name(domain_config);
+
+        tokio::task::block_in_place(|| {
+            block_on(async move {
+                let records = self
+                    .get_records(&domain_config.apitoken, &domain_config.zone)
+                    .await?;
+
+                Ok(records
+                    .into_iter()
+                    .filter(|r| {
+                        r.name == name
+                            && (r.record_type == RecordType::A || r.record_type == RecordType::AAAA)
+                    })
+                    .filter_map(|r| r.value.parse().ok())
+                    .collect())
+            })
+        })
+    }
+
+    fn record_types(&self) -> &'static [RecordType] {
+        RECORD_TYPES
+    }
+
+    fn get_record_set(
+        &self,
+        domain_config: &DomainConfig,
+        record_type: RecordType,
+    ) -> Result<Option<RecordSet>, Box<dyn Error>> {
+        let name = record_name(domain_config);
+
+        tokio::task::block_in_place(|| {
+            block_on(async move {
+                let records: Vec<Record> = self
+                    .get_records(&domain_config.apitoken, &domain_config.zone)
+                    .await?
+                    .into_iter()
+                    .filter(|r| r.name == name && r.record_type == record_type)
+                    .collect();
+
+                if records.is_empty() {
+                    return Ok(None);
+                }
+
+                Ok(Some(RecordSet {
+                    record_type,
+                    values: records
+                        .iter()
+                        .map(|r| unquote(record_type, &r.value).to_string())
+                        .collect(),
+                    ttl: records[0].ttl,
+                }))
+            })
+        })
+    }
+
+    fn upsert_record_set(
+        &self,
+        domain_config: &DomainConfig,
+        record_set: &RecordSet,
+    ) -> Result<UpdateStatus, Box<dyn Error>> {
+        let record_type = record_set.record_type;
+        check_record_type(self, record_type)?;
+
+        let name = record_name(domain_config);
+
+        info!(
+            "Setting {} {record_type} records of \"{}\" in zone {} (ID: {})",
+            record_set.values.len(),
+            domain_config.host,
+            domain_config.zone.name,
+            domain_config.zone.id
+        );
 
-                Ok(true)
+        tokio::task::block_in_place(|| {
+            block_on(async move {
+                let records = self
+                    .get_records(&domain_config.apitoken, &domain_config.zone)
+                    .await?;
+
+                let existing: Vec<Record> = records
+                    .into_iter()
+                    .filter(|r| r.name == name && r.record_type == record_type)
+                    .collect();
+
+                let mut status = UpdateStatus::Unchanged;
+                let mut kept = Vec::new();
+
+                for record in existing {
+                    let value = unquote(record_type, &record.value).to_string();
+                    if !record_set.values.contains(&value) {
+                        self.delete_record(&domain_config.apitoken, &record).await?;
+                        status = UpdateStatus::Updated { previous: None };
+                        continue;
+                    }
+
+                    if record_set.ttl.is_some_and(|ttl| record.ttl != Some(ttl)) {
+                        let updated = Record {
+                            ttl: record_set.ttl,
+                            ..record
+                        };
+                        self.update_record(&domain_config.apitoken, &updated)
+                            .await?;
+                        status = UpdateStatus::Updated { previous: None };
+                    }
+                    kept.push(value);
+                }
+
+                for value in record_set.values.iter().filter(|v| !kept.contains(v)) {
+                    let record = NewRecord {
+                        record_type,
+                        zone_id: &domain_config.zone.id,
+                        name,
+                        value: quote(record_type, value),
+                        ttl: record_set.ttl,
+                    };
+                    self.create_record(&domain_config.apitoken, &record).await?;
+                    status = UpdateStatus::Updated { previous: None };
+                }
+
+                Ok(status)
+            })
+        })
+    }
+
+    fn delete_record_set(
+        &self,
+        domain_config: &DomainConfig,
+        record_type: RecordType,
+    ) -> Result<(), Box<dyn Error>> {
+        let name = record_name(domain_config);
+
+        info!(
+            "Deleting {record_type} records of \"{}\" in zone {} (ID: {})",
+            domain_config.host, domain_config.zone.name, domain_config.zone.id
+        );
+
+        tokio::task::block_in_place(|| {
+            block_on(async move {
+                let records = self
+                    .get_records(&domain_config.apitoken, &domain_config.zone)
+                    .await?;
+
+                for record in records
+                    .iter()
+                    .filter(|r| r.name == name && r.record_type == record_type)
+                {
+                    self.delete_record(&domain_config.apitoken, record).await?;
+                }
+
+                Ok(())
+            })
+        })
+    }
+
+    fn nameservers(&self, domain_config: &DomainConfig) -> Result<Vec<String>, Box<dyn Error>> {
+        tokio::task::block_in_place(|| {
+            block_on(async move {
+                let records = self
+                    .get_records(&domain_config.apitoken, &domain_config.zone)
+                    .await?;
+
+                Ok(records
+                    .into_iter()
+                    .filter(|r| r.name == "@" && r.record_type == RecordType::NS)
+                    .map(|r| r.value)
+                    .collect())
             })
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use serde_json::{json, Value};
+
+    use crate::{providers::Provider, server::tests::test_config};
+
+    use super::*;
+
+    /// Request received by the local API: method, path and body
+    type Request = (String, String, String);
+
+    /// Local DNS API that lists `records` and accepts every change, answering requests with one of the
+    /// `failing` methods with an error
+    fn serve(
+        records: Value,
+        failing: &'static [&'static str],
+    ) -> (String, Arc<Mutex<Vec<Request>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let received = requests.clone();
+        thread::spawn(move || loop {
+            let Ok((stream, _)) = listener.accept() else {
+                return;
+            };
+            let mut reader = BufReader::new(stream);
+
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut parts = request_line.split_whitespace();
+            let method = parts.next().unwrap_or_default().to_string();
+            let path = parts.next().unwrap_or_default().to_string();
+
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            let body = String::from_utf8(body).unwrap();
+
+            let (status, response) = if failing.contains(&method.as_str()) {
+                (500, json!({"error": "Internal error"}))
+            } else {
+                match method.as_str() {
+                    "GET" => (200, json!({ "records": records })),
+                    "POST" => {
+                        let mut record: Value = serde_json::from_str(&body).unwrap();
+                        record["id"] = json!("new");
+                        (200, json!({ "record": record }))
+                    }
+                    "PUT" => (
+                        200,
+                        json!({ "record": serde_json::from_str::<Value>(&body).unwrap() }),
+                    ),
+                    _ => (200, json!({})),
+                }
+            };
+            received.lock().unwrap().push((method, path, body));
+
+            let response = response.to_string();
+            let _ = write!(
+                reader.get_mut(),
+                "HTTP/1.1 {status} Status\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+                response.len()
+            );
+        });
+
+        (url, requests)
+    }
+
+    fn provider(url: String) -> HetznerProvider {
+        HetznerProvider {
+            client: reqwest::Client::new(),
+            api_url: url,
+        }
+    }
+
+    fn domain_config() -> DomainConfig {
+        let mut domain_config = test_config().users[0].domains[0].clone();
+        domain_config.host = "_acme-challenge.example.com".to_string();
+        domain_config.zone = Zone {
+            id: "zoneid".to_string(),
+            name: "example.com".to_string(),
+        };
+        domain_config
+    }
+
+    fn record(id: &str, record_type: &str, value: &str, ttl: u64) -> Value {
+        json!({
+            "id": id,
+            "type": record_type,
+            "zone_id": "zoneid",
+            "name": "_acme-challenge",
+            "value": value,
+            "ttl": ttl
+        })
+    }
+
+    /// Requests that changed records, without the requests listing them
+    fn changes(requests: &Mutex<Vec<Request>>) -> Vec<Request> {
+        requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(method, _, _)| method != "GET")
+            .cloned()
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn get_txt_records_without_quotes() {
+        let (url, _) = serve(
+            json!([
+                record("1", "TXT", "\"first\"", 60),
+                record("2", "TXT", "\"second\"", 60),
+                record("3", "A", "192.0.2.1", 60),
+            ]),
+            &[],
+        );
+
+        let record_set = provider(url)
+            .get_record_set(&domain_config(), RecordType::TXT)
+            .unwrap();
+
+        assert_eq!(
+            record_set,
+            Some(RecordSet {
+                record_type: RecordType::TXT,
+                values: vec!["first".to_string(), "second".to_string()],
+                ttl: Some(60),
+            })
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn replace_stale_values() {
+        let (url, requests) = serve(
+            json!([
+                record("1", "TXT", "\"stale\"", 60),
+                record("2", "TXT", "\"kept\"", 60),
+            ]),
+            &[],
+        );
+
+        let status = provider(url)
+            .upsert_record_set(
+                &domain_config(),
+                &RecordSet {
+                    record_type: RecordType::TXT,
+                    values: vec!["kept".to_string(), "new".to_string()],
+                    ttl: Some(60),
+                },
+            )
+            .unwrap();
+
+        assert_eq!(status, UpdateStatus::Updated { previous: None });
+        let changes = changes(&requests);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(
+            (changes[0].0.as_str(), changes[0].1.as_str()),
+            ("DELETE", "/records/1")
+        );
+        assert_eq!(
+            (changes[1].0.as_str(), changes[1].1.as_str()),
+            ("POST", "/records")
+        );
+        let created: Value = serde_json::from_str(&changes[1].2).unwrap();
+        assert_eq!(created["value"], "\"new\"");
+        assert_eq!(created["ttl"], 60);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn update_only_the_ttl() {
+        let (url, requests) = serve(json!([record("1", "A", "192.0.2.1", 86400)]), &[]);
+        let provider = provider(url);
+        let record_set = |ttl| RecordSet {
+            record_type: RecordType::A,
+            values: vec!["192.0.2.1".to_string()],
+            ttl: Some(ttl),
+        };
+
+        let status = provider
+            .upsert_record_set(&domain_config(), &record_set(86400))
+            .unwrap();
+        assert_eq!(status, UpdateStatus::Unchanged);
+        assert!(changes(&requests).is_empty());
+
+        let status = provider
+            .upsert_record_set(&domain_config(), &record_set(60))
+            .unwrap();
+        assert_eq!(status, UpdateStatus::Updated { previous: None });
+        let changes = changes(&requests);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            (changes[0].0.as_str(), changes[0].1.as_str()),
+            ("PUT", "/records/1")
+        );
+        let updated: Value = serde_json::from_str(&changes[0].2).unwrap();
+        assert_eq!(updated["ttl"], 60);
+        assert_eq!(updated["value"], "192.0.2.1");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn report_missing_records() {
+        let (url, requests) = serve(json!([record("1", "TXT", "\"value\"", 60)]), &[]);
+
+        let error = provider(url)
+            .update_ip(&domain_config(), "192.0.2.1".parse().unwrap(), None)
+            .unwrap_err();
+
+        assert!(error.is::<RecordNotFoundError>());
+        assert!(changes(&requests).is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn return_api_errors() {
+        let (url, _) = serve(json!([record("1", "A", "192.0.2.1", 86400)]), &["PUT"]);
+        let provider = provider(url);
+
+        let result = provider.upsert_record_set(
+            &domain_config(),
+            &RecordSet {
+                record_type: RecordType::A,
+                values: vec!["192.0.2.1".to_string()],
+                ttl: Some(60),
+            },
+        );
+        assert!(result.is_err());
+
+        let (url, _) = serve(json!([]), &["GET"]);
+        let provider = HetznerProvider {
+            api_url: url,
+            ..provider
+        };
+        assert!(provider
+            .get_record_set(&domain_config(), RecordType::A)
+            .is_err());
+        assert!(provider
+            .delete_record_set(&domain_config(), RecordType::A)
+            .is_err());
+    }
+}
@@ -0,0 +1,609 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+//! Provider for nameservers that accept dynamic updates (RFC 2136), e.g. BIND or Knot in the local network
+//!
+//! Updates are signed with TSIG (RFC 8945) and HMAC-SHA256 if a key is configured.
+
+use std::{
+    error::Error,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::info;
+use ring::hmac;
+use serde::Deserialize;
+use time::OffsetDateTime;
+
+use super::{check_record_type, RecordSet, RecordType, UnsupportedRecordTypeError, UpdateStatus};
+use crate::{
+    config::DomainConfig,
+    dns::{self, build_query, encode_name, exchange, parse_answers, CLASS_IN, TYPE_A, TYPE_AAAA},
+};
+
+const TYPE_SOA: u16 = 6;
+const TYPE_TXT: u16 = 16;
+const TYPE_TSIG: u16 = 250;
+const CLASS_ANY: u16 = 255;
+const OPCODE_UPDATE: u16 = 5 << 11;
+
+const TSIG_ALGORITHM: &str = "hmac-sha256.";
+/// Seconds the clocks of server and client may differ
+const TSIG_FUDGE: u16 = 300;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// TTL of records that are created without one
+const DEFAULT_TTL: u64 = 300;
+
+const RECORD_TYPES: &[RecordType] = &[RecordType::A, RecordType::AAAA, RecordType::TXT];
+
+/// Nameserver that the records of a domain are sent to
+#[derive(Deserialize, Clone)]
+pub struct Rfc2136Config {
+    /// Address of the primary nameserver of the zone, e.g. `192.168.1.2:53`
+    pub server: SocketAddr,
+    /// Key the updates are signed with, they are sent unsigned if not set
+    pub key: Option<TsigKey>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct TsigKey {
+    pub name: String,
+    /// Base64-encoded secret for HMAC-SHA256, as in the key files of BIND and Knot
+    pub secret: String,
+}
+
+fn type_code(record_type: RecordType) -> Result<u16, Box<dyn Error>> {
+    match record_type {
+        RecordType::A => Ok(TYPE_A),
+        RecordType::AAAA => Ok(TYPE_AAAA),
+        RecordType::TXT => Ok(TYPE_TXT),
+        _ => Err(Box::new(UnsupportedRecordTypeError(record_type))),
+    }
+}
+
+/// Record data in wire format
+fn encode_value(record_type: RecordType, value: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    match record_type {
+        RecordType::A => Ok(value.parse::<Ipv4Addr>()?.octets().to_vec()),
+        RecordType::AAAA => Ok(value.parse::<Ipv6Addr>()?.octets().to_vec()),
+        RecordType::TXT => {
+            // Long values are split into several strings of at most 255 bytes
+            let mut data = Vec::new();
+            for chunk in value.as_bytes().chunks(255) {
+                data.push(chunk.len() as u8);
+                data.extend_from_slice(chunk);
+            }
+            if data.is_empty() {
+                data.push(0);
+            }
+            Ok(data)
+        }
+        _ => Err(Box::new(UnsupportedRecordTypeError(record_type))),
+    }
+}
+
+/// Record data in presentation format
+fn decode_value(record_type: RecordType, data: &[u8]) -> Result<String, Box<dyn Error>> {
+    match record_type {
+        RecordType::A | RecordType::AAAA => match data.len() {
+            4 => Ok(IpAddr::from(<[u8; 4]>::try_from(data)?).to_string()),
+            16 => Ok(IpAddr::from(<[u8; 16]>::try_from(data)?).to_string()),
+            _ => Err(dns::error("Invalid address record")),
+        },
+        RecordType::TXT => {
+            let mut value = Vec::new();
+            let mut rest = data;
+            while let Some((&length, tail)) = rest.split_first() {
+                let string = tail
+                    .get(..length as usize)
+                    .ok_or_else(|| dns::error("Truncated TXT record"))?;
+                value.extend_from_slice(string);
+                rest = &tail[length as usize..];
+            }
+            Ok(String::from_utf8(value)?)
+        }
+        _ => Err(Box::new(UnsupportedRecordTypeError(record_type))),
+    }
+}
+
+/// Message that changes the records of a zone
+struct Update {
+    packet: Vec<u8>,
+    updates: u16,
+}
+
+impl Update {
+    fn new(id: u16, zone: &str) -> Result<Update, Box<dyn Error>> {
+        let mut packet = Vec::with_capacity(512);
+
+        packet.extend_from_slice(&id.to_be_bytes());
+        packet.extend_from_slice(&OPCODE_UPDATE.to_be_bytes());
+        // One zone, the other counts are set when the message is finished
+        packet.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+        encode_name(&mut packet, zone)?;
+        packet.extend_from_slice(&TYPE_SOA.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+        Ok(Update { packet, updates: 0 })
+    }
+
+    fn record(
+        &mut self,
+        name: &str,
+        record_type: u16,
+        class: u16,
+        ttl: u32,
+        data: &[u8],
+    ) -> Result<(), Box<dyn Error>> {
+        encode_name(&mut self.packet, name)?;
+        self.packet.extend_from_slice(&record_type.to_be_bytes());
+        self.packet.extend_from_slice(&class.to_be_bytes());
+        self.packet.extend_from_slice(&ttl.to_be_bytes());
+        self.packet
+            .extend_from_slice(&u16::try_from(data.len())?.to_be_bytes());
+        self.packet.extend_from_slice(data);
+        self.updates += 1;
+
+        Ok(())
+    }
+
+    /// Delete all records of a type
+    fn delete(&mut self, name: &str, record_type: u16) -> Result<(), Box<dyn Error>> {
+        self.record(name, record_type, CLASS_ANY, 0, &[])
+    }
+
+    fn add(
+        &mut self,
+        name: &str,
+        record_type: u16,
+        ttl: u64,
+        data: &[u8],
+    ) -> Result<(), Box<dyn Error>> {
+        let ttl = u32::try_from(ttl).unwrap_or(u32::MAX);
+        self.record(name, record_type, CLASS_IN, ttl, data)
+    }
+
+    /// Set the number of updates and sign the message with `key`
+    fn finish(
+        mut self,
+        key: Option<&TsigKey>,
+        time_signed: u64,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.packet[8..10].copy_from_slice(&self.updates.to_be_bytes());
+
+        if let Some(key) = key {
+            sign(&mut self.packet, key, time_signed)?;
+        }
+
+        Ok(self.packet)
+    }
+}
+
+/// Append a TSIG record with the MAC of the message
+fn sign(packet: &mut Vec<u8>, key: &TsigKey, time_signed: u64) -> Result<(), Box<dyn Error>> {
+    let secret = STANDARD.decode(&key.secret)?;
+
+    let mut key_name = Vec::new();
+    encode_name(&mut key_name, &key.name.to_ascii_lowercase())?;
+    let mut algorithm = Vec::new();
+    encode_name(&mut algorithm, TSIG_ALGORITHM)?;
+    // 48 bit seconds since the epoch
+    let time = &time_signed.to_be_bytes()[2..];
+
+    let mut signed = packet.clone();
+    signed.extend_from_slice(&key_name);
+    signed.extend_from_slice(&CLASS_ANY.to_be_bytes());
+    signed.extend_from_slice(&0u32.to_be_bytes());
+    signed.extend_from_slice(&algorithm);
+    signed.extend_from_slice(time);
+    signed.extend_from_slice(&TSIG_FUDGE.to_be_bytes());
+    // No error and no other data
+    signed.extend_from_slice(&[0, 0, 0, 0]);
+
+    let mac = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &secret), &signed);
+    let mac = mac.as_ref();
+
+    let mut data = algorithm;
+    data.extend_from_slice(time);
+    data.extend_from_slice(&TSIG_FUDGE.to_be_bytes());
+    data.extend_from_slice(&u16::try_from(mac.len())?.to_be_bytes());
+    data.extend_from_slice(mac);
+    // Original ID of the message
+    data.extend_from_slice(&packet[0..2]);
+    data.extend_from_slice(&[0, 0, 0, 0]);
+
+    packet.extend_from_slice(&key_name);
+    packet.extend_from_slice(&TYPE_TSIG.to_be_bytes());
+    packet.extend_from_slice(&CLASS_ANY.to_be_bytes());
+    packet.extend_from_slice(&0u32.to_be_bytes());
+    packet.extend_from_slice(&u16::try_from(data.len())?.to_be_bytes());
+    packet.extend_from_slice(&data);
+
+    let additional = u16::from_be_bytes([packet[10], packet[11]]) + 1;
+    packet[10..12].copy_from_slice(&additional.to_be_bytes());
+
+    Ok(())
+}
+
+fn settings(domain_config: &DomainConfig) -> Result<&Rfc2136Config, Box<dyn Error>> {
+    domain_config.rfc2136.as_ref().ok_or_else(|| {
+        dns::error(&format!(
+            "No RFC 2136 server configured for {}",
+            domain_config.host
+        ))
+    })
+}
+
+/// Values in the form the server returns them, so they can be compared
+fn normalize(record_type: RecordType, values: &[String]) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut values = values
+        .iter()
+        .map(|v| decode_value(record_type, &encode_value(record_type, v)?))
+        .collect::<Result<Vec<_>, _>>()?;
+    values.sort();
+    values.dedup();
+
+    Ok(values)
+}
+
+pub struct Rfc2136Provider {
+    timeout: Duration,
+}
+
+impl Default for Rfc2136Provider {
+    fn default() -> Self {
+        Rfc2136Provider::new()
+    }
+}
+
+impl Rfc2136Provider {
+    #[must_use]
+    pub fn new() -> Rfc2136Provider {
+        Rfc2136Provider { timeout: TIMEOUT }
+    }
+
+    fn query(
+        &self,
+        domain_config: &DomainConfig,
+        record_type: RecordType,
+    ) -> Result<Option<RecordSet>, Box<dyn Error>> {
+        let server = settings(domain_config)?.server;
+        let code = type_code(record_type)?;
+        let id = rand::random();
+
+        let response = tokio::task::block_in_place(|| {
+            exchange(
+                server,
+                &build_query(id, &domain_config.host, code)?,
+                self.timeout,
+            )
+        })?;
+
+        let records: Vec<_> = parse_answers(&response, id)?
+            .into_iter()
+            .filter(|r| r.record_type == code)
+            .collect();
+        if records.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(RecordSet {
+            record_type,
+            values: records
+                .iter()
+                .map(|r| decode_value(record_type, r.data))
+                .collect::<Result<_, _>>()?,
+            ttl: records.first().map(|r| u64::from(r.ttl)),
+        }))
+    }
+
+    fn send(&self, domain_config: &DomainConfig, update: Update) -> Result<(), Box<dyn Error>> {
+        let settings = settings(domain_config)?;
+        let id = u16::from_be_bytes([update.packet[0], update.packet[1]]);
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let packet = update.finish(settings.key.as_ref(), u64::try_from(now)?)?;
+
+        let response =
+            tokio::task::block_in_place(|| exchange(settings.server, &packet, self.timeout))?;
+        parse_answers(&response, id)?;
+
+        Ok(())
+    }
+}
+
+impl super::Provider for Rfc2136Provider {
+    fn update_ip(
+        &self,
+        domain_config: &DomainConfig,
+        new_ip: IpAddr,
+        ttl: Option<u64>,
+    ) -> Result<UpdateStatus, Box<dyn Error>> {
+        let record_type = if new_ip.is_ipv4() {
+            RecordType::A
+        } else {
+            RecordType::AAAA
+        };
+
+        self.upsert_record_set(
+            domain_config,
+            &RecordSet {
+                record_type,
+                values: vec![new_ip.to_string()],
+                ttl,
+            },
+        )
+    }
+
+    fn get_ips(&self, domain_config: &DomainConfig) -> Result<Vec<IpAddr>, Box<dyn Error>> {
+        let mut ips = Vec::new();
+        for record_type in [RecordType::A, RecordType::AAAA] {
+            if let Some(record_set) = self.query(domain_config, record_type)? {
+                ips.extend(
+                    record_set
+                        .values
+                        .iter()
+                        .filter_map(|v| v.parse::<IpAddr>().ok()),
+                );
+            }
+        }
+
+        Ok(ips)
+    }
+
+    fn record_types(&self) -> &'static [RecordType] {
+        RECORD_TYPES
+    }
+
+    fn get_record_set(
+        &self,
+        domain_config: &DomainConfig,
+        record_type: RecordType,
+    ) -> Result<Option<RecordSet>, Box<dyn Error>> {
+        self.query(domain_config, record_type)
+    }
+
+    fn upsert_record_set(
+        &self,
+        domain_config: &DomainConfig,
+        record_set: &RecordSet,
+    ) -> Result<UpdateStatus, Box<dyn Error>> {
+        let record_type = record_set.record_type;
+        check_record_type(self, record_type)?;
+        let code = type_code(record_type)?;
+        let current = self.query(domain_config, record_type)?;
+
+        if let Some(current) = &current {
+            if normalize(record_type, &current.values)?
+                == normalize(record_type, &record_set.values)?
+                && record_set.ttl.is_none_or(|ttl| current.ttl == Some(ttl))
+            {
+                return Ok(UpdateStatus::Unchanged);
+            }
+        }
+
+        info!(
+            "Updating {record_type} records of {} on {}",
+            domain_config.host,
+            settings(domain_config)?.server
+        );
+
+        // Keep the TTL of existing records unless another one is requested
+        let ttl = record_set
+            .ttl
+            .or(current.as_ref().and_then(|c| c.ttl))
+            .unwrap_or(DEFAULT_TTL);
+
+        let mut update = Update::new(rand::random(), &domain_config.zone.name)?;
+        update.delete(&domain_config.host, code)?;
+        for value in &record_set.values {
+            update.add(
+                &domain_config.host,
+                code,
+                ttl,
+                &encode_value(record_type, value)?,
+            )?;
+        }
+        self.send(domain_config, update)?;
+
+        let previous = current
+            .and_then(|c| c.values.first().cloned())
+            .and_then(|v| v.parse().ok());
+
+        Ok(UpdateStatus::Updated { previous })
+    }
+
+    fn delete_record_set(
+        &self,
+        domain_config: &DomainConfig,
+        record_type: RecordType,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut update = Update::new(rand::random(), &domain_config.zone.name)?;
+        update.delete(&domain_config.host, type_code(record_type)?)?;
+
+        self.send(domain_config, update)
+    }
+
+    fn nameservers(&self, domain_config: &DomainConfig) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(vec![settings(domain_config)?.server.to_string()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::UdpSocket,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use crate::{dns::tests::answer, providers::Provider, server::tests::test_config};
+
+    use super::*;
+
+    /// Secret of the test key, base64 of "testsecret"
+    const SECRET: &str = "dGVzdHNlY3JldA==";
+
+    /// Local nameserver that answers queries with `current` and accepts every update, which are collected
+    fn serve(current: Vec<IpAddr>) -> (SocketAddr, Arc<Mutex<Vec<Vec<u8>>>>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        let updates = Arc::new(Mutex::new(Vec::new()));
+
+        let received = updates.clone();
+        thread::spawn(move || {
+            let mut buffer = [0; 1024];
+            loop {
+                let Ok((length, peer)) = socket.recv_from(&mut buffer) else {
+                    return;
+                };
+                let message = &buffer[..length];
+
+                let response =
+                    if u16::from_be_bytes([message[2], message[3]]) & 0x7800 == OPCODE_UPDATE {
+                        received.lock().unwrap().push(message.to_vec());
+                        let mut response = message[..12].to_vec();
+                        response[2] |= 0x80;
+                        // Without the zone section, which servers usually repeat
+                        response[4..12].fill(0);
+                        response
+                    } else {
+                        answer(message, &current)
+                    };
+                let _ = socket.send_to(&response, peer);
+            }
+        });
+
+        (address, updates)
+    }
+
+    fn domain(server: SocketAddr) -> DomainConfig {
+        DomainConfig {
+            host: "nas.home.arpa".to_string(),
+            zone: crate::config::Zone {
+                id: String::new(),
+                name: "home.arpa".to_string(),
+            },
+            rfc2136: Some(Rfc2136Config {
+                server,
+                key: Some(TsigKey {
+                    name: "dyndns".to_string(),
+                    secret: SECRET.to_string(),
+                }),
+            }),
+            ..test_config().users[0].domains[0].clone()
+        }
+    }
+
+    #[test]
+    fn encode_update() {
+        let mut update = Update::new(0x1234, "home.arpa").unwrap();
+        update.delete("nas.home.arpa", TYPE_A).unwrap();
+        update
+            .add("nas.home.arpa", TYPE_A, 60, &[192, 168, 1, 10])
+            .unwrap();
+
+        assert_eq!(
+            update.finish(None, 0).unwrap(),
+            b"\x12\x34\x28\x00\x00\x01\x00\x00\x00\x02\x00\x00\
+              \x04home\x04arpa\x00\x00\x06\x00\x01\
+              \x03nas\x04home\x04arpa\x00\x00\x01\x00\xff\x00\x00\x00\x00\x00\x00\
+              \x03nas\x04home\x04arpa\x00\x00\x01\x00\x01\x00\x00\x00\x3c\x00\x04\xc0\xa8\x01\x0a"
+        );
+    }
+
+    #[test]
+    fn sign_update() {
+        let key = TsigKey {
+            name: "dyndns".to_string(),
+            secret: SECRET.to_string(),
+        };
+        let unsigned = Update::new(0x1234, "home.arpa")
+            .unwrap()
+            .finish(None, 0)
+            .unwrap();
+        let signed = Update::new(0x1234, "home.arpa")
+            .unwrap()
+            .finish(Some(&key), 1_700_000_000)
+            .unwrap();
+
+        // One additional record
+        assert_eq!(&signed[10..12], &[0, 1]);
+        let tsig = &signed[unsigned.len()..];
+        assert!(tsig.starts_with(b"\x06dyndns\x00\x00\xfa\x00\xff"));
+
+        // The MAC covers the message and the TSIG variables
+        let mut signed_data = unsigned.clone();
+        signed_data.extend_from_slice(b"\x06dyndns\x00\x00\xff\x00\x00\x00\x00");
+        signed_data.extend_from_slice(b"\x0bhmac-sha256\x00");
+        signed_data.extend_from_slice(&1_700_000_000u64.to_be_bytes()[2..]);
+        signed_data.extend_from_slice(&[0x01, 0x2c, 0, 0, 0, 0]);
+        let mac = hmac::sign(
+            &hmac::Key::new(hmac::HMAC_SHA256, b"testsecret"),
+            &signed_data,
+        );
+        let mac_offset = tsig.len() - 6 - 32;
+        assert_eq!(&tsig[mac_offset..mac_offset + 32], mac.as_ref());
+    }
+
+    #[test]
+    fn decode_txt() {
+        let long = "a".repeat(300);
+        let data = encode_value(RecordType::TXT, &long).unwrap();
+
+        assert_eq!(data[0], 255);
+        assert_eq!(decode_value(RecordType::TXT, &data).unwrap(), long);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn update_address() {
+        let (server, updates) = serve(vec!["192.168.1.10".parse().unwrap()]);
+        let provider = Rfc2136Provider::new();
+        let domain = domain(server);
+
+        assert_eq!(
+            provider
+                .update_ip(&domain, "192.168.1.10".parse().unwrap(), None)
+                .unwrap(),
+            UpdateStatus::Unchanged
+        );
+        assert!(updates.lock().unwrap().is_empty());
+
+        assert_eq!(
+            provider
+                .update_ip(&domain, "192.168.1.20".parse().unwrap(), None)
+                .unwrap(),
+            UpdateStatus::Updated {
+                previous: Some("192.168.1.10".parse().unwrap())
+            }
+        );
+        let updates = updates.lock().unwrap();
+        assert_eq!(updates.len(), 1);
+        assert!(updates[0].windows(4).any(|w| w == [192, 168, 1, 20]));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reject_unsupported_record_types() {
+        let (server, updates) = serve(Vec::new());
+        let provider = Rfc2136Provider::new();
+
+        let error = provider
+            .upsert_record_set(
+                &domain(server),
+                &RecordSet {
+                    record_type: RecordType::MX,
+                    values: vec!["10 mail.home.arpa.".to_string()],
+                    ttl: None,
+                },
+            )
+            .unwrap_err();
+
+        assert!(error.is::<UnsupportedRecordTypeError>());
+        assert!(updates.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,386 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+//! Records that are set again if they were changed outside of dyndns, e.g. in the console of the provider
+//!
+//! The addresses the records should hold are the last ones requested for each host, taken from the history.
+
+use std::{error::Error, net::IpAddr, time::Duration};
+
+use log::{info, warn};
+use serde::Deserialize;
+use time::OffsetDateTime;
+
+use crate::{
+    config::{store::ConfigStore, DomainConfig, Providers, User},
+    history::History,
+    metrics::Metrics,
+    providers::{update_ipv4, update_ipv6, RecordSet, RecordType},
+};
+
+const DRIFT_METRIC: &str = "dyndns_drift_total";
+const DRIFT_HELP: &str =
+    "Number of records that differed from the last requested address by result";
+
+fn default_interval() -> u64 {
+    3600
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ReconcileConfig {
+    /// Seconds between two comparisons of the records with the last requested addresses
+    #[serde(default = "default_interval")]
+    pub interval_secs: u64,
+}
+
+pub struct Reconciler {
+    store: ConfigStore,
+    providers: Providers,
+    history: History,
+    metrics: Metrics,
+}
+
+impl Reconciler {
+    #[must_use]
+    pub fn new(
+        store: ConfigStore,
+        providers: Providers,
+        history: History,
+        metrics: Metrics,
+    ) -> Self {
+        Reconciler {
+            store,
+            providers,
+            history,
+            metrics,
+        }
+    }
+
+    /// Compare the records of all hosts with their last requested addresses and set them again if they differ
+    pub fn check(&self, now: OffsetDateTime) {
+        let config = self.store.get();
+
+        // Hosts in maintenance mode point to their maintenance addresses on purpose
+        for (user, domain_config) in config
+            .users
+            .iter()
+            .flat_map(|u| u.domains.iter().map(move |d| (u, d)))
+            .filter(|(_, d)| !d.offline)
+        {
+            let targets = std::iter::once((domain_config, false))
+                .chain(domain_config.lan.as_deref().map(|lan| (lan, true)));
+
+            // Keyed records hold the addresses of several clients
+            for (target, lan) in targets.filter(|(t, _)| t.keyed.is_none()) {
+                for record_type in [RecordType::A, RecordType::AAAA] {
+                    if let Err(error) = self.check_record(user, target, lan, record_type, now) {
+                        warn!(
+                            "Error comparing the {record_type} record of {} with its last update: {error}",
+                            target.host
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_record(
+        &self,
+        user: &User,
+        domain_config: &DomainConfig,
+        lan: bool,
+        record_type: RecordType,
+        now: OffsetDateTime,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some(last) = self
+            .history
+            .entries(&user.name, &domain_config.host)
+            .into_iter()
+            .find(|e| e.record_type == record_type.to_string() && e.lan == lan)
+        else {
+            return Ok(());
+        };
+
+        // Expired leases have been removed or set to their fallback on purpose
+        if domain_config
+            .lease
+            .as_ref()
+            .is_some_and(|lease| now >= lease.expires(last.time))
+        {
+            return Ok(());
+        }
+
+        let provider = self.providers.get(domain_config)?;
+        let record_set = provider.get_record_set(domain_config, record_type)?;
+        let current: Vec<IpAddr> = record_set
+            .as_ref()
+            .map(|r| r.values.iter().filter_map(|v| v.parse().ok()).collect())
+            .unwrap_or_default();
+
+        // Other values may have been added by hand, only the address of the host has to be there
+        if current.contains(&last.ip) {
+            return Ok(());
+        }
+
+        let current = current
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        warn!(
+            "The {record_type} record of {} holds {} instead of {}, setting it again",
+            domain_config.host,
+            if current.is_empty() {
+                "no address"
+            } else {
+                &current
+            },
+            last.ip
+        );
+
+        // Records that were deleted have to be created again, which updating the address does not do
+        let result = match (record_set, last.ip) {
+            (None, _) => provider
+                .upsert_record_set(
+                    domain_config,
+                    &RecordSet {
+                        record_type,
+                        values: vec![last.ip.to_string()],
+                        ttl: domain_config.ttl,
+                    },
+                )
+                .map_err(|error| format!("Error: {error}")),
+            (Some(_), IpAddr::V4(ipv4)) => {
+                update_ipv4(provider, &ipv4, domain_config, domain_config.ttl)
+            }
+            (Some(_), IpAddr::V6(ipv6)) => {
+                update_ipv6(provider, &ipv6, domain_config, domain_config.ttl)
+            }
+        };
+
+        let label = if result.is_ok() {
+            "corrected"
+        } else {
+            "failed"
+        };
+        self.metrics.increment(
+            DRIFT_METRIC,
+            DRIFT_HELP,
+            &[("record_type", &record_type.to_string()), ("result", label)],
+        );
+
+        result?;
+        info!(
+            "Set the {record_type} record of {} to {} again",
+            domain_config.host, last.ip
+        );
+
+        Ok(())
+    }
+}
+
+/// Reconcile the records periodically
+pub async fn watch(reconciler: Reconciler, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+
+        reconciler.check(OffsetDateTime::now_utc());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use crate::{
+        history::{Entry, HistoryConfig},
+        keyed::KeyedConfig,
+        leases::LeaseConfig,
+        providers::{MockProvider, UpdateStatus},
+        server::tests::test_config,
+    };
+
+    use super::*;
+
+    const START: OffsetDateTime = datetime!(2026-01-01 00:00 UTC);
+
+    fn a_record(value: &str) -> RecordSet {
+        RecordSet {
+            record_type: RecordType::A,
+            values: vec![value.to_string()],
+            ttl: None,
+        }
+    }
+
+    fn update(history: &History, host: &str, address: &str) {
+        history.record(Entry {
+            time: START,
+            user: "test".to_string(),
+            host: host.to_string(),
+            record_type: "A".to_string(),
+            ip: address.parse().unwrap(),
+            client: None,
+            result: "updated".to_string(),
+            error: None,
+            lan: false,
+        });
+    }
+
+    fn reconciler(
+        mock: MockProvider,
+        config: crate::config::Config,
+        history: &History,
+        metrics: &Metrics,
+    ) -> Reconciler {
+        Reconciler::new(
+            ConfigStore::fixed(config),
+            Providers {
+                hetzner_provider: None,
+                rfc2136_provider: None,
+                mock_provider: Some(mock),
+            },
+            history.clone(),
+            metrics.clone(),
+        )
+    }
+
+    #[test]
+    fn correct_changed_records() {
+        let mut mock = MockProvider::default();
+        mock.expect_get_record_set()
+            .withf(|d, record_type| d.host == "example.com" && *record_type == RecordType::A)
+            .times(2)
+            .returning(|_, _| Ok(Some(a_record("192.0.2.99"))));
+        mock.expect_get_record_set()
+            .withf(|d, record_type| d.host == "www.example.com" && *record_type == RecordType::A)
+            .times(2)
+            .returning(|_, _| Ok(Some(a_record("192.0.2.2"))));
+        mock.expect_update_ip()
+            .withf(|d, ip, _| d.host == "example.com" && ip.to_string() == "192.0.2.1")
+            .once()
+            .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
+        mock.expect_update_ip()
+            .withf(|d, ip, _| d.host == "example.com" && ip.to_string() == "192.0.2.1")
+            .once()
+            .returning(|_, _, _| Err("Provider unavailable".into()));
+
+        let history = History::new(&HistoryConfig::default()).unwrap();
+        update(&history, "example.com", "192.0.2.1");
+        update(&history, "www.example.com", "192.0.2.2");
+        let metrics = Metrics::default();
+
+        let reconciler = reconciler(mock, test_config(), &history, &metrics);
+        reconciler.check(START);
+        reconciler.check(START);
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("dyndns_drift_total{record_type=\"A\",result=\"corrected\"} 1"));
+        assert!(rendered.contains("dyndns_drift_total{record_type=\"A\",result=\"failed\"} 1"));
+    }
+
+    #[test]
+    fn create_deleted_records() {
+        let mut mock = MockProvider::default();
+        mock.expect_get_record_set()
+            .withf(|d, record_type| d.host == "example.com" && *record_type == RecordType::A)
+            .once()
+            .returning(|_, _| Ok(None));
+        mock.expect_upsert_record_set()
+            .withf(|d, record_set| {
+                d.host == "example.com"
+                    && record_set.record_type == RecordType::A
+                    && record_set.values == ["192.0.2.1"]
+                    && record_set.ttl == Some(60)
+            })
+            .once()
+            .returning(|_, _| Ok(UpdateStatus::Updated { previous: None }));
+        mock.expect_update_ip().never();
+
+        let history = History::new(&HistoryConfig::default()).unwrap();
+        update(&history, "example.com", "192.0.2.1");
+        let metrics = Metrics::default();
+
+        let mut config = test_config();
+        config.users[0].domains.truncate(1);
+        config.users[0].domains[0].ttl = Some(60);
+
+        let reconciler = reconciler(mock, config, &history, &metrics);
+        reconciler.check(START);
+
+        assert!(metrics
+            .render()
+            .contains("dyndns_drift_total{record_type=\"A\",result=\"corrected\"} 1"));
+    }
+
+    #[test]
+    fn keep_additional_values() {
+        let mut mock = MockProvider::default();
+        mock.expect_get_record_set()
+            .withf(|d, record_type| d.host == "example.com" && *record_type == RecordType::A)
+            .times(2)
+            .returning(|_, _| {
+                Ok(Some(RecordSet {
+                    record_type: RecordType::A,
+                    values: vec!["192.0.2.1".to_string(), "192.0.2.99".to_string()],
+                    ttl: None,
+                }))
+            });
+        mock.expect_update_ip().never();
+
+        let history = History::new(&HistoryConfig::default()).unwrap();
+        update(&history, "example.com", "192.0.2.1");
+        let metrics = Metrics::default();
+
+        let mut config = test_config();
+        config.users[0].domains.truncate(1);
+
+        let reconciler = reconciler(mock, config, &history, &metrics);
+        reconciler.check(START);
+        reconciler.check(START);
+
+        assert!(!metrics.render().contains(DRIFT_METRIC));
+    }
+
+    #[test]
+    fn skip_offline_hosts() {
+        let mut mock = MockProvider::default();
+        mock.expect_get_record_set().never();
+        mock.expect_update_ip().never();
+
+        let history = History::new(&HistoryConfig::default()).unwrap();
+        update(&history, "example.com", "192.0.2.1");
+
+        // The maintenance address was set without an entry in the history
+        let mut config = test_config();
+        config.users[0].domains.truncate(1);
+        config.users[0].domains[0].offline = true;
+
+        let reconciler = reconciler(mock, config, &history, &Metrics::default());
+        reconciler.check(START);
+    }
+
+    #[test]
+    fn skip_keyed_records_and_expired_leases() {
+        let mut mock = MockProvider::default();
+        mock.expect_get_record_set().never();
+        mock.expect_update_ip().never();
+
+        let history = History::new(&HistoryConfig::default()).unwrap();
+        update(&history, "example.com", "192.0.2.1");
+        update(&history, "www.example.com", "192.0.2.2");
+
+        let mut config = test_config();
+        config.users[0].domains[0].keyed = Some(KeyedConfig { lease: 3600 });
+        config.users[0].domains[1].lease = Some(LeaseConfig {
+            duration: 3600,
+            fallback_ipv4: None,
+            fallback_ipv6: None,
+        });
+
+        let reconciler = reconciler(mock, config, &history, &Metrics::default());
+        reconciler.check(START + time::Duration::hours(2));
+    }
+}
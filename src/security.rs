@@ -0,0 +1,96 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+use std::net::IpAddr;
+
+use log::warn;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+/// Log target of the security log stream, e.g. for filtering with fail2ban
+pub const TARGET: &str = "dyndns::security";
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, strum_macros::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum SecurityEvent {
+    InvalidUser,
+    WrongPassword,
+    InvalidHost,
+    InvalidToken,
+    ExpiredToken,
+}
+
+/// Format a security log line
+///
+/// The format is considered stable, as external tools like fail2ban match against it:
+///
+///     SECURITY <timestamp> event=<event> client=<ip> user="<user>" host="<host>"
+///
+/// User-supplied values are quoted and escaped, so they can neither break the line
+/// nor spoof the client address that precedes them.
+fn format_event(
+    timestamp: OffsetDateTime,
+    event: SecurityEvent,
+    client: Option<IpAddr>,
+    user: &str,
+    host: &str,
+) -> String {
+    let timestamp = timestamp
+        .format(&Rfc3339)
+        .unwrap_or_else(|_| "-".to_string());
+    let client = client.map_or_else(|| "-".to_string(), |ip| ip.to_string());
+
+    format!("SECURITY {timestamp} event={event} client={client} user={user:?} host={host:?}")
+}
+
+/// Emit an event to the security log stream
+pub fn log_event(event: SecurityEvent, client: Option<IpAddr>, user: &str, host: &str) {
+    warn!(
+        target: TARGET,
+        "{}",
+        format_event(OffsetDateTime::now_utc(), event, client, user, host)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn format_wrong_password() {
+        let line = format_event(
+            datetime!(2026-01-02 03:04:05 UTC),
+            SecurityEvent::WrongPassword,
+            Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))),
+            "test",
+            "example.com",
+        );
+
+        assert_eq!(
+            line,
+            "SECURITY 2026-01-02T03:04:05Z event=wrong_password client=192.0.2.1 user=\"test\" host=\"example.com\""
+        );
+    }
+
+    #[test]
+    fn format_escapes_user_input() {
+        let line = format_event(
+            datetime!(2026-01-02 03:04:05 UTC),
+            SecurityEvent::InvalidUser,
+            None,
+            "a\" client=198.51.100.1\n",
+            "",
+        );
+
+        assert_eq!(
+            line,
+            "SECURITY 2026-01-02T03:04:05Z event=invalid_user client=- user=\"a\\\" client=198.51.100.1\\n\" host=\"\""
+        );
+    }
+}
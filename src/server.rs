@@ -5,79 +5,68 @@
 #![deny(clippy::all)]
 
 use std::{
-    fmt::Write,
-    net::{Ipv4Addr, Ipv6Addr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
     str::FromStr,
 };
 
-use log::{error, info, warn};
-use rocket::{get, http::Status, routes, State};
+use log::info;
+use rocket::{figment::Figment, get, http::Status, routes, State};
 
 use crate::{
-    config::{self, Config, DomainConfig, Providers, User},
-    providers::{update_ipv4, update_ipv6, Provider},
+    acme_dns, api,
+    auth::{get_user, Credentials},
+    config::{store::ConfigStore, Providers},
+    history::History,
+    httpreq, ip,
+    keyed::KeyedEntries,
+    logging,
+    metrics::{self, Metrics},
+    notifications::Notifier,
+    ui,
+    update::Updater,
 };
 
-fn get_user<'user_config_lifetime>(
-    config: &'user_config_lifetime Config,
-    username: &str,
-    password: &str,
-) -> Result<&'user_config_lifetime User, ()> {
-    let user_config = config.users.iter().find(|u| u.name == username);
-
-    if user_config.is_none() {
-        warn!("Invalid user {}", username);
-        return Err(());
-    }
-    let user = user_config.unwrap();
-
-    if user.password != password {
-        warn!("Wrong password for user {}", user.name);
-        return Err(());
-    }
-
-    Ok(user)
-}
-
-fn get_domain_config<'user_config_lifetime>(
-    user: &'user_config_lifetime User,
-    host: &str,
-) -> Result<&'user_config_lifetime DomainConfig, ()> {
-    let domain_config = user.domains.iter().find(|d| d.host == host);
-
-    if domain_config.is_none() {
-        warn!("Invalid domain {host} for user {}", user.name);
-        return Err(());
-    }
-    Ok(domain_config.unwrap())
-}
-
-#[get("/update?<user>&<password>&<host>&<ip>&<ip6>")]
+#[get("/update?<user>&<password>&<host>&<ip>&<ip6>&<lanip>&<key>&<offline>&<verify>")]
+#[allow(clippy::too_many_arguments)]
 fn update(
-    user: &str,
-    password: &str,
+    user: Option<&str>,
+    password: Option<&str>,
+    credentials: Option<Credentials>,
     host: &str,
     ip: Option<&str>,
     ip6: Option<&str>,
-    config: &State<Config>,
+    lanip: Option<&str>,
+    key: Option<&str>,
+    offline: Option<&str>,
+    verify: Option<bool>,
+    client_ip: Option<IpAddr>,
+    store: &State<ConfigStore>,
     providers: &State<Providers>,
+    notifier: &State<Notifier>,
+    metrics: &State<Metrics>,
+    history: &State<History>,
+    keyed_entries: &State<KeyedEntries>,
 ) -> (Status, String) {
-    let user = get_user(config, user, password);
-    if user.is_err() {
-        return (Status::Unauthorized, "Invalid user".to_string());
-    }
-    let user = user.unwrap();
+    let _context = logging::enter_request();
+    logging::set_field("host", host);
+    let config = &store.get();
 
-    let domain_config = get_domain_config(user, host);
-    if domain_config.is_err() {
-        return (Status::BadRequest, "Invalid domain".to_string());
-    }
-    let domain_config = domain_config.unwrap();
-
-    let p: &dyn Provider = match domain_config.provider {
-        config::ProviderType::HetznerProvider => providers.hetzner_provider.as_ref().unwrap(),
-        config::ProviderType::MockProvider => providers.mock_provider.as_ref().unwrap(),
+    // Clients speaking the dyndns2 protocol send the credentials with Basic authentication
+    let authenticated = match (user, password, credentials) {
+        (Some(user), Some(password), _) => {
+            logging::set_field("user", user);
+            get_user(config, user, password, client_ip, host)
+        }
+        (_, _, Some(credentials)) => {
+            logging::set_field("user", credentials.user());
+            credentials.authenticate(config, client_ip, host)
+        }
+        _ => Err(()),
+    };
+    let Ok(authenticated) = authenticated else {
+        return (Status::Unauthorized, "Invalid user".to_string());
     };
+    let user = authenticated.user;
 
     info!(
         "Received IP addresses: IPv4 {}, IPv6: {}",
@@ -103,89 +92,188 @@ fn update(
         None
     };
 
-    let mut status_code = Status::Ok;
-    let mut response: String = String::default();
-
-    if parsed_ipv4.is_some() {
-        let res = update_ipv4(p, &parsed_ipv4.unwrap(), domain_config);
+    // With auto, the client is expected to send the request from within the local network
+    let lan_ip = match lanip.filter(|s| !s.is_empty()) {
+        None => None,
+        Some("auto") => client_ip,
+        Some(lanip) => match IpAddr::from_str(lanip) {
+            Ok(i) => Some(i),
+            Err(_) => return (Status::BadRequest, "Invalid LAN address".to_string()),
+        },
+    };
 
-        match res {
-            Ok(s) => {
-                let _ = writeln!(response, "{s}");
-                info!("{s}");
-            }
-            Err(e) => {
-                let _ = writeln!(response, "Error updating IPv4 address: {e}");
-                status_code = Status::InternalServerError;
-                error!("{e}");
-            }
-        }
-    }
+    // Clients speaking the dyndns2 protocol send YES or NO
+    let offline = match offline.map(str::to_ascii_uppercase).as_deref() {
+        None => None,
+        Some("YES") => Some(true),
+        Some("NO") => Some(false),
+        Some(_) => return (Status::BadRequest, "Invalid offline flag".to_string()),
+    };
 
-    if ip6.is_some_and(|s| !s.is_empty()) {
-        let res = update_ipv6(p, &parsed_ipv6.unwrap(), domain_config);
+    let addresses: Vec<IpAddr> = parsed_ipv4
+        .map(IpAddr::V4)
+        .into_iter()
+        .chain(parsed_ipv6.map(IpAddr::V6))
+        .collect();
 
-        match res {
-            Ok(s) => {
-                let _ = writeln!(response, "{s}");
-                info!("{s}");
-            }
-            Err(e) => {
-                let _ = writeln!(response, "Error updating IPv6 address: {e}");
-                status_code = Status::InternalServerError;
-                error!("{e}");
-            }
-        }
-    }
+    // Several hosts can be updated at once by separating them with commas or by using a group
+    let hosts: Vec<&str> = host.split(',').map(str::trim).collect();
+    let hosts = user.expand_groups(&hosts);
 
-    if response.is_empty() {
-        return (Status::Ok, "No IP address specified".to_string());
-    }
+    let updater = Updater {
+        user,
+        token: authenticated.token,
+        client_ip,
+        config,
+        store,
+        providers,
+        notifier,
+        metrics,
+        history,
+        keyed_entries,
+        ttl: None,
+        key,
+        offline,
+        lan_ip,
+        verify: verify.unwrap_or(false),
+    };
 
-    (status_code, response)
+    updater.update_hosts_text(&hosts, &addresses)
 }
 
-// #[launch]
-pub fn rocket() -> rocket::Rocket<rocket::Build> {
-    rocket::build().mount("/", routes![update])
+/// Server with all routes, configured by `figment`
+pub fn rocket(figment: Figment) -> rocket::Rocket<rocket::Build> {
+    rocket::custom(figment)
+        .mount("/", routes![update, metrics::metrics])
+        .mount("/", ip::routes())
+        .mount("/api/v1", api::routes())
+        .register("/api/v1", api::catchers())
+        .mount("/ui", ui::routes())
+        .mount("/acme-dns", acme_dns::routes())
+        .register("/acme-dns", api::catchers())
+        .mount("/httpreq", httpreq::routes())
+        .register("/httpreq", api::catchers())
 }
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
     use crate::{
-        config::{DomainConfig, User},
-        providers::MockProvider,
+        address_policy::AddressPolicy,
+        config::{AddressFamily, ApiToken, Config, DomainConfig, HostGroup, User},
+        history::HistoryConfig,
+        notifications::NotificationConfig,
+        providers::{MockProvider, RecordNotFoundError, UpdateStatus},
+        ui::Sessions,
+        verification::VerificationConfig,
     };
 
     use super::*;
     use rocket::local::blocking::Client;
 
-    fn construct(mock_provider: Option<MockProvider>) -> Client {
+    pub fn test_config() -> Config {
+        Config {
+            users: vec![User {
+                name: "test".to_string(),
+                password: "testpassword".to_string(),
+                domains: vec![
+                    DomainConfig {
+                        provider: crate::config::ProviderType::MockProvider,
+                        apitoken: "testtoken".to_string(),
+                        host: "example.com".to_string(),
+                        zone: crate::config::Zone {
+                            id: "testzoneid".to_string(),
+                            name: "testzone".to_string(),
+                        },
+                        ttl: None,
+                        keyed: None,
+                        lease: None,
+                        maintenance: None,
+                        offline: false,
+                        address_policy: None,
+                        rfc2136: None,
+                        lan: None,
+                    },
+                    DomainConfig {
+                        provider: crate::config::ProviderType::MockProvider,
+                        apitoken: "testtoken".to_string(),
+                        host: "www.example.com".to_string(),
+                        zone: crate::config::Zone {
+                            id: "testzoneid".to_string(),
+                            name: "testzone".to_string(),
+                        },
+                        ttl: None,
+                        keyed: None,
+                        lease: None,
+                        maintenance: None,
+                        offline: false,
+                        address_policy: None,
+                        rfc2136: None,
+                        lan: None,
+                    },
+                ],
+                groups: vec![HostGroup {
+                    name: "home".to_string(),
+                    hosts: vec!["www.example.com".to_string(), "example.com".to_string()],
+                }],
+                tokens: vec![
+                    ApiToken {
+                        name: "script".to_string(),
+                        token: "testapitoken".to_string(),
+                        hosts: Vec::new(),
+                        families: Vec::new(),
+                        expires: None,
+                    },
+                    ApiToken {
+                        name: "router".to_string(),
+                        token: "testwwwtoken".to_string(),
+                        hosts: vec!["www.example.com".to_string()],
+                        families: vec![AddressFamily::Ipv6],
+                        expires: None,
+                    },
+                    ApiToken {
+                        name: "old".to_string(),
+                        token: "testexpiredtoken".to_string(),
+                        hosts: Vec::new(),
+                        families: Vec::new(),
+                        expires: Some(time::macros::datetime!(2020-01-01 00:00 UTC)),
+                    },
+                ],
+            }],
+            // The tests use addresses from the documentation ranges
+            address_policy: AddressPolicy {
+                global_only: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn construct_with_config(config: Config, mock_provider: Option<MockProvider>) -> Client {
+        construct_with_store(ConfigStore::fixed(config), mock_provider)
+    }
+
+    pub fn construct_with_store(store: ConfigStore, mock_provider: Option<MockProvider>) -> Client {
         Client::tracked(
-            rocket()
-                .manage(Config {
-                    users: vec![User {
-                        name: "test".to_string(),
-                        password: "testpassword".to_string(),
-                        domains: vec![DomainConfig {
-                            provider: crate::config::ProviderType::MockProvider,
-                            apitoken: "testtoken".to_string(),
-                            host: "example.com".to_string(),
-                            zone: crate::config::Zone {
-                                id: "testzoneid".to_string(),
-                                name: "testzone".to_string(),
-                            },
-                        }],
-                    }],
-                })
+            rocket(rocket::Config::figment())
+                .manage(store)
                 .manage(Providers {
                     hetzner_provider: None,
-                    mock_provider: mock_provider,
-                }),
+                    rfc2136_provider: None,
+                    mock_provider,
+                })
+                .manage(Notifier::new(&NotificationConfig::default()).unwrap())
+                .manage(Metrics::default())
+                .manage(History::new(&HistoryConfig::default()).unwrap())
+                .manage(KeyedEntries::default())
+                .manage(Sessions::default()),
         )
         .expect("valid rocket instance")
     }
 
+    pub fn construct(mock_provider: Option<MockProvider>) -> Client {
+        construct_with_config(test_config(), mock_provider)
+    }
+
     mod uri_checks {
         use super::*;
 
@@ -228,7 +316,9 @@ mod tests {
         #[test]
         fn update_ipv4_only() {
             let mut mock = MockProvider::default();
-            mock.expect_update_ip().once().returning(|_, _| Ok(true));
+            mock.expect_update_ip()
+                .once()
+                .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
 
             let client = construct(Some(mock));
             let response = client
@@ -241,10 +331,54 @@ mod tests {
             );
         }
 
+        #[test]
+        fn enforce_ttl_of_host() {
+            let mut mock = MockProvider::default();
+            mock.expect_update_ip()
+                .withf(|d, _, ttl| d.host == "example.com" && *ttl == Some(60))
+                .once()
+                .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
+
+            let mut config = test_config();
+            config.users[0].domains[0].ttl = Some(60);
+
+            let client = construct_with_config(config, Some(mock));
+            let response = client
+                .get("/update?user=test&password=testpassword&host=example.com&ip=192.0.2.0")
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        }
+
+        #[test]
+        fn report_ttl_change() {
+            let mut mock = MockProvider::default();
+            mock.expect_update_ip()
+                .once()
+                .returning(|_, ip, _| Ok(UpdateStatus::Updated { previous: Some(ip) }));
+
+            let mut config = test_config();
+            config.users[0].domains[0].ttl = Some(60);
+
+            let client = construct_with_config(config, Some(mock));
+            let response = client
+                .get("/update?user=test&password=testpassword&host=example.com&ip=192.0.2.0")
+                .dispatch();
+            assert_eq!(
+                response.into_string().unwrap(),
+                "Updated TTL of IPv4 successfully\n"
+            );
+
+            let metrics = client.get("/metrics").dispatch().into_string().unwrap();
+            assert!(metrics
+                .contains("dyndns_updates_total{record_type=\"A\",result=\"ttl_updated\"} 1\n"));
+        }
+
         #[test]
         fn update_ipv6_only() {
             let mut mock = MockProvider::default();
-            mock.expect_update_ip().once().returning(|_, _| Ok(true));
+            mock.expect_update_ip()
+                .once()
+                .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
 
             let client = construct(Some(mock));
             let response = client
@@ -260,7 +394,9 @@ mod tests {
         #[test]
         fn update_ipv4_ipv6() {
             let mut mock = MockProvider::default();
-            mock.expect_update_ip().times(2).returning(|_, _| Ok(true));
+            mock.expect_update_ip()
+                .times(2)
+                .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
 
             let client = construct(Some(mock));
             let response = client
@@ -276,9 +412,13 @@ mod tests {
         #[test]
         fn update_ipv4_only_twice() {
             let mut mock = MockProvider::default();
-            mock.expect_update_ip().once().returning(|_, _| Ok(true));
+            mock.expect_update_ip()
+                .once()
+                .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
             // The second time, the IP address will already be set correctly
-            mock.expect_update_ip().once().returning(|_, _| Ok(false));
+            mock.expect_update_ip()
+                .once()
+                .returning(|_, _, _| Ok(UpdateStatus::Unchanged));
 
             let client = construct(Some(mock));
             let response = client
@@ -300,4 +440,628 @@ mod tests {
             );
         }
     }
+    mod multiple_hosts {
+        use super::*;
+
+        #[test]
+        fn update_two_hosts() {
+            let mut mock = MockProvider::default();
+            mock.expect_update_ip()
+                .withf(|d, _, _| d.host == "www.example.com")
+                .times(2)
+                .returning(|_, _, _| Ok(UpdateStatus::Unchanged));
+            mock.expect_update_ip()
+                .withf(|d, _, _| d.host == "example.com")
+                .times(2)
+                .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
+
+            let client = construct(Some(mock));
+            let response = client
+                .get("/update?user=test&password=testpassword&host=www.example.com,example.com&ip=192.0.2.0&ip6=2001:db8::1")
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert_eq!(
+                response.into_string().unwrap(),
+                "www.example.com: IPv4 already set correctly; IPv6 already set correctly\n\
+                 example.com: Updated IPv4 successfully; Updated IPv6 successfully\n"
+            );
+        }
+
+        #[test]
+        fn update_group() {
+            let mut mock = MockProvider::default();
+            mock.expect_update_ip()
+                .withf(|d, _, _| d.host == "www.example.com")
+                .once()
+                .returning(|_, _, _| Ok(UpdateStatus::Unchanged));
+            mock.expect_update_ip()
+                .withf(|d, _, _| d.host == "example.com")
+                .once()
+                .returning(|_, _, _| Err(Box::new(RecordNotFoundError)));
+
+            let client = construct(Some(mock));
+            let response = client
+                .get("/update?user=test&password=testpassword&host=home&ip=192.0.2.0")
+                .dispatch();
+            assert_eq!(response.status(), Status::InternalServerError);
+            assert_eq!(
+                response.into_string().unwrap(),
+                "www.example.com: IPv4 already set correctly\n\
+                 example.com: Error updating IPv4 address: Error: Record not found\n"
+            );
+        }
+
+        #[test]
+        fn skip_foreign_host() {
+            let mut mock = MockProvider::default();
+            mock.expect_update_ip()
+                .withf(|d, _, _| d.host == "example.com")
+                .once()
+                .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
+
+            let client = construct(Some(mock));
+            let response = client
+                .get("/update?user=test&password=testpassword&host=example.com,example.org&ip=192.0.2.0")
+                .dispatch();
+            assert_eq!(response.status(), Status::BadRequest);
+            assert_eq!(
+                response.into_string().unwrap(),
+                "example.com: Updated IPv4 successfully\nexample.org: Invalid domain\n"
+            );
+        }
+
+        #[test]
+        fn report_missing_provider() {
+            let client = construct(None);
+            let response = client
+                .get("/update?user=test&password=testpassword&host=example.com&ip=192.0.2.0")
+                .dispatch();
+            assert_eq!(response.status(), Status::InternalServerError);
+            assert_eq!(
+                response.into_string().unwrap(),
+                "Error updating IPv4 address: Error: Provider MockProvider is not available\n"
+            );
+        }
+    }
+
+    mod tokens {
+        use rocket::http::Header;
+
+        use super::*;
+
+        fn expect_www_ipv6() -> MockProvider {
+            let mut mock = MockProvider::default();
+            mock.expect_update_ip()
+                .withf(|d, ip, _| d.host == "www.example.com" && ip.is_ipv6())
+                .once()
+                .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
+            mock
+        }
+
+        #[test]
+        fn token_as_password() {
+            let client = construct(Some(expect_www_ipv6()));
+            let response = client
+                .get("/update?user=test&password=testwwwtoken&host=www.example.com&ip6=2001:db8::1")
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert_eq!(
+                response.into_string().unwrap(),
+                "Updated IPv6 successfully\n"
+            );
+        }
+
+        #[test]
+        fn token_with_basic_auth() {
+            let client = construct(Some(expect_www_ipv6()));
+            let response = client
+                .get("/update?host=www.example.com&ip6=2001:db8::1")
+                // test:testwwwtoken
+                .header(Header::new(
+                    "Authorization",
+                    "Basic dGVzdDp0ZXN0d3d3dG9rZW4=",
+                ))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        }
+
+        #[test]
+        fn host_outside_scope() {
+            let client = construct(Some(MockProvider::default()));
+            let response = client
+                .get("/update?user=test&password=testwwwtoken&host=example.com&ip6=2001:db8::1")
+                .dispatch();
+            assert_eq!(response.status(), Status::BadRequest);
+            assert_eq!(response.into_string().unwrap(), "Invalid domain");
+        }
+
+        #[test]
+        fn family_outside_scope() {
+            let client = construct(Some(MockProvider::default()));
+            let response = client
+                .get("/update?user=test&password=testwwwtoken&host=www.example.com&ip=192.0.2.0&ip6=2001:db8::1")
+                .dispatch();
+            assert_eq!(response.status(), Status::Forbidden);
+            assert_eq!(
+                response.into_string().unwrap(),
+                "Token may not update ipv4 addresses"
+            );
+        }
+
+        #[test]
+        fn expired_token() {
+            let client = construct(Some(MockProvider::default()));
+            let response = client
+                .get("/update?user=test&password=testexpiredtoken&host=example.com&ip=192.0.2.0")
+                .dispatch();
+            assert_eq!(response.status(), Status::Unauthorized);
+        }
+    }
+
+    mod verification {
+        use std::net::Ipv4Addr;
+
+        use crate::dns::tests::serve;
+
+        use super::*;
+
+        #[test]
+        fn update_ipv4_verified() {
+            let nameserver = serve(|_| vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 0))]);
+
+            let mut mock = MockProvider::default();
+            mock.expect_update_ip()
+                .once()
+                .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
+            mock.expect_nameservers().never();
+
+            let mut config = test_config();
+            config.verification = Some(VerificationConfig {
+                nameservers: vec![nameserver.to_string()],
+                timeout_secs: 2,
+                interval_secs: 1,
+            });
+
+            let client = construct_with_config(config, Some(mock));
+            let response = client
+                .get("/update?user=test&password=testpassword&host=example.com&ip=192.0.2.0")
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert_eq!(
+                response.into_string().unwrap(),
+                "Updated IPv4 successfully\n"
+            );
+
+            // Verification runs in the background after the response has been sent
+            let verified = (0..50).any(|_| {
+                let metrics = client.get("/metrics").dispatch().into_string().unwrap();
+                let done = metrics.contains("dyndns_verifications_total{result=\"verified\"} 1\n");
+                if !done {
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                }
+                done
+            });
+            assert!(verified);
+
+            let metrics = client.get("/metrics").dispatch().into_string().unwrap();
+            assert!(
+                metrics.contains("dyndns_updates_total{record_type=\"A\",result=\"updated\"} 1\n")
+            );
+        }
+
+        #[test]
+        fn wait_for_verification() {
+            let nameserver = serve(|_| vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 0))]);
+
+            let mut mock = MockProvider::default();
+            mock.expect_update_ip()
+                .times(2)
+                .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
+
+            let mut config = test_config();
+            config.verification = Some(VerificationConfig {
+                nameservers: vec![nameserver.to_string()],
+                timeout_secs: 2,
+                interval_secs: 1,
+            });
+
+            let client = construct_with_config(config, Some(mock));
+            let response = client
+                .get("/update?user=test&password=testpassword&host=example.com&ip=192.0.2.0&verify=true")
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert_eq!(
+                response.into_string().unwrap(),
+                "Updated IPv4 successfully\nIPv4 verified on 1 nameserver(s) after 0s\n"
+            );
+
+            let response = client
+                .post("/api/v1/update")
+                .header(rocket::http::ContentType::JSON)
+                .header(rocket::http::Header::new(
+                    "Authorization",
+                    "Bearer testapitoken",
+                ))
+                .body(r#"{"host": "example.com", "ipv4": "192.0.2.0", "verify": true}"#)
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            let response: serde_json::Value = response.into_json().unwrap();
+            assert_eq!(
+                response["hosts"][0]["ipv4"]["verification"],
+                serde_json::json!({
+                    "result": "verified",
+                    "message": "verified on 1 nameserver(s) after 0s"
+                })
+            );
+
+            let metrics = client.get("/metrics").dispatch().into_string().unwrap();
+            assert!(metrics.contains("dyndns_verifications_total{result=\"verified\"} 2\n"));
+        }
+
+        #[test]
+        fn unchanged_not_verified() {
+            let mut mock = MockProvider::default();
+            mock.expect_update_ip()
+                .once()
+                .returning(|_, _, _| Ok(UpdateStatus::Unchanged));
+            mock.expect_nameservers().never();
+
+            let mut config = test_config();
+            config.verification = Some(VerificationConfig {
+                nameservers: Vec::new(),
+                timeout_secs: 2,
+                interval_secs: 1,
+            });
+
+            let client = construct_with_config(config, Some(mock));
+            let response = client
+                .get("/update?user=test&password=testpassword&host=example.com&ip=192.0.2.0")
+                .dispatch();
+            assert_eq!(
+                response.into_string().unwrap(),
+                "IPv4 already set correctly\n"
+            );
+        }
+    }
+
+    mod keyed {
+        use crate::{
+            keyed::KeyedConfig,
+            providers::{RecordSet, RecordType},
+        };
+
+        use super::*;
+
+        fn a_records(values: &[&str]) -> RecordSet {
+            RecordSet {
+                record_type: RecordType::A,
+                values: values.iter().map(ToString::to_string).collect(),
+                ttl: None,
+            }
+        }
+
+        #[test]
+        fn one_address_per_uplink() {
+            let mut mock = MockProvider::default();
+            mock.expect_update_ip().never();
+            mock.expect_get_record_set()
+                .once()
+                .returning(|_, _| Ok(Some(a_records(&["192.0.2.1", "192.0.2.9"]))));
+            mock.expect_upsert_record_set()
+                .withf(|d, r| {
+                    d.host == "example.com" && *r == a_records(&["192.0.2.9", "192.0.2.1"])
+                })
+                .once()
+                .returning(|_, _| Ok(UpdateStatus::Unchanged));
+            mock.expect_upsert_record_set()
+                .withf(|_, r| *r == a_records(&["192.0.2.9", "192.0.2.1", "198.51.100.1"]))
+                .once()
+                .returning(|_, _| Ok(UpdateStatus::Updated { previous: None }));
+            mock.expect_upsert_record_set()
+                .withf(|_, r| *r == a_records(&["192.0.2.9", "198.51.100.1", "192.0.2.2"]))
+                .once()
+                .returning(|_, _| Ok(UpdateStatus::Updated { previous: None }));
+
+            let mut config = test_config();
+            config.users[0].domains[0].keyed = Some(KeyedConfig { lease: 3600 });
+            let client = construct_with_config(config, Some(mock));
+
+            let update = |key: &str, ip: &str| {
+                client
+                    .get(format!(
+                        "/update?user=test&password=testpassword&host=example.com&ip={ip}&key={key}"
+                    ))
+                    .dispatch()
+                    .into_string()
+                    .unwrap()
+            };
+
+            // Addresses that were already in the record are kept
+            assert_eq!(
+                update("uplink1", "192.0.2.1"),
+                "IPv4 already set correctly\n"
+            );
+            assert_eq!(
+                update("uplink2", "198.51.100.1"),
+                "Updated IPv4 successfully\n"
+            );
+            assert_eq!(
+                update("uplink1", "192.0.2.2"),
+                "Updated IPv4 successfully\n"
+            );
+        }
+    }
+
+    mod offline {
+        use serde_json::json;
+
+        use super::*;
+
+        const UPDATE: &str = "/update?user=test&password=testpassword";
+
+        #[test]
+        fn ignore_updates_while_offline() {
+            let mut mock = MockProvider::default();
+            mock.expect_update_ip()
+                .withf(|d, ip, _| d.host == "example.com" && ip.to_string() == "203.0.113.1")
+                .once()
+                .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
+            mock.expect_update_ip()
+                .withf(|_, ip, _| ip.to_string() == "192.0.2.1")
+                .once()
+                .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
+
+            let store = ConfigStore::in_memory(json!({
+                "name": "test",
+                "password": "testpassword",
+                "domains": [{
+                    "provider": "MockProvider",
+                    "apitoken": "testtoken",
+                    "host": "example.com",
+                    "zone": {"name": "example.com", "id": "testzoneid"},
+                    "maintenance": {"ipv4": "203.0.113.1"},
+                    "address_policy": {"global_only": false}
+                }, {
+                    "provider": "MockProvider",
+                    "apitoken": "testtoken",
+                    "host": "www.example.com",
+                    "zone": {"name": "example.com", "id": "testzoneid"}
+                }]
+            }))
+            .unwrap();
+            let client = construct_with_store(store.clone(), Some(mock));
+
+            let response = client
+                .get(format!("{UPDATE}&host=example.com&offline=YES"))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert_eq!(
+                response.into_string().unwrap(),
+                "Updated IPv4 successfully\n"
+            );
+            assert!(store.get().users[0].domains[0].offline);
+
+            let response = client
+                .get(format!("{UPDATE}&host=example.com&ip=192.0.2.1"))
+                .dispatch();
+            assert_eq!(response.status(), Status::Conflict);
+            assert_eq!(
+                response.into_string().unwrap(),
+                "Host is offline, update ignored"
+            );
+
+            let response = client
+                .get(format!("{UPDATE}&host=example.com&ip=192.0.2.1&offline=NO"))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert!(!store.get().users[0].domains[0].offline);
+
+            // Hosts without maintenance addresses cannot be taken offline
+            let response = client
+                .get(format!("{UPDATE}&host=www.example.com&offline=YES"))
+                .dispatch();
+            assert_eq!(response.status(), Status::BadRequest);
+            assert_eq!(
+                response.into_string().unwrap(),
+                "Host www.example.com is offline, but has no maintenance address"
+            );
+        }
+
+        #[test]
+        fn keep_state_if_unchanged() {
+            let mut mock = MockProvider::default();
+            mock.expect_update_ip()
+                .once()
+                .returning(|_, _, _| Ok(UpdateStatus::Unchanged));
+
+            // Changes to a fixed configuration are rejected, so this fails if the state is written
+            let client = construct_with_config(test_config(), Some(mock));
+            let response = client
+                .get(format!("{UPDATE}&host=example.com&ip=192.0.2.1&offline=NO"))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        }
+
+        #[test]
+        fn stay_online_if_maintenance_address_fails() {
+            let mut mock = MockProvider::default();
+            mock.expect_update_ip()
+                .withf(|_, ip, _| ip.to_string() == "203.0.113.1")
+                .once()
+                .returning(|_, _, _| Err(Box::new(RecordNotFoundError)));
+
+            let store = ConfigStore::in_memory(json!({
+                "name": "test",
+                "password": "testpassword",
+                "domains": [{
+                    "provider": "MockProvider",
+                    "apitoken": "testtoken",
+                    "host": "example.com",
+                    "zone": {"name": "example.com", "id": "testzoneid"},
+                    "maintenance": {"ipv4": "203.0.113.1"}
+                }]
+            }))
+            .unwrap();
+            let client = construct_with_store(store.clone(), Some(mock));
+
+            let response = client
+                .get(format!("{UPDATE}&host=example.com&offline=YES"))
+                .dispatch();
+            assert_eq!(response.status(), Status::InternalServerError);
+            assert!(!store.get().users[0].domains[0].offline);
+        }
+    }
+
+    mod address_policy {
+        use super::*;
+
+        const UPDATE: &str = "/update?user=test&password=testpassword";
+
+        #[test]
+        fn reject_non_global_addresses() {
+            let mut mock = MockProvider::default();
+            mock.expect_update_ip()
+                .withf(|d, ip, _| d.host == "example.com" && ip.to_string() == "2a01:4f8::1")
+                .once()
+                .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
+            mock.expect_update_ip()
+                .withf(|d, ip, _| d.host == "www.example.com" && ip.to_string() == "10.1.2.3")
+                .once()
+                .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
+
+            let mut config = test_config();
+            config.address_policy = AddressPolicy::default();
+            config.users[0].domains[1].address_policy = Some(AddressPolicy {
+                global_only: true,
+                allow: vec!["10.0.0.0/8".parse().unwrap()],
+                deny: Vec::new(),
+            });
+            let client = construct_with_config(config, Some(mock));
+
+            for (parameter, ip) in [
+                ("ip", "127.0.0.1"),
+                ("ip", "192.168.1.1"),
+                ("ip", "192.0.2.1"),
+                ("ip6", "fe80::1"),
+            ] {
+                let response = client
+                    .get(format!("{UPDATE}&host=example.com&{parameter}={ip}"))
+                    .dispatch();
+                assert_eq!(response.status(), Status::BadRequest);
+                assert_eq!(
+                    response.into_string().unwrap(),
+                    format!("Address {ip} is not a global unicast address")
+                );
+            }
+
+            // No record is updated if one of the addresses is rejected
+            let response = client
+                .get(format!(
+                    "{UPDATE}&host=example.com&ip=10.0.0.1&ip6=2a01:4f8::1"
+                ))
+                .dispatch();
+            assert_eq!(response.status(), Status::BadRequest);
+
+            let response = client
+                .get(format!("{UPDATE}&host=example.com&ip6=2a01:4f8::1"))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+
+            // The policy of the host replaces the global one
+            let response = client
+                .get(format!("{UPDATE}&host=www.example.com&ip=10.1.2.3"))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+
+            let response = client
+                .get(format!("{UPDATE}&host=www.example.com&ip6=2a01:4f8::1"))
+                .dispatch();
+            assert_eq!(response.status(), Status::BadRequest);
+            assert_eq!(
+                response.into_string().unwrap(),
+                "Address 2a01:4f8::1 is not in an allowed network"
+            );
+        }
+    }
+
+    mod split_horizon {
+        use rocket::http::Header;
+
+        use super::*;
+
+        const UPDATE: &str = "/update?user=test&password=testpassword&host=example.com";
+
+        /// Test configuration where example.com also has a record in the internal zone home.arpa
+        fn lan_config() -> Config {
+            let mut config = test_config();
+            let public = config.users[0].domains[0].clone();
+            config.users[0].domains[0].lan = Some(Box::new(DomainConfig {
+                zone: crate::config::Zone {
+                    id: String::new(),
+                    name: "home.arpa".to_string(),
+                },
+                ..public
+            }));
+
+            config
+        }
+
+        #[test]
+        fn update_public_and_lan_address() {
+            let mut mock = MockProvider::default();
+            mock.expect_update_ip()
+                .withf(|d, ip, _| d.zone.name == "testzone" && ip.to_string() == "192.0.2.1")
+                .once()
+                .returning(|_, _, _| Ok(UpdateStatus::Unchanged));
+            mock.expect_update_ip()
+                .withf(|d, ip, _| d.zone.name == "home.arpa" && ip.to_string() == "192.168.1.10")
+                .once()
+                .returning(|_, _, _| Ok(UpdateStatus::Updated { previous: None }));
+            mock.expect_update_ip()
+                .withf(|d, ip, _| d.zone.name == "home.arpa" && ip.to_string() == "192.168.1.20")
+                .once()
+                .returning(|_, _, _| Ok(UpdateStatus::Unchanged));
+
+            let client = construct_with_config(lan_config(), Some(mock));
+
+            let response = client
+                .get(format!("{UPDATE}&ip=192.0.2.1&lanip=192.168.1.10"))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert_eq!(
+                response.into_string().unwrap(),
+                "IPv4 already set correctly\nUpdated IPv4 successfully (LAN)\n"
+            );
+
+            // The LAN address is taken from the request
+            let response = client
+                .get(format!("{UPDATE}&lanip=auto"))
+                .header(Header::new("X-Real-IP", "192.168.1.20"))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert_eq!(
+                response.into_string().unwrap(),
+                "IPv4 already set correctly (LAN)\n"
+            );
+
+            let response = client.get(format!("{UPDATE}&lanip=invalid")).dispatch();
+            assert_eq!(response.status(), Status::BadRequest);
+        }
+
+        #[test]
+        fn ignore_lan_address_without_internal_zone() {
+            let mut mock = MockProvider::default();
+            mock.expect_update_ip()
+                .withf(|d, _, _| d.host == "www.example.com")
+                .once()
+                .returning(|_, _, _| Ok(UpdateStatus::Unchanged));
+
+            let client = construct_with_config(lan_config(), Some(mock));
+
+            let response = client
+                .get("/update?user=test&password=testpassword&host=www.example.com&ip=192.0.2.1&lanip=192.168.1.10")
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        }
+    }
 }
@@ -0,0 +1,208 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+//! Serving HTTPS without a reverse proxy
+
+use std::{
+    fs,
+    net::IpAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use log::info;
+use rocket::{
+    figment::Figment,
+    http::{Method, Status},
+    response::Redirect,
+    route::{Handler, Outcome},
+    Build, Data, Request, Rocket, Route, Shutdown,
+};
+use serde::Deserialize;
+
+use crate::acme::AcmeConfig;
+
+/// Interval in which the certificate files are checked for changes
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Time to wait after a change, as certificate and key are usually replaced one after another
+const SETTLE_TIME: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize, Clone)]
+pub struct TlsConfig {
+    /// Certificate chain in PEM format, e.g. fullchain.pem of certbot
+    pub certs: PathBuf,
+    /// Private key in PEM format, e.g. privkey.pem of certbot
+    pub key: PathBuf,
+    /// Port of a plain HTTP listener that only redirects to HTTPS, HTTP is refused if not set
+    pub redirect_port: Option<u16>,
+    /// Obtain and renew the certificate automatically instead of reading the files of another client
+    pub acme: Option<AcmeConfig>,
+}
+
+/// Configuration of the main server with TLS enabled
+///
+/// Clients connect directly, so addresses in the X-Real-IP header are not trusted anymore.
+#[must_use]
+pub fn figment(config: &TlsConfig) -> Figment {
+    rocket::Config::figment()
+        .merge(("tls.certs", &config.certs))
+        .merge(("tls.key", &config.key))
+        .merge(("ip_header", false))
+}
+
+/// Modification times of the certificate and key, following the symlinks certbot uses
+fn modified(config: &TlsConfig) -> Option<(SystemTime, SystemTime)> {
+    let certs = fs::metadata(&config.certs)
+        .and_then(|m| m.modified())
+        .ok()?;
+    let key = fs::metadata(&config.key).and_then(|m| m.modified()).ok()?;
+
+    Some((certs, key))
+}
+
+/// Shut the server down once the certificate or key changed
+async fn watch(config: TlsConfig, shutdown: Shutdown, changed: Arc<AtomicBool>) {
+    let initial = modified(&config);
+
+    loop {
+        tokio::time::sleep(CHECK_INTERVAL).await;
+
+        let current = modified(&config);
+        if current.is_some() && current != initial {
+            tokio::time::sleep(SETTLE_TIME).await;
+
+            changed.store(true, Ordering::SeqCst);
+            shutdown.notify();
+            return;
+        }
+    }
+}
+
+/// Run a server built by `build`, which is started again whenever the certificate changes
+///
+/// Rocket reads the certificate only at launch, so the server is shut down gracefully and launched
+/// with the new certificate. State that has to survive this has to be shared between the instances.
+pub async fn serve(
+    config: &TlsConfig,
+    build: impl Fn() -> Rocket<Build>,
+) -> Result<(), rocket::Error> {
+    loop {
+        let rocket = build().ignite().await?;
+
+        let changed = Arc::new(AtomicBool::new(false));
+        let watcher = tokio::spawn(watch(config.clone(), rocket.shutdown(), changed.clone()));
+
+        let result = rocket.launch().await;
+        watcher.abort();
+        result?;
+
+        if !changed.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        info!("Certificate changed, restarting server");
+    }
+}
+
+/// Redirects every request to the same location on the HTTPS server
+#[derive(Clone)]
+struct RedirectHandler {
+    port: u16,
+}
+
+#[rocket::async_trait]
+impl Handler for RedirectHandler {
+    async fn handle<'r>(&self, request: &'r Request<'_>, _data: Data<'r>) -> Outcome<'r> {
+        let Some(host) = request.host() else {
+            return Outcome::Error(Status::BadRequest);
+        };
+
+        let port = if self.port == 443 {
+            String::new()
+        } else {
+            format!(":{}", self.port)
+        };
+        let location = format!("https://{}{port}{}", host.domain(), request.uri());
+
+        Outcome::from(request, Redirect::permanent(location))
+    }
+}
+
+fn redirect_routes(port: u16) -> Vec<Route> {
+    [
+        Method::Get,
+        Method::Head,
+        Method::Post,
+        Method::Put,
+        Method::Patch,
+        Method::Delete,
+        Method::Options,
+    ]
+    .into_iter()
+    .map(|method| Route::new(method, "/<path..>", RedirectHandler { port }))
+    .collect()
+}
+
+/// Plain HTTP server that redirects to the HTTPS server at `https_port`, if a port is configured for it
+#[must_use]
+pub fn redirect_listener(
+    config: &TlsConfig,
+    address: IpAddr,
+    https_port: u16,
+) -> Option<Rocket<Build>> {
+    let port = config.redirect_port?;
+
+    let figment = rocket::Config::figment()
+        .merge(("address", address))
+        .merge(("port", port));
+
+    Some(rocket::custom(figment).mount("/", redirect_routes(https_port)))
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{
+        http::{uri::Host, Status},
+        local::blocking::{Client, LocalRequest},
+    };
+
+    use super::*;
+
+    fn location(mut request: LocalRequest<'_>, host: &'static str) -> Option<String> {
+        request.inner_mut().set_host(Host::parse(host).unwrap());
+
+        let response = request.dispatch();
+        assert_eq!(response.status(), Status::PermanentRedirect);
+
+        response
+            .headers()
+            .get_one("Location")
+            .map(ToString::to_string)
+    }
+
+    #[test]
+    fn redirect_to_https() {
+        let client = Client::tracked(rocket::build().mount("/", redirect_routes(8443))).unwrap();
+
+        assert_eq!(
+            location(
+                client.get("/update?user=test&host=example.com"),
+                "dyndns.example.com"
+            ),
+            Some("https://dyndns.example.com:8443/update?user=test&host=example.com".to_string())
+        );
+        assert_eq!(
+            location(client.post("/api/v1/update"), "dyndns.example.com:80"),
+            Some("https://dyndns.example.com:8443/api/v1/update".to_string())
+        );
+
+        assert_eq!(client.get("/").dispatch().status(), Status::BadRequest);
+    }
+}
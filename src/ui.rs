@@ -0,0 +1,373 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+//! Read-only web interface showing the status of the hosts of a user
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+use rocket::{
+    form::{Form, FromForm},
+    get,
+    http::{Cookie, CookieJar, SameSite, Status},
+    post,
+    response::{content::RawHtml, Redirect},
+    Route, State,
+};
+use time::{format_description::well_known::Rfc3339, Duration, OffsetDateTime};
+
+use crate::{
+    api::{host_overview, HostResponse},
+    auth::{get_user, Authenticated},
+    config::{
+        store::{generate_token, ConfigStore},
+        Config, Providers,
+    },
+    history::History,
+    logging,
+};
+
+const COOKIE: &str = "dyndns_session";
+const SESSION_LIFETIME: Duration = Duration::hours(12);
+
+struct Session {
+    user: String,
+    /// Name of the token used for logging in, which restricts the hosts that are shown
+    token: Option<String>,
+    expires: OffsetDateTime,
+}
+
+/// Sessions of logged in users, which are lost on restart
+///
+/// Clones share their sessions, so they are kept when the server is built again for a new certificate.
+#[derive(Clone, Default)]
+pub struct Sessions {
+    sessions: Arc<Mutex<HashMap<String, Session>>>,
+}
+
+impl Sessions {
+    fn start(&self, authenticated: Authenticated<'_>) -> String {
+        let id = generate_token();
+        let now = OffsetDateTime::now_utc();
+
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, s| s.expires > now);
+        sessions.insert(
+            id.clone(),
+            Session {
+                user: authenticated.user.name.clone(),
+                token: authenticated.token.map(|t| t.name.clone()),
+                expires: now + SESSION_LIFETIME,
+            },
+        );
+
+        id
+    }
+
+    /// User of a session, as long as the user and its token still exist
+    fn get<'a>(&self, id: &str, config: &'a Config) -> Option<Authenticated<'a>> {
+        let now = OffsetDateTime::now_utc();
+        let sessions = self.sessions.lock().unwrap();
+        let session = sessions.get(id).filter(|s| s.expires > now)?;
+
+        let user = config.users.iter().find(|u| u.name == session.user)?;
+        let token = match &session.token {
+            Some(name) => Some(
+                user.tokens
+                    .iter()
+                    .find(|t| &t.name == name)
+                    .filter(|t| !t.is_expired(now))?,
+            ),
+            None => None,
+        };
+
+        Some(Authenticated { user, token })
+    }
+
+    fn end(&self, id: &str) {
+        self.sessions.lock().unwrap().remove(id);
+    }
+}
+
+/// Escape text for HTML
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn page(title: &str, body: &str) -> RawHtml<String> {
+    RawHtml(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+table {{ border-collapse: collapse; }}
+th, td {{ border: 1px solid #ccc; padding: 0.4em 0.8em; text-align: left; vertical-align: top; }}
+.error {{ color: #b00; }}
+form.inline {{ display: inline; }}
+</style>
+</head>
+<body>
+{body}
+</body>
+</html>
+"#,
+        title = escape(title)
+    ))
+}
+
+fn login_page(error: Option<&str>) -> RawHtml<String> {
+    let error = error.map_or_else(String::new, |e| {
+        format!(r#"<p class="error">{}</p>"#, escape(e))
+    });
+
+    page(
+        "dyndns",
+        &format!(
+            r#"<h1>dyndns</h1>
+{error}<form method="post" action="/ui/login">
+<p><label>User <input name="user" autocomplete="username" required></label></p>
+<p><label>Password or token <input name="password" type="password" autocomplete="current-password" required></label></p>
+<p><button type="submit">Log in</button></p>
+</form>"#
+        ),
+    )
+}
+
+fn format_time(time: Option<OffsetDateTime>) -> String {
+    time.and_then(|t| t.format(&Rfc3339).ok())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+fn addresses<T: ToString>(addresses: &[T]) -> String {
+    if addresses.is_empty() {
+        "-".to_string()
+    } else {
+        addresses
+            .iter()
+            .map(|a| escape(&a.to_string()))
+            .collect::<Vec<_>>()
+            .join("<br>")
+    }
+}
+
+fn dashboard(user: &str, hosts: &[HostResponse]) -> RawHtml<String> {
+    let mut rows = String::new();
+
+    for host in hosts {
+        let mut error = host.last_error.as_ref().map_or_else(
+            || "-".to_string(),
+            |e| format!("{}: {}", format_time(Some(e.time)), escape(&e.error)),
+        );
+        if let Some(e) = &host.error {
+            error = format!(r#"<span class="error">{}</span><br>{error}"#, escape(e));
+        }
+
+        let _ = writeln!(
+            rows,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{error}</td></tr>",
+            escape(&host.host),
+            addresses(&host.ipv4),
+            addresses(&host.ipv6),
+            format_time(host.last_update),
+            host.last_client
+                .as_ref()
+                .map_or_else(|| "-".to_string(), IpAddr::to_string),
+        );
+    }
+
+    page(
+        "dyndns",
+        &format!(
+            r#"<h1>dyndns</h1>
+<p>Logged in as {}. <form class="inline" method="post" action="/ui/logout"><button type="submit">Log out</button></form></p>
+<table>
+<tr><th>Host</th><th>IPv4</th><th>IPv6</th><th>Last update</th><th>Last client</th><th>Last error</th></tr>
+{rows}</table>"#,
+            escape(user)
+        ),
+    )
+}
+
+#[get("/")]
+fn index(
+    cookies: &CookieJar<'_>,
+    store: &State<ConfigStore>,
+    providers: &State<Providers>,
+    history: &State<History>,
+    sessions: &State<Sessions>,
+) -> RawHtml<String> {
+    let config = store.get();
+
+    let authenticated = cookies
+        .get(COOKIE)
+        .and_then(|cookie| sessions.get(cookie.value(), &config));
+
+    match authenticated {
+        Some(authenticated) => {
+            let _context = logging::enter_request();
+            logging::set_field("user", &authenticated.user.name);
+
+            dashboard(
+                &authenticated.user.name,
+                &host_overview(authenticated, providers, history),
+            )
+        }
+        None => login_page(None),
+    }
+}
+
+#[derive(FromForm)]
+struct Login<'r> {
+    user: &'r str,
+    password: &'r str,
+}
+
+#[post("/login", data = "<login>")]
+fn login(
+    login: Form<Login<'_>>,
+    client_ip: Option<IpAddr>,
+    cookies: &CookieJar<'_>,
+    store: &State<ConfigStore>,
+    sessions: &State<Sessions>,
+) -> Result<Redirect, (Status, RawHtml<String>)> {
+    let _context = logging::enter_request();
+    logging::set_field("user", login.user);
+    let config = store.get();
+
+    let Ok(authenticated) = get_user(&config, login.user, login.password, client_ip, "-") else {
+        return Err((
+            Status::Unauthorized,
+            login_page(Some("Invalid user or password")),
+        ));
+    };
+
+    let id = sessions.start(authenticated);
+    cookies.add(
+        Cookie::build((COOKIE, id))
+            .path("/ui")
+            .http_only(true)
+            .same_site(SameSite::Strict),
+    );
+
+    Ok(Redirect::to("/ui"))
+}
+
+#[post("/logout")]
+fn logout(cookies: &CookieJar<'_>, sessions: &State<Sessions>) -> Redirect {
+    if let Some(cookie) = cookies.get(COOKIE) {
+        sessions.end(cookie.value());
+    }
+    cookies.remove(Cookie::build(COOKIE).path("/ui"));
+
+    Redirect::to("/ui")
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![index, login, logout]
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::ContentType;
+
+    use crate::{
+        providers::{MockProvider, RecordNotFoundError, UpdateStatus},
+        server::tests::construct,
+    };
+
+    use super::*;
+
+    #[test]
+    fn show_login_without_session() {
+        let client = construct(Some(MockProvider::default()));
+
+        let response = client.get("/ui").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.into_string().unwrap().contains("Log in"));
+
+        let response = client
+            .post("/ui/login")
+            .header(ContentType::Form)
+            .body("user=test&password=wrongpassword")
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert!(response
+            .into_string()
+            .unwrap()
+            .contains("Invalid user or password"));
+    }
+
+    #[test]
+    fn show_hosts_after_login() {
+        let mut mock = MockProvider::default();
+        mock.expect_update_ip()
+            .once()
+            .returning(|_, _, _| Err(Box::new(RecordNotFoundError)));
+        mock.expect_update_ip()
+            .returning(|_, _, _| Ok(UpdateStatus::Unchanged));
+        mock.expect_get_ips()
+            .withf(|d| d.host == "example.com")
+            .returning(|_| Ok(vec!["192.0.2.1".parse().unwrap()]));
+        mock.expect_get_ips()
+            .withf(|d| d.host == "www.example.com")
+            .returning(|_| Ok(Vec::new()));
+
+        let client = construct(Some(mock));
+        client
+            .get("/update?user=test&password=testpassword&host=example.com&ip=192.0.2.1")
+            .dispatch();
+
+        let response = client
+            .post("/ui/login")
+            .header(ContentType::Form)
+            .body("user=test&password=testwwwtoken")
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+
+        // Logged in with a token, only the hosts of the token are shown
+        let page = client.get("/ui").dispatch().into_string().unwrap();
+        assert!(page.contains("<td>www.example.com</td>"));
+        assert!(!page.contains("<td>example.com</td>"));
+
+        client.post("/ui/logout").dispatch();
+        assert!(client
+            .get("/ui")
+            .dispatch()
+            .into_string()
+            .unwrap()
+            .contains("Log in"));
+
+        client
+            .post("/ui/login")
+            .header(ContentType::Form)
+            .body("user=test&password=testpassword")
+            .dispatch();
+
+        let page = client.get("/ui").dispatch().into_string().unwrap();
+        assert!(page.contains("<td>example.com</td><td>192.0.2.1</td><td>-</td>"));
+        assert!(page.contains("Error: Record not found"));
+    }
+
+    #[test]
+    fn escape_html() {
+        assert_eq!(
+            escape(r#"<script>alert("&")</script>"#),
+            "&lt;script&gt;alert(&quot;&amp;&quot;)&lt;/script&gt;"
+        );
+    }
+}
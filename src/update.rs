@@ -0,0 +1,479 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+use std::{fmt::Write, net::IpAddr};
+
+use log::{error, info, warn};
+use rocket::http::Status;
+use time::OffsetDateTime;
+
+use crate::{
+    admin::edit_error,
+    config::{
+        store::{set_offline, ConfigStore, EditError},
+        AddressFamily, ApiToken, Config, DomainConfig, Providers, User,
+    },
+    history::{Entry, History},
+    keyed::{KeyedConfig, KeyedEntries},
+    logging,
+    metrics::Metrics,
+    notifications::{Notification, Notifier},
+    providers::{update_ipv4, update_ipv6, Provider, RecordSet, RecordType, UpdateStatus},
+    security::{self, SecurityEvent},
+    verification::{self, VerificationResult},
+};
+
+const UPDATES_METRIC: &str = "dyndns_updates_total";
+const UPDATES_HELP: &str = "Number of record updates by record type and result";
+
+/// Result of updating a single record of a host
+pub struct RecordResult {
+    pub new_ip: IpAddr,
+    /// Whether the record is in the internal zone of the host
+    pub lan: bool,
+    pub outcome: Result<UpdateStatus, String>,
+    /// Result of the verification, if the client waited for it
+    pub verification: Option<VerificationResult>,
+}
+
+impl RecordResult {
+    #[must_use]
+    pub fn family(&self) -> &'static str {
+        match (self.lan, self.new_ip.is_ipv4()) {
+            (false, true) => "IPv4",
+            (false, false) => "IPv6",
+            (true, true) => "LAN IPv4",
+            (true, false) => "LAN IPv6",
+        }
+    }
+
+    #[must_use]
+    pub fn record_type(&self) -> &'static str {
+        if self.new_ip.is_ipv4() {
+            "A"
+        } else {
+            "AAAA"
+        }
+    }
+}
+
+/// Result of updating all records of a host
+pub enum HostResult {
+    /// The host does not belong to the user
+    InvalidDomain,
+    /// The token may not update addresses of this family
+    Forbidden(AddressFamily),
+    /// The host is offline, so the update has been ignored
+    Offline,
+    /// The host could not be taken offline or brought back online
+    OfflineFailed(EditError),
+    /// An address is not accepted by the address policy, with the reason
+    RejectedAddress(String),
+    Updated(Vec<RecordResult>),
+}
+
+impl HostResult {
+    /// Status code and human-readable lines describing the result
+    #[must_use]
+    pub fn describe(&self) -> (Status, Vec<String>) {
+        let records = match self {
+            HostResult::InvalidDomain => {
+                return (Status::BadRequest, vec!["Invalid domain".to_string()])
+            }
+            HostResult::Forbidden(family) => {
+                return (
+                    Status::Forbidden,
+                    vec![format!("Token may not update {family} addresses")],
+                )
+            }
+            HostResult::Offline => {
+                return (
+                    Status::Conflict,
+                    vec!["Host is offline, update ignored".to_string()],
+                )
+            }
+            HostResult::OfflineFailed(e) => return (edit_error(e.clone()).0, vec![e.to_string()]),
+            HostResult::RejectedAddress(reason) => {
+                return (Status::BadRequest, vec![reason.clone()])
+            }
+            HostResult::Updated(records) => records,
+        };
+
+        if records.is_empty() {
+            return (Status::Ok, vec!["No IP address specified".to_string()]);
+        }
+
+        let mut status = Status::Ok;
+        let mut lines = Vec::new();
+
+        for record in records {
+            match &record.outcome {
+                Ok(update_status) if record.lan => {
+                    lines.push(format!("{} (LAN)", update_status.describe(&record.new_ip)));
+                }
+                Ok(update_status) => lines.push(update_status.describe(&record.new_ip)),
+                Err(e) => {
+                    lines.push(format!("Error updating {} address: {e}", record.family()));
+                    status = Status::InternalServerError;
+                }
+            }
+
+            if let Some(verification) = &record.verification {
+                lines.push(format!("{} {verification}", record.family()));
+            }
+        }
+
+        (status, lines)
+    }
+}
+
+/// Pick the more severe of two status codes
+#[must_use]
+pub fn worse(a: Status, b: Status) -> Status {
+    if b.code > a.code {
+        b
+    } else {
+        a
+    }
+}
+
+/// Updates the records of the hosts of an authenticated user
+pub struct Updater<'a> {
+    pub user: &'a User,
+    /// Token the user authenticated with, which restricts the hosts and families that are updated
+    pub token: Option<&'a ApiToken>,
+    pub client_ip: Option<IpAddr>,
+    pub config: &'a Config,
+    /// Store the offline state of hosts is written to
+    pub store: &'a ConfigStore,
+    pub providers: &'a Providers,
+    pub notifier: &'a Notifier,
+    pub metrics: &'a Metrics,
+    pub history: &'a History,
+    pub keyed_entries: &'a KeyedEntries,
+    /// TTL that is set on updated records, if given and the host has no TTL of its own
+    pub ttl: Option<u64>,
+    /// Client that owns the addresses in keyed records, the name of the token if not given
+    pub key: Option<&'a str>,
+    /// Take the hosts offline, setting their maintenance addresses, or bring them back online
+    pub offline: Option<bool>,
+    /// Address of the hosts in the local network, which is set in the internal zone of hosts that have one
+    pub lan_ip: Option<IpAddr>,
+    /// Wait for the verification of updated records and report its result, instead of verifying them in the background
+    pub verify: bool,
+}
+
+impl Updater<'_> {
+    /// Configuration of `host`, if it belongs to the user and may be updated with the token
+    fn domain_config(&self, host: &str) -> Option<&DomainConfig> {
+        let domain_config = self
+            .user
+            .domains
+            .iter()
+            .find(|d| d.host == host)
+            .filter(|_| self.token.is_none_or(|t| t.allows_host(host)));
+
+        if domain_config.is_none() {
+            warn!("Invalid domain {host} for user {}", self.user.name);
+            security::log_event(
+                SecurityEvent::InvalidHost,
+                self.client_ip,
+                &self.user.name,
+                host,
+            );
+        }
+
+        domain_config
+    }
+
+    /// Update the records of `host` to the given addresses
+    pub fn update_host(&self, host: &str, addresses: &[IpAddr]) -> HostResult {
+        logging::set_field("host", host);
+
+        let Some(domain_config) = self.domain_config(host) else {
+            return HostResult::InvalidDomain;
+        };
+
+        let addresses = match (self.offline, &domain_config.maintenance) {
+            (Some(true), Some(maintenance)) => maintenance.addresses(),
+            (None, _) if domain_config.offline => {
+                info!("{host} is offline, ignoring update");
+                return HostResult::Offline;
+            }
+            _ => {
+                let policy = domain_config
+                    .address_policy
+                    .as_ref()
+                    .unwrap_or(&self.config.address_policy);
+                if let Some(reason) = addresses.iter().find_map(|ip| policy.check(*ip).err()) {
+                    warn!("Rejected update of {host}: {reason}");
+                    return HostResult::RejectedAddress(reason);
+                }
+
+                addresses.to_vec()
+            }
+        };
+
+        // LAN addresses are usually private, so they are only checked if the internal record has a policy
+        let lan = domain_config.lan.as_deref().zip(self.lan_ip);
+        if let Some(reason) = lan.and_then(|(lan, ip)| lan.address_policy.as_ref()?.check(ip).err())
+        {
+            warn!("Rejected LAN update of {host}: {reason}");
+            return HostResult::RejectedAddress(reason);
+        }
+
+        if let Some(family) = addresses
+            .iter()
+            .chain(lan.map(|(_, ip)| ip).as_ref())
+            .map(AddressFamily::of)
+            .find(|f| self.token.is_some_and(|t| !t.allows_family(*f)))
+        {
+            warn!("Token may not update {family} addresses of {host}");
+            return HostResult::Forbidden(family);
+        }
+
+        logging::set_field("provider", domain_config.provider);
+
+        let mut records: Vec<RecordResult> = addresses
+            .iter()
+            .map(|new_ip| self.update_record(domain_config, *new_ip, false))
+            .collect();
+
+        if let Some((lan, lan_ip)) = lan {
+            records.push(self.update_record(lan, lan_ip, true));
+        }
+
+        // The state only changes once the records point to the right addresses
+        if let Some(offline) = self.offline.filter(|o| *o != domain_config.offline) {
+            if records.iter().any(|r| r.outcome.is_err()) {
+                return HostResult::Updated(records);
+            }
+
+            let result = self
+                .store
+                .edit(|value| set_offline(value, &self.user.name, host, offline));
+            if let Err(e) = result {
+                warn!("Error changing offline state of {host}: {e}");
+                return HostResult::OfflineFailed(e);
+            }
+
+            if offline {
+                info!("Took {host} offline");
+            } else {
+                info!("Brought {host} back online");
+            }
+        }
+
+        HostResult::Updated(records)
+    }
+
+    fn update_record(
+        &self,
+        domain_config: &DomainConfig,
+        new_ip: IpAddr,
+        lan: bool,
+    ) -> RecordResult {
+        let ttl = domain_config.ttl.or(self.ttl);
+        let provider = self
+            .providers
+            .get(domain_config)
+            .map_err(|error| format!("Error: {error}"));
+        let outcome = provider
+            .clone()
+            .and_then(|p| match (&domain_config.keyed, new_ip) {
+                (Some(keyed), _) => self.update_keyed(p, domain_config, keyed, new_ip, ttl),
+                (None, IpAddr::V4(ipv4)) => update_ipv4(p, &ipv4, domain_config, ttl),
+                (None, IpAddr::V6(ipv6)) => update_ipv6(p, &ipv6, domain_config, ttl),
+            });
+
+        // Records that were only changed for their TTL still have the same address
+        let outcome = outcome.map(|status| match status {
+            UpdateStatus::Updated { previous } if previous == Some(new_ip) => {
+                UpdateStatus::TtlUpdated
+            }
+            status => status,
+        });
+
+        let mut result = RecordResult {
+            new_ip,
+            lan,
+            outcome,
+            verification: None,
+        };
+
+        match &result.outcome {
+            Ok(update_status) => {
+                info!("{}", update_status.describe(&new_ip));
+
+                let label = match update_status {
+                    UpdateStatus::Updated { .. } => "updated",
+                    UpdateStatus::Unchanged => "unchanged",
+                    UpdateStatus::TtlUpdated => "ttl_updated",
+                };
+                self.metrics.increment(
+                    UPDATES_METRIC,
+                    UPDATES_HELP,
+                    &[("record_type", result.record_type()), ("result", label)],
+                );
+
+                self.notifier.notify(&match update_status {
+                    UpdateStatus::Updated { previous } => Notification::ip_changed(
+                        &self.user.name,
+                        &domain_config.host,
+                        *previous,
+                        new_ip,
+                    ),
+                    UpdateStatus::Unchanged | UpdateStatus::TtlUpdated => {
+                        Notification::ip_unchanged(&self.user.name, &domain_config.host, new_ip)
+                    }
+                });
+
+                // The nameservers of the internal zone are usually not reachable from public resolvers
+                if let (UpdateStatus::Updated { .. }, Some(verification), false, Ok(p)) =
+                    (update_status, &self.config.verification, lan, &provider)
+                {
+                    if self.verify {
+                        result.verification = Some(verification::wait(
+                            verification,
+                            *p,
+                            domain_config,
+                            new_ip,
+                            self.metrics,
+                        ));
+                    } else {
+                        verification::spawn(verification, *p, domain_config, new_ip, self.metrics);
+                    }
+                }
+            }
+            Err(e) => {
+                error!("{e}");
+
+                self.metrics.increment(
+                    UPDATES_METRIC,
+                    UPDATES_HELP,
+                    &[("record_type", result.record_type()), ("result", "failed")],
+                );
+
+                self.notifier.notify(&Notification::update_failed(
+                    &self.user.name,
+                    &domain_config.host,
+                    new_ip,
+                    e,
+                ));
+            }
+        }
+
+        self.history.record(Entry {
+            time: OffsetDateTime::now_utc(),
+            user: self.user.name.clone(),
+            host: domain_config.host.clone(),
+            record_type: result.record_type().to_string(),
+            ip: new_ip,
+            client: self.client_ip,
+            result: match &result.outcome {
+                Ok(UpdateStatus::Updated { .. }) => "updated",
+                Ok(UpdateStatus::Unchanged) => "unchanged",
+                Ok(UpdateStatus::TtlUpdated) => "ttl_updated",
+                Err(_) => "failed",
+            }
+            .to_string(),
+            error: result.outcome.as_ref().err().cloned(),
+            lan,
+        });
+
+        result
+    }
+
+    /// Set the address of the client in a keyed record, keeping the addresses of the other clients
+    fn update_keyed(
+        &self,
+        p: &dyn Provider,
+        domain_config: &DomainConfig,
+        keyed: &KeyedConfig,
+        new_ip: IpAddr,
+        ttl: Option<u64>,
+    ) -> Result<UpdateStatus, String> {
+        let record_type = if new_ip.is_ipv4() {
+            RecordType::A
+        } else {
+            RecordType::AAAA
+        };
+        logging::set_field("record_type", record_type);
+
+        let key = self
+            .key
+            .or(self.token.map(|t| t.name.as_str()))
+            .unwrap_or("default");
+        let now = OffsetDateTime::now_utc();
+        let host = &domain_config.host;
+
+        let user = &self.user.name;
+
+        if !self.keyed_entries.contains(user, host, record_type) {
+            let current = p
+                .get_record_set(domain_config, record_type)
+                .map_err(|error| format!("Error: {error}"))?;
+            let ips: Vec<IpAddr> = current
+                .iter()
+                .flat_map(|r| &r.values)
+                .filter_map(|v| v.parse().ok())
+                .collect();
+            self.keyed_entries
+                .load(user, host, record_type, &ips, keyed.expires(now));
+        }
+
+        let (previous, ips) = self.keyed_entries.set(
+            user,
+            host,
+            record_type,
+            key,
+            new_ip,
+            keyed.expires(now),
+            now,
+        );
+        info!("Setting address of {key} in {record_type} records of {host}");
+
+        let record_set = RecordSet {
+            record_type,
+            values: ips.iter().map(ToString::to_string).collect(),
+            ttl,
+        };
+
+        match p.upsert_record_set(domain_config, &record_set) {
+            Ok(UpdateStatus::Unchanged) => Ok(UpdateStatus::Unchanged),
+            Ok(_) => Ok(UpdateStatus::Updated { previous }),
+            Err(error) => Err(format!("Error: {error}")),
+        }
+    }
+
+    /// Update several hosts and describe the results in plain text
+    ///
+    /// For a single host, the lines describing its records are returned as they are.
+    /// For several hosts, there is one line per host in the given order, prefixed with the host.
+    pub fn update_hosts_text(&self, hosts: &[&str], addresses: &[IpAddr]) -> (Status, String) {
+        let mut status = Status::Ok;
+        let mut response = String::new();
+
+        for host in hosts {
+            let result = self.update_host(host, addresses);
+            let (host_status, lines) = result.describe();
+            status = worse(status, host_status);
+
+            if hosts.len() > 1 {
+                let _ = writeln!(response, "{host}: {}", lines.join("; "));
+            } else if matches!(&result, HostResult::Updated(records) if !records.is_empty()) {
+                for line in lines {
+                    let _ = writeln!(response, "{line}");
+                }
+            } else {
+                // Errors and hints are returned without a line break, as they always have been
+                response = lines.join("");
+            }
+        }
+
+        (status, response)
+    }
+}
@@ -0,0 +1,349 @@
+// SPDX-FileCopyrightText: 2026 Benedikt Bastin
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+#![deny(clippy::all)]
+
+use std::{
+    fmt::Display,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use serde::Deserialize;
+
+use crate::{
+    config::DomainConfig,
+    dns::{self, TYPE_A, TYPE_AAAA},
+    metrics::Metrics,
+    providers::Provider,
+};
+
+const VERIFICATIONS_METRIC: &str = "dyndns_verifications_total";
+const VERIFICATIONS_HELP: &str = "Number of verifications of updated records by result";
+const VERIFICATION_SECONDS_METRIC: &str = "dyndns_verification_seconds_total";
+const VERIFICATION_SECONDS_HELP: &str =
+    "Time spent until updated records were served by all nameservers";
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+fn default_interval_secs() -> u64 {
+    5
+}
+
+#[derive(Deserialize, Clone)]
+pub struct VerificationConfig {
+    /// Nameservers to query instead of the authoritative nameservers of the zone,
+    /// given as an address or hostname with an optional port
+    #[serde(default)]
+    pub nameservers: Vec<String>,
+    /// Time after which verification is given up
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Time between two rounds of queries
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum VerificationResult {
+    /// All nameservers serve the new address
+    Verified {
+        nameservers: usize,
+        elapsed: Duration,
+    },
+    /// Some nameservers did not serve the new address before the timeout
+    Timeout { pending: Vec<SocketAddr> },
+    /// Verification could not be started
+    Error(String),
+}
+
+impl VerificationResult {
+    /// Short name of the result for metrics
+    #[must_use]
+    pub fn label(&self) -> &'static str {
+        match self {
+            VerificationResult::Verified { .. } => "verified",
+            VerificationResult::Timeout { .. } => "timeout",
+            VerificationResult::Error(_) => "error",
+        }
+    }
+}
+
+impl Display for VerificationResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerificationResult::Verified {
+                nameservers,
+                elapsed,
+            } => write!(
+                f,
+                "verified on {nameservers} nameserver(s) after {}s",
+                elapsed.as_secs()
+            ),
+            VerificationResult::Timeout { pending } => write!(
+                f,
+                "not served by {} before timeout",
+                pending
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            VerificationResult::Error(error) => write!(f, "could not be verified: {error}"),
+        }
+    }
+}
+
+/// Resolve a nameserver given as address or hostname with an optional port
+fn resolve_nameserver(nameserver: &str) -> Result<SocketAddr, String> {
+    if let Ok(address) = nameserver.parse::<SocketAddr>() {
+        return Ok(address);
+    }
+    if let Ok(ip) = nameserver.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, 53));
+    }
+
+    let nameserver = nameserver.trim_end_matches('.');
+    let addresses = if nameserver.contains(':') {
+        nameserver.to_socket_addrs()
+    } else {
+        (nameserver, 53).to_socket_addrs()
+    }
+    .map_err(|error| format!("Error resolving nameserver {nameserver}: {error}"))?
+    .collect::<Vec<_>>();
+
+    // Prefer IPv4, as not every server has IPv6 connectivity
+    addresses
+        .iter()
+        .find(|a| a.is_ipv4())
+        .or(addresses.first())
+        .copied()
+        .ok_or_else(|| format!("Nameserver {nameserver} has no addresses"))
+}
+
+/// Determine the addresses of the nameservers that are queried for a domain
+pub fn nameservers(
+    config: &VerificationConfig,
+    provider: &dyn Provider,
+    domain_config: &DomainConfig,
+) -> Result<Vec<SocketAddr>, String> {
+    let names = if config.nameservers.is_empty() {
+        provider
+            .nameservers(domain_config)
+            .map_err(|error| format!("Error getting nameservers: {error}"))?
+    } else {
+        config.nameservers.clone()
+    };
+
+    if names.is_empty() {
+        return Err(format!(
+            "No nameservers for zone {}",
+            domain_config.zone.name
+        ));
+    }
+
+    names.iter().map(|n| resolve_nameserver(n)).collect()
+}
+
+/// Query the nameservers until all of them serve `expected` for `host` or the timeout is reached
+pub fn verify(
+    config: &VerificationConfig,
+    nameservers: &[SocketAddr],
+    host: &str,
+    expected: IpAddr,
+) -> VerificationResult {
+    let record_type = if expected.is_ipv4() {
+        TYPE_A
+    } else {
+        TYPE_AAAA
+    };
+    let start = Instant::now();
+    let timeout = Duration::from_secs(config.timeout_secs);
+    let query_timeout = Duration::from_secs(config.interval_secs.clamp(1, 5));
+
+    let mut pending = nameservers.to_vec();
+
+    loop {
+        pending.retain(|nameserver| {
+            match dns::lookup(*nameserver, host, record_type, query_timeout) {
+                Ok(addresses) => !addresses.contains(&expected),
+                Err(error) => {
+                    warn!("Error querying {nameserver} for {host}: {error}");
+                    true
+                }
+            }
+        });
+
+        if pending.is_empty() {
+            let result = VerificationResult::Verified {
+                nameservers: nameservers.len(),
+                elapsed: start.elapsed(),
+            };
+            info!("{host} {result}");
+            return result;
+        }
+
+        if start.elapsed() + Duration::from_secs(config.interval_secs) > timeout {
+            let result = VerificationResult::Timeout { pending };
+            warn!("{host} {expected} {result}");
+            return result;
+        }
+
+        thread::sleep(Duration::from_secs(config.interval_secs));
+    }
+}
+
+/// Count the result of a verification in the metrics
+fn record_metrics(metrics: &Metrics, result: &VerificationResult) {
+    metrics.increment(
+        VERIFICATIONS_METRIC,
+        VERIFICATIONS_HELP,
+        &[("result", result.label())],
+    );
+    if let VerificationResult::Verified { elapsed, .. } = result {
+        metrics.increase(
+            VERIFICATION_SECONDS_METRIC,
+            VERIFICATION_SECONDS_HELP,
+            &[],
+            elapsed.as_secs_f64(),
+        );
+    }
+}
+
+/// Verify an updated record and wait for the result, which takes up to `timeout_secs`
+pub fn wait(
+    config: &VerificationConfig,
+    provider: &dyn Provider,
+    domain_config: &DomainConfig,
+    expected: IpAddr,
+    metrics: &Metrics,
+) -> VerificationResult {
+    let result = match nameservers(config, provider, domain_config) {
+        Ok(nameservers) => tokio::task::block_in_place(|| {
+            verify(config, &nameservers, &domain_config.host, expected)
+        }),
+        Err(error) => {
+            warn!(
+                "{} {expected} could not be verified: {error}",
+                domain_config.host
+            );
+            VerificationResult::Error(error)
+        }
+    };
+    record_metrics(metrics, &result);
+
+    result
+}
+
+/// Verify an updated record in the background, reporting the result in the log and the metrics
+///
+/// The nameservers are determined right away, only querying them until they serve the new address
+/// happens in the background, so that responses to updates are not delayed.
+/// This has to be called from within the Tokio runtime, which is the case for request handlers.
+pub fn spawn(
+    config: &VerificationConfig,
+    provider: &dyn Provider,
+    domain_config: &DomainConfig,
+    expected: IpAddr,
+    metrics: &Metrics,
+) {
+    let nameservers = match nameservers(config, provider, domain_config) {
+        Ok(nameservers) => nameservers,
+        Err(error) => {
+            warn!(
+                "{} {expected} could not be verified: {error}",
+                domain_config.host
+            );
+            record_metrics(metrics, &VerificationResult::Error(error));
+            return;
+        }
+    };
+
+    let config = config.clone();
+    let host = domain_config.host.clone();
+    let metrics = metrics.clone();
+
+    tokio::task::spawn_blocking(move || {
+        let result = verify(&config, &nameservers, &host, expected);
+        record_metrics(&metrics, &result);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use crate::dns::tests::serve;
+
+    use super::*;
+
+    fn config(timeout_secs: u64) -> VerificationConfig {
+        VerificationConfig {
+            nameservers: Vec::new(),
+            timeout_secs,
+            interval_secs: 1,
+        }
+    }
+
+    const OLD: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    const NEW: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+
+    #[test]
+    fn verified_immediately() {
+        let nameservers = vec![serve(|_| vec![NEW]), serve(|_| vec![OLD, NEW])];
+
+        let result = verify(&config(5), &nameservers, "test.example.com", NEW);
+
+        assert!(matches!(
+            result,
+            VerificationResult::Verified { nameservers: 2, .. }
+        ));
+    }
+
+    #[test]
+    fn verified_after_propagation() {
+        let nameservers = vec![serve(
+            |query| if query == 0 { vec![OLD] } else { vec![NEW] },
+        )];
+
+        let result = verify(&config(5), &nameservers, "test.example.com", NEW);
+
+        assert!(matches!(
+            result,
+            VerificationResult::Verified { nameservers: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn timeout() {
+        let stale = serve(|_| vec![OLD]);
+        let nameservers = vec![serve(|_| vec![NEW]), stale];
+
+        let result = verify(&config(1), &nameservers, "test.example.com", NEW);
+
+        assert_eq!(
+            result,
+            VerificationResult::Timeout {
+                pending: vec![stale]
+            }
+        );
+    }
+
+    #[test]
+    fn resolve_configured_nameservers() {
+        assert_eq!(
+            resolve_nameserver("192.0.2.53").unwrap(),
+            "192.0.2.53:53".parse().unwrap()
+        );
+        assert_eq!(
+            resolve_nameserver("[2001:db8::53]:5353").unwrap(),
+            "[2001:db8::53]:5353".parse().unwrap()
+        );
+        assert_eq!(resolve_nameserver("localhost:5353").unwrap().port(), 5353);
+    }
+}